@@ -0,0 +1,2 @@
+// the engine lives in utils, main.rs is just the window and the event loop
+pub mod utils;
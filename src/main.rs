@@ -12,19 +12,100 @@ use winit::
     window::{ WindowBuilder },
 };
 
-// utils is my module
-use utils::state::State;
+// utils is my module (see 'lib.rs')
+use my_game::utils::
+{
+    state::{ self, State },
+    replay,
+    console,
+    timestep::{ self, FixedTimestep },
+    game_state::GameStateStack,
+    screens,
+    paths,
+};
 
-mod utils;
+// after a hitch, at most this many ticks run in one frame to catch up
+const MAX_CATCH_UP_TICKS: u32 = 5;
 
 
+// the value after a command line flag, like the file in '--record file.ron'
+fn flag_value(flag: &str) -> Option<String>
+{
+    let mut args = std::env::args().skip_while(|arg| arg != flag);
+    args.next()?;
+    args.next()
+}
+
 fn main() {
     env_logger::init();
+
+    // --replay <file>: play a recording back without a window and check it goes the same way (see 'replay.rs')
+    if let Some(path) = flag_value("--replay")
+    {
+        match replay::replay_file(std::path::Path::new(&path), &paths::asset_root())
+        {
+            Ok(report) =>
+            {
+                println!("{}", report);
+                std::process::exit(if report.divergence.is_some() { 1 } else { 0 });
+            }
+            Err(e) =>
+            {
+                eprintln!("{}", e);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    // --script <file>: run console commands without a window, for automated tests (see 'console.rs')
+    if let Some(path) = flag_value("--script")
+    {
+        match console::run_script_file(std::path::Path::new(&path), &paths::asset_root(), state::MAIN_SCENE)
+        {
+            Ok(report) =>
+            {
+                println!("{}", report);
+                std::process::exit(if report.failure.is_some() { 1 } else { 0 });
+            }
+            Err(e) =>
+            {
+                eprintln!("{}", e);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    // the window and graphics settings, the player's own or the defaults in assets/config (see 'settings.rs' and 'paths.rs')
+    let settings = State::load_settings();
     let event_loop = EventLoop::new();
-    let window = WindowBuilder::new().build(&event_loop).unwrap();
+    let window = WindowBuilder::new().with_inner_size(settings.window_size()).build(&event_loop).unwrap();
+    settings.apply_to_window(&window);
 
-    // initializing the State
-    let mut state = pollster::block_on(State::new(&window));
+    // initializing the State, without a gpu or the first level there's no game to run
+    let mut state = match pollster::block_on(State::new(&window, settings))
+    {
+        Ok(state) => state,
+        Err(e) =>
+        {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    // --record <file>: save the inputs of the last level played, for --replay
+    if let Some(path) = flag_value("--record")
+    {
+        // a checksum every second
+        state.start_recording(path, timestep::TICK_RATE_HZ as u64);
+    }
+    // --gpu-picking: find what was clicked on with the gpu instead of testing every mesh (see 'picking.rs')
+    if std::env::args().any(|arg| arg == "--gpu-picking")
+    {
+        state.set_gpu_picking(true);
+    }
+    // menus, gameplay, pause... (see 'game_state.rs' and 'screens.rs')
+    let mut game = GameStateStack::new(&mut state, Box::new(screens::MainMenu::new()));
+    let mut title = String::new();
+    let mut timestep = FixedTimestep::new(timestep::TICK_RATE_HZ, MAX_CATCH_UP_TICKS);
 
     event_loop.run(move |event, _, control_flow| { match event
         {
@@ -33,66 +114,125 @@ fn main() {
                 ref event,
                 window_id,
             } 
-            if window_id == window.id() => 
+            if window_id == window.id() =>
             {
-                if !state.input(event)
+                // the current game state decides what keys do (Escape pauses, quits from the menu, ...)
+                game.input(&mut state, event);
+                if game.is_empty()
                 {
-                    // match for different events that can be triggered
-                    // like close requested, resized, etc.
-                    match event
+                    *control_flow = ControlFlow::Exit;
+                }
+
+                // match for different events that can be triggered
+                // like close requested, resized, etc.
+                match event
+                {
+
+                    // if close window signal ---> exit
+                    WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+
+                    // if window resized --->
+                    WindowEvent::Resized(physical_size) =>
                     {
+                    // ---> resize
+                        state.resize(*physical_size);
+                    },
 
-                        // if close window signal --->
-                        WindowEvent::CloseRequested | WindowEvent::KeyboardInput
-                        {
-                            input:
-                                KeyboardInput
-                                {
-                                    state: ElementState::Pressed,
-                                    virtual_keycode: Some(VirtualKeyCode::Escape),
-                                    ..
-                                },
-                            ..
-                        // ---> exit
-                        } => *control_flow = ControlFlow::Exit,
-
-                        // if window resized --->
-                        WindowEvent::Resized(physical_size) =>
-                        {
-                        // ---> resize
-                            state.resize(*physical_size);
-                        },
-
-                        // if scale factor changed --->
-                        WindowEvent::ScaleFactorChanged { new_inner_size, .. } =>
-                        {
-                        // ---> resize
-                            // new_inner_size is &&mut so we have to dereference it twice
-                            state.resize(**new_inner_size);
-                        },
-                        _ => {}
-                    }
+                    // if scale factor changed --->
+                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } =>
+                    {
+                    // ---> resize
+                        // new_inner_size is &&mut so we have to dereference it twice
+                        state.resize(**new_inner_size);
+                    },
+                    _ => {}
                 }
             }
+            // the system took the app to the background (or brought it back), nothing is drawn in between
+            Event::Suspended => state.set_suspended(true),
+            Event::Resumed => state.set_suspended(false),
+
             Event::RedrawRequested(window_id) if window_id == window.id() =>
             {
-                state.update();
-                match state.render()
+                // nothing to draw to while minimized, the game picks up where it was when it comes back
+                // (without the time it was away counting as lag)
+                if !state.is_drawable()
+                {
+                    timestep.reset();
+                    return;
+                }
+                // everything on the gpu is made again, on whatever gpu is left (see 'gpu.rs')
+                if state.is_device_lost()
+                {
+                    if let Err(e) = pollster::block_on(state.recover_device(&window))
+                    {
+                        eprintln!("{}", e);
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+                    timestep.reset();
+                }
+
+                // run as many fixed ticks as fit in the time since the last frame (see 'timestep.rs')
+                for _ in 0..timestep.advance(std::time::Instant::now())
+                {
+                    game.update(&mut state, timestep.tick_duration());
+                }
+                if game.is_empty()
+                {
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
+
+                // the menus show what to do in the title (loading progress, how to unpause, ...)
+                let new_title = game.title(&state);
+                if new_title != title
+                {
+                    window.set_title(&new_title);
+                    title = new_title;
+                }
+
+                // draw in between the last two ticks so motion stays smooth
+                match game.render(&mut state, timestep.alpha())
                 {
                     Ok(_) => {}
-                    // Reconfigure the surface if lost
-                    Err(wgpu::SurfaceError::Lost) => state.resize(state.size),
-                    // The system is out of memory, we should probably quit
-                    Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
-                    // All other errors (Outdated, Timeout) should be resolved by the next frame
-                    Err(e) => eprintln!("{:?}", e),
+                    // Lost and Outdated surfaces are configured again, so are ones that keep timing out
+                    Err(e) => if let Err(e) = state.recover_surface(e)
+                    {
+                        // The system is out of memory, we should probably quit
+                        eprintln!("{:?}, quitting", e);
+                        *control_flow = ControlFlow::Exit;
+                    },
+                }
+            }
+
+            Event::LoopDestroyed =>
+            {
+                if let Err(e) = state.finish_recording()
+                {
+                    eprintln!("{}", e);
                 }
+                state.finish_captures();
             }
 
             Event::MainEventsCleared =>
             {
+                // the settings panel changed the resolution or went fullscreen, the Resized event follows
+                if let Some(settings) = state.take_window_changes()
+                {
+                    settings.apply_to_window(&window);
+                }
                 // RedrawRequested will only trigger once, unless we manually request it.
-                window.request_redraw();
+                // while there's nothing to draw to, wait for the window to come back instead of spinning
+                if state.is_drawable()
+                {
+                    *control_flow = ControlFlow::Poll;
+                    window.request_redraw();
+                }
+                else
+                {
+                    *control_flow = ControlFlow::Wait;
+                }
             }
             _ => {}
         }
@@ -0,0 +1,19 @@
+// copies a frame that was drawn offscreen to be captured onto the surface (see 'capture.rs')
+[[group(0), binding(0)]]
+var t_frame: texture_2d<f32>;
+
+// one triangle big enough to cover the screen, no vertex buffer needed
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32) -> [[builtin(position)]] vec4<f32>
+{
+    let x = f32(i32(index & 1u) * 4 - 1);
+    let y = f32(i32(index & 2u) * 2 - 1);
+    return vec4<f32>(x, y, 0.0, 1.0);
+}
+
+// the target is the same size as the surface, so each pixel is copied as it is
+[[stage(fragment)]]
+fn fs_main([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32>
+{
+    return textureLoad(t_frame, vec2<i32>(position.xy), 0);
+}
@@ -0,0 +1,37 @@
+// Lines drawn over the world for debugging (see 'debug_draw.rs')
+struct CameraUniform
+{
+    view_proj: mat4x4<f32>;
+};
+
+// the only bind group, so it's group 0 here
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+struct VertexInput
+{
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] color: vec3<f32>;
+};
+
+struct VertexOutput
+{
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] color: vec3<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(line: VertexInput) -> VertexOutput
+{
+    var out: VertexOutput;
+    out.color = line.color;
+    // already in world space
+    out.clip_position = camera.view_proj * vec4<f32>(line.position, 1.0);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32>
+{
+    return vec4<f32>(in.color, 1.0);
+}
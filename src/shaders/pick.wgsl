@@ -0,0 +1,52 @@
+// Draws every object's id instead of its color, for picking (see 'picking.rs')
+struct CameraUniform
+{
+    view_proj: mat4x4<f32>;
+};
+
+// the only bind group, so it's group 0 here
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+// the same vertex and instance buffers as 'shader.wgsl', only the positions and model matrices are used
+struct VertexInput
+{
+    [[location(0)]] position: vec3<f32>;
+};
+
+struct InstanceInput
+{
+    [[location(5)]] model_matrix_0: vec4<f32>;
+    [[location(6)]] model_matrix_1: vec4<f32>;
+    [[location(7)]] model_matrix_2: vec4<f32>;
+    [[location(8)]] model_matrix_3: vec4<f32>;
+    [[location(12)]] id: u32;
+};
+
+struct VertexOutput
+{
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0), interpolate(flat)]] id: u32;
+};
+
+[[stage(vertex)]]
+fn vs_main(model: VertexInput, instance: InstanceInput,) -> VertexOutput
+{
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+
+    var out: VertexOutput;
+    out.id = instance.id;
+    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] u32
+{
+    return in.id;
+}
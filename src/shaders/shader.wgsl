@@ -2,6 +2,8 @@
 struct CameraUniform
 {
     view_proj: mat4x4<f32>;
+    // x = znear, y = zfar
+    planes: vec4<f32>;
 };
 
 // Because we've created a new bind group, we need to specify which one we're using in the shader.
@@ -12,22 +14,52 @@ struct VertexInput
 {
     [[location(0)]] position: vec3<f32>;
     [[location(1)]] tex_coords: vec2<f32>;
+    [[location(2)]] normal: vec3<f32>;
+};
+
+// the model matrix comes in one column per location (see 'instance.rs')
+struct InstanceInput
+{
+    [[location(5)]] model_matrix_0: vec4<f32>;
+    [[location(6)]] model_matrix_1: vec4<f32>;
+    [[location(7)]] model_matrix_2: vec4<f32>;
+    [[location(8)]] model_matrix_3: vec4<f32>;
+    [[location(9)]] normal_matrix_0: vec3<f32>;
+    [[location(10)]] normal_matrix_1: vec3<f32>;
+    [[location(11)]] normal_matrix_2: vec3<f32>;
 };
 
 struct VertexOutput
 {
     [[builtin(position)]] clip_position: vec4<f32>;
     [[location(0)]] tex_coords: vec2<f32>;
+    [[location(1)]] world_normal: vec3<f32>;
+    [[location(2)]] world_position: vec3<f32>;
 };
 
 [[stage(vertex)]]
-fn vs_main(model: VertexInput,) -> VertexOutput
+fn vs_main(model: VertexInput, instance: InstanceInput,) -> VertexOutput
 {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    let normal_matrix = mat3x3<f32>(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2,
+    );
+
     var out: VertexOutput;
     out.tex_coords = model.tex_coords;
+    out.world_normal = normal_matrix * model.normal;
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+    out.world_position = world_position.xyz;
     // Multiplication order is important when it comes to matrices
     // The vector goes on the right, and the matrices go on the left in order of importance
-    out.clip_position = camera.view_proj * vec4<f32>(model.position, 1.0);
+    out.clip_position = camera.view_proj * world_position;
     return out;
 }
 
@@ -39,8 +71,132 @@ var t_diffuse: texture_2d<f32>;
 [[group(0), binding(1)]]
 var s_diffuse: sampler;
 
+// see LightUniform in 'light.rs'
+struct Light
+{
+    position: vec4<f32>;
+    color: vec4<f32>;
+    ambient: vec4<f32>;
+};
+[[group(2), binding(0)]]
+var<uniform> light: Light;
+
+fn lit_color(in: VertexOutput) -> vec4<f32>
+{
+    let object_color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+
+    // w = 1 -> point light at position.xyz, w = 0 -> directional light shining along position.xyz
+    let light_dir = select(
+        normalize(-light.position.xyz),
+        normalize(light.position.xyz - in.world_position),
+        light.position.w > 0.5,
+    );
+    let diffuse_strength = max(dot(normalize(in.world_normal), light_dir), 0.0);
+    let diffuse = light.color.rgb * light.color.a * diffuse_strength;
+
+    return vec4<f32>((light.ambient.rgb + diffuse) * object_color.rgb, object_color.a);
+}
+
+// opaque and blended meshes, blending is set on the pipeline (see 'render_mode.rs')
 [[stage(fragment)]]
 fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32>
 {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords);
-}
\ No newline at end of file
+    return lit_color(in);
+}
+
+// cutout meshes: see-through bits of the texture aren't drawn at all, so no sorting is needed
+[[stage(fragment)]]
+fn fs_cutout(in: VertexOutput) -> [[location(0)]] vec4<f32>
+{
+    let color = lit_color(in);
+    if (color.a < 0.5)
+    {
+        discard;
+    }
+    return vec4<f32>(color.rgb, 1.0);
+}
+
+
+// Debug render modes (see 'render_mode.rs'), each one is a different fragment entry point
+
+// the normal as a color, x -> red, y -> green, z -> blue
+[[stage(fragment)]]
+fn fs_normals(in: VertexOutput) -> [[location(0)]] vec4<f32>
+{
+    return vec4<f32>(normalize(in.world_normal) * 0.5 + 0.5, 1.0);
+}
+
+// an 8x8 checkerboard over the texture coordinates, tinted by them, shows stretching and seams
+[[stage(fragment)]]
+fn fs_uv_checker(in: VertexOutput) -> [[location(0)]] vec4<f32>
+{
+    let cell = floor(in.tex_coords * 8.0);
+    let checker = abs(cell.x + cell.y) % 2.0;
+    let tint = vec3<f32>(fract(in.tex_coords), 0.5);
+    return vec4<f32>(tint * (0.5 + 0.5 * checker), 1.0);
+}
+
+// white up close fading to black at the far plane, on a log scale so there's something to see at every distance
+[[stage(fragment)]]
+fn fs_depth(in: VertexOutput) -> [[location(0)]] vec4<f32>
+{
+    let near = camera.planes.x;
+    let far = camera.planes.y;
+    // the depth buffer's 0..1 back to a distance
+    let linear = near * far / (far - in.clip_position.z * (far - near));
+    let shade = 1.0 - clamp(log2(linear / near) / log2(far / near), 0.0, 1.0);
+    return vec4<f32>(vec3<f32>(shade), 1.0);
+}
+
+
+// Wireframe for when the gpu can't draw lines itself: every triangle is drawn on its own
+// (no index buffer, see WireframeMesh in 'mesh.rs') so each corner knows which one it is
+
+struct WireframeOutput
+{
+    [[builtin(position)]] clip_position: vec4<f32>;
+    // 1 at its own corner, 0 along the edge across from it
+    [[location(0)]] barycentric: vec3<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_wireframe(
+    [[location(0)]] position: vec3<f32>,
+    instance: InstanceInput,
+    [[builtin(vertex_index)]] vertex_index: u32,
+) -> WireframeOutput
+{
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+
+    var out: WireframeOutput;
+    let corner = vertex_index % 3u;
+    out.barycentric = vec3<f32>(f32(corner == 0u), f32(corner == 1u), f32(corner == 2u));
+    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(position, 1.0);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_wireframe(in: WireframeOutput) -> [[location(0)]] vec4<f32>
+{
+    // about a pixel and a half from any edge counts as on the line
+    let width = fwidth(in.barycentric) * 1.5;
+    let edge = smoothStep(vec3<f32>(0.0), width, in.barycentric);
+    let coverage = 1.0 - min(min(edge.x, edge.y), edge.z);
+    if (coverage < 0.1)
+    {
+        discard;
+    }
+    return vec4<f32>(vec3<f32>(coverage), 1.0);
+}
+
+// the lines themselves with POLYGON_MODE_LINE
+[[stage(fragment)]]
+fn fs_white(in: VertexOutput) -> [[location(0)]] vec4<f32>
+{
+    return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+}
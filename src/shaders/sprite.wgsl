@@ -0,0 +1,45 @@
+// 2D sprites for the HUD and 2D levels (see 'sprite.rs')
+struct CameraUniform
+{
+    view_proj: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+// the asset manager's textures, same bind group layout as the world uses
+[[group(1), binding(0)]]
+var t_sprite: texture_2d<f32>;
+[[group(1), binding(1)]]
+var s_sprite: sampler;
+
+struct VertexInput
+{
+    [[location(0)]] position: vec2<f32>;
+    [[location(1)]] tex_coords: vec2<f32>;
+    [[location(2)]] color: vec4<f32>;
+};
+
+struct VertexOutput
+{
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+    [[location(1)]] color: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(sprite: VertexInput) -> VertexOutput
+{
+    var out: VertexOutput;
+    out.tex_coords = sprite.tex_coords;
+    out.color = sprite.color;
+    // the corners are already rotated and moved into place
+    out.clip_position = camera.view_proj * vec4<f32>(sprite.position, 0.0, 1.0);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32>
+{
+    return textureSample(t_sprite, s_sprite, in.tex_coords) * in.color;
+}
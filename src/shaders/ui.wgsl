@@ -0,0 +1,59 @@
+// the immediate mode ui (see 'ui.rs'), positions come in points from the top left
+struct ScreenUniform
+{
+    // in points, the last two are padding
+    size: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> screen: ScreenUniform;
+
+[[group(1), binding(0)]]
+var t_ui: texture_2d<f32>;
+[[group(1), binding(1)]]
+var s_ui: sampler;
+
+struct VertexInput
+{
+    [[location(0)]] position: vec2<f32>;
+    [[location(1)]] tex_coords: vec2<f32>;
+    // srgb and premultiplied, as egui hands it over
+    [[location(2)]] color: vec4<f32>;
+};
+
+struct VertexOutput
+{
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+    [[location(1)]] color: vec4<f32>;
+};
+
+// the surface is srgb, so colors get blended in linear space
+fn linear_from_srgb(srgb: vec3<f32>) -> vec3<f32>
+{
+    let cutoff = srgb < vec3<f32>(0.04045);
+    let lower = srgb / vec3<f32>(12.92);
+    let higher = pow((srgb + vec3<f32>(0.055)) / vec3<f32>(1.055), vec3<f32>(2.4));
+    return select(higher, lower, cutoff);
+}
+
+[[stage(vertex)]]
+fn vs_main(ui: VertexInput) -> VertexOutput
+{
+    var out: VertexOutput;
+    out.tex_coords = ui.tex_coords;
+    out.color = vec4<f32>(linear_from_srgb(ui.color.rgb), ui.color.a);
+    out.clip_position = vec4<f32>(
+        2.0 * ui.position.x / screen.size.x - 1.0,
+        1.0 - 2.0 * ui.position.y / screen.size.y,
+        0.0,
+        1.0,
+    );
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32>
+{
+    return in.color * textureSample(t_ui, s_ui, in.tex_coords);
+}
@@ -1,5 +1,36 @@
 pub mod state;
 pub mod vertex;
 pub mod texture;
+pub mod mesh;
+pub mod bounds;
+pub mod assets;
+pub mod instance;
+pub mod scene;
+pub mod light;
+pub mod ecs;
+pub mod systems;
+pub mod timestep;
+pub mod game_state;
+pub mod screens;
+pub mod input;
+pub mod gamepad;
+pub mod simulation;
+pub mod replay;
+pub mod data_file;
+pub mod scene_file;
 pub mod camera;
-pub mod camera_controller;
\ No newline at end of file
+pub mod camera_controller;
+pub mod picking;
+pub mod debug_draw;
+pub mod render_mode;
+pub mod sprite;
+pub mod atlas;
+pub mod sprite_animation;
+pub mod text;
+pub mod ui;
+pub mod console;
+pub mod profiler;
+pub mod capture;
+pub mod settings;
+pub mod gpu;
+pub mod paths;
\ No newline at end of file
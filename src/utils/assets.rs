@@ -0,0 +1,781 @@
+use std::
+{
+    collections::HashMap,
+    path::{ Path, PathBuf },
+    sync::{ mpsc, Arc, Mutex },
+    thread,
+    time::{ Duration, Instant },
+};
+use anyhow::*;
+use notify::Watcher;
+
+use super::
+{
+    texture,
+    mesh,
+};
+
+
+/*
+    Decoding a png or parsing an obj can take a long time, so the
+    asset manager hands the file reading and decoding to worker threads.
+    The workers send the cpu side data back through a channel and
+    poll() (called on the main thread every frame) uploads it to the gpu.
+    Until then anything that asks for a texture gets a placeholder.
+
+    With watch() turned on, files that change on disk go through the
+    same path again and the new gpu resources replace the old ones
+    behind the same handle, so nothing holding a handle has to care.
+    The same goes for recreate(), which loads everything again onto a
+    new device after the old one was lost (see State::recover_device).
+*/
+
+// exporters tend to write a file in several steps,
+// so wait for it to stop changing before reloading it
+const RELOAD_DELAY: Duration = Duration::from_millis(250);
+
+// handles are just indices into the asset manager's slots,
+// they stay valid for as long as the asset manager lives
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TextureHandle(usize);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MeshHandle(usize);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AssetStatus
+{
+    Loading,
+    Loaded,
+    Failed,
+}
+
+// how far along the background loading is, for a loading screen
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LoadProgress
+{
+    pub loaded: usize,
+    pub failed: usize,
+    pub total: usize,
+}
+
+impl LoadProgress
+{
+    pub fn is_done(&self) -> bool
+    {
+        self.loaded + self.failed >= self.total
+    }
+
+    // 0.0 -> nothing finished, 1.0 -> everything finished (failed assets count as finished)
+    pub fn fraction(&self) -> f32
+    {
+        if self.total == 0
+        {
+            return 1.0;
+        }
+        (self.loaded + self.failed) as f32 / self.total as f32
+    }
+}
+
+
+/*   <--------Worker Messages-------->   */
+#[derive(Copy, Clone, Debug)]
+enum Slot
+{
+    Texture(usize),
+    Mesh(usize),
+}
+
+struct Job
+{
+    slot: Slot,
+    path: PathBuf,
+    reload: bool,
+}
+
+enum Decoded
+{
+    Texture(image::RgbaImage),
+    Mesh(mesh::MeshData, mesh::MeshShape),
+}
+
+struct Finished
+{
+    slot: Slot,
+    reload: bool,
+    result: Result<Decoded>,
+}
+
+// runs on a worker thread, no gpu access in here
+fn decode(job: &Job) -> Result<Decoded>
+{
+    let bytes = std::fs::read(&job.path)
+        .with_context(|| format!("could not read {}", job.path.display()))?;
+
+    match job.slot
+    {
+        Slot::Texture(_) =>
+        {
+            let img = image::load_from_memory(&bytes)
+                .with_context(|| format!("could not decode image {}", job.path.display()))?;
+            Ok(Decoded::Texture(img.to_rgba8()))
+        }
+        Slot::Mesh(_) =>
+        {
+            let data = mesh::MeshData::from_obj_bytes(&bytes)
+                .with_context(|| format!("could not parse mesh {}", job.path.display()))?;
+            let shape = mesh::MeshShape::from_data(&data);
+            Ok(Decoded::Mesh(data, shape))
+        }
+    }
+}
+
+
+/*   <--------Asset Slots-------->   */
+struct TextureSlot
+{
+    path: PathBuf,
+    status: AssetStatus,
+    texture: Option<texture::Texture>,
+    bind_group: Option<wgpu::BindGroup>,
+    // textures made with add_texture() have no file to load again from, so they keep a copy
+    pixels: Option<image::RgbaImage>,
+}
+
+struct MeshSlot
+{
+    path: PathBuf,
+    status: AssetStatus,
+    mesh: Option<mesh::Mesh>,
+    shape: Option<mesh::MeshShape>,
+    // only made for the wireframe render mode, when it needs one (see 'render_mode.rs')
+    wireframe: Option<mesh::WireframeMesh>,
+}
+
+// the notify watcher has to be kept alive for events to keep coming
+struct FileWatcher
+{
+    _watcher: notify::RecommendedWatcher,
+    changed: mpsc::Receiver<PathBuf>,
+}
+
+// paths are compared after resolving symlinks and '..' so that
+// the paths notify reports match the ones the assets were loaded from
+fn canonical(path: PathBuf) -> PathBuf
+{
+    std::fs::canonicalize(&path).unwrap_or(path)
+}
+
+
+/*   <--------Asset Manager-------->   */
+pub struct AssetManager
+{
+    root: PathBuf,
+    jobs: Option<mpsc::Sender<Job>>,
+    finished: mpsc::Receiver<Finished>,
+    workers: Vec<thread::JoinHandle<()>>,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    placeholder_bind_group: wgpu::BindGroup,
+    textures: Vec<TextureSlot>,
+    texture_paths: HashMap<PathBuf, TextureHandle>,
+    meshes: Vec<MeshSlot>,
+    mesh_paths: HashMap<PathBuf, MeshHandle>,
+    progress: LoadProgress,
+    watcher: Option<FileWatcher>,
+    pending_reloads: HashMap<PathBuf, Instant>,
+}
+
+impl AssetManager
+{
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, root: impl Into<PathBuf>) -> Result<Self>
+    {
+        // workers pull jobs from one shared receiver, whoever is free takes the next one
+        let (job_sender, job_receiver) = mpsc::channel::<Job>();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let (finished_sender, finished) = mpsc::channel();
+
+        let worker_count = thread::available_parallelism()
+            .map(|n| n.get().saturating_sub(1).clamp(1, 4))
+            .unwrap_or(1);
+
+        let mut workers = Vec::with_capacity(worker_count);
+        for i in 0..worker_count
+        {
+            let job_receiver = Arc::clone(&job_receiver);
+            let finished_sender = finished_sender.clone();
+            let worker = thread::Builder::new()
+                .name(format!("asset worker {}", i))
+                .spawn(move || loop
+                {
+                    // the lock is only held while waiting for a job, not while decoding
+                    let job = match job_receiver.lock().unwrap().recv()
+                    {
+                        std::result::Result::Ok(job) => job,
+                        // the asset manager was dropped
+                        Err(_) => break,
+                    };
+                    let result = decode(&job);
+                    if finished_sender.send(Finished { slot: job.slot, reload: job.reload, result }).is_err()
+                    {
+                        break;
+                    }
+                })?;
+            workers.push(worker);
+        }
+
+        // A BindGroup describes a set of resources and how they can be accessed by a shader
+        let texture_bind_group_layout = Self::create_texture_bind_group_layout(device);
+        let placeholder_bind_group = Self::create_placeholder(device, queue, &texture_bind_group_layout)?;
+
+        Ok(Self
+        {
+            root: canonical(root.into()),
+            jobs: Some(job_sender),
+            finished,
+            workers,
+            texture_bind_group_layout,
+            placeholder_bind_group,
+            textures: Vec::new(),
+            texture_paths: HashMap::new(),
+            meshes: Vec::new(),
+            mesh_paths: HashMap::new(),
+            progress: LoadProgress::default(),
+            watcher: None,
+            pending_reloads: HashMap::new(),
+        })
+    }
+
+    fn create_texture_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout
+    {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry
+                    {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture
+                        {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry
+                    {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+                label: Some("texture_bind_group_layout"),
+            })
+    }
+
+    // grey checkerboard shown in place of textures that are still loading
+    fn create_placeholder(device: &wgpu::Device, queue: &wgpu::Queue, layout: &wgpu::BindGroupLayout) -> Result<wgpu::BindGroup>
+    {
+        let placeholder_image = image::RgbaImage::from_fn(8, 8, |x, y|
+        {
+            if (x + y) % 2 == 0 { image::Rgba([96, 96, 96, 255]) } else { image::Rgba([160, 160, 160, 255]) }
+        });
+        let placeholder = texture::Texture::from_rgba(device, queue, &placeholder_image, Some("placeholder texture"))?;
+        Ok(Self::create_texture_bind_group(device, layout, &placeholder))
+    }
+
+    // everything again on a new device, after the old one was lost. handles stay the same,
+    // files are loaded again in the background (like a hot reload) and added textures come from their copies,
+    // an added texture that can't be made again gets the placeholder and is marked failed, the rest carry on
+    pub fn recreate(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<()>
+    {
+        self.texture_bind_group_layout = Self::create_texture_bind_group_layout(device);
+        self.placeholder_bind_group = Self::create_placeholder(device, queue, &self.texture_bind_group_layout)?;
+
+        let mut reloads = Vec::new();
+        for (i, slot) in self.textures.iter_mut().enumerate()
+        {
+            // the old ones went with the old device
+            slot.texture = None;
+            slot.bind_group = None;
+            if let Some(pixels) = &slot.pixels
+            {
+                match texture::Texture::from_rgba(device, queue, pixels, Some(&slot.path.display().to_string()))
+                {
+                    std::result::Result::Ok(texture) =>
+                    {
+                        slot.bind_group = Some(Self::create_texture_bind_group(device, &self.texture_bind_group_layout, &texture));
+                        slot.texture = Some(texture);
+                    }
+                    Err(e) =>
+                    {
+                        // added textures aren't counted in the load progress, so it stays as it is
+                        log::error!("couldn't make {} again: {:#}", slot.path.display(), e);
+                        slot.status = AssetStatus::Failed;
+                    }
+                }
+            }
+            // the ones still loading get uploaded to the new device when they're done anyway
+            else if slot.status == AssetStatus::Loaded
+            {
+                reloads.push((Slot::Texture(i), slot.path.clone()));
+            }
+        }
+        for (i, slot) in self.meshes.iter_mut().enumerate()
+        {
+            slot.mesh = None;
+            slot.wireframe = None;
+            if slot.status == AssetStatus::Loaded
+            {
+                reloads.push((Slot::Mesh(i), slot.path.clone()));
+            }
+        }
+        log::info!("loading {} assets again", reloads.len());
+        for (slot, path) in reloads
+        {
+            self.submit(slot, path, true);
+        }
+        Ok(())
+    }
+
+    fn create_texture_bind_group(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, texture: &texture::Texture) -> wgpu::BindGroup
+    {
+        // a BindGroup is a more specific declaration of the BindGroupLayout
+        device.create_bind_group(
+            &wgpu::BindGroupDescriptor
+            {
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry
+                    {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&texture.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                    },
+                ],
+                label: Some("diffuse_bind_group"),
+            }
+        )
+    }
+
+    // layout every texture bind group handed out by the asset manager uses
+    pub fn texture_bind_group_layout(&self) -> &wgpu::BindGroupLayout
+    {
+        &self.texture_bind_group_layout
+    }
+
+    fn submit(&mut self, slot: Slot, path: PathBuf, reload: bool)
+    {
+        // reloads happen in the background of a running game, they aren't part of a loading screen
+        if !reload
+        {
+            self.progress.total += 1;
+        }
+        if let Some(jobs) = &self.jobs
+        {
+            // the workers only stop once the sender is dropped, so this can't fail
+            let _ = jobs.send(Job { slot, path, reload });
+        }
+    }
+
+    // starts watching the asset root, any loaded texture or mesh whose file changes gets reloaded
+    pub fn watch(&mut self) -> Result<()>
+    {
+        let (changed_sender, changed) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>|
+        {
+            match res
+            {
+                std::result::Result::Ok(event) =>
+                {
+                    if matches!(event.kind, notify::EventKind::Create(_) | notify::EventKind::Modify(_))
+                    {
+                        for path in event.paths
+                        {
+                            let _ = changed_sender.send(path);
+                        }
+                    }
+                }
+                Err(e) => log::warn!("asset watcher: {}", e),
+            }
+        })?;
+        watcher.watch(&self.root, notify::RecursiveMode::Recursive)
+            .with_context(|| format!("could not watch {}", self.root.display()))?;
+
+        log::info!("watching {} for changes", self.root.display());
+        self.watcher = Some(FileWatcher { _watcher: watcher, changed });
+        Ok(())
+    }
+
+    // sends files that changed (and then sat still for RELOAD_DELAY) back to the workers
+    fn queue_reloads(&mut self)
+    {
+        let now = Instant::now();
+        if let Some(watcher) = &self.watcher
+        {
+            while let std::result::Result::Ok(path) = watcher.changed.try_recv()
+            {
+                self.pending_reloads.insert(canonical(path), now);
+            }
+        }
+
+        let ready: Vec<PathBuf> = self.pending_reloads.iter()
+            .filter(|(_, changed_at)| now.duration_since(**changed_at) >= RELOAD_DELAY)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in ready
+        {
+            self.pending_reloads.remove(&path);
+            // files that were never loaded (or aren't assets at all) are ignored
+            if let Some(handle) = self.texture_paths.get(&path).copied()
+            {
+                self.submit(Slot::Texture(handle.0), path, true);
+            }
+            else if let Some(handle) = self.mesh_paths.get(&path).copied()
+            {
+                self.submit(Slot::Mesh(handle.0), path, true);
+            }
+        }
+    }
+
+    // starts loading a texture (path is relative to the asset root) and returns right away
+    pub fn load_texture(&mut self, path: impl AsRef<Path>) -> TextureHandle
+    {
+        let path = canonical(self.root.join(path));
+        if let Some(handle) = self.texture_paths.get(&path)
+        {
+            return *handle;
+        }
+
+        let handle = TextureHandle(self.textures.len());
+        self.textures.push(TextureSlot
+        {
+            path: path.clone(),
+            status: AssetStatus::Loading,
+            texture: None,
+            bind_group: None,
+            pixels: None,
+        });
+        self.texture_paths.insert(path.clone(), handle);
+        self.submit(Slot::Texture(handle.0), path, false);
+        handle
+    }
+
+    // starts loading an .obj mesh (path is relative to the asset root) and returns right away
+    pub fn load_mesh(&mut self, path: impl AsRef<Path>) -> MeshHandle
+    {
+        let path = canonical(self.root.join(path));
+        if let Some(handle) = self.mesh_paths.get(&path)
+        {
+            return *handle;
+        }
+
+        let handle = MeshHandle(self.meshes.len());
+        self.meshes.push(MeshSlot
+        {
+            path: path.clone(),
+            status: AssetStatus::Loading,
+            mesh: None,
+            shape: None,
+            wireframe: None,
+        });
+        self.mesh_paths.insert(path.clone(), handle);
+        self.submit(Slot::Mesh(handle.0), path, false);
+        handle
+    }
+
+    // a texture made while the game runs (like a texture atlas, see 'atlas.rs') instead of loaded from a file,
+    // there's no file to reload it from, the name is only for labels and texture_path()
+    pub fn add_texture(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, name: &str, rgba: &image::RgbaImage) -> Result<TextureHandle>
+    {
+        let texture = texture::Texture::from_rgba(device, queue, rgba, Some(name))?;
+        let bind_group = Self::create_texture_bind_group(device, &self.texture_bind_group_layout, &texture);
+        let handle = TextureHandle(self.textures.len());
+        self.textures.push(TextureSlot
+        {
+            path: PathBuf::from(name),
+            status: AssetStatus::Loaded,
+            texture: Some(texture),
+            bind_group: Some(bind_group),
+            pixels: Some(rgba.clone()),
+        });
+        Ok(handle)
+    }
+
+    // changes part of a texture made with add_texture (like the glyph cache, see 'text.rs')
+    pub fn write_texture(&mut self, queue: &wgpu::Queue, handle: TextureHandle, x: u32, y: u32, rgba: &image::RgbaImage)
+    {
+        let slot = &mut self.textures[handle.0];
+        if let Some(pixels) = &mut slot.pixels
+        {
+            image::imageops::replace(pixels, rgba, x as i64, y as i64);
+        }
+        if let Some(texture) = &slot.texture
+        {
+            texture.write_region(queue, x, y, rgba);
+        }
+    }
+
+    // finishes whatever the workers decoded since the last call by uploading it to the gpu
+    // has to run on the thread that owns the device, once per frame is enough
+    pub fn poll(&mut self, device: &wgpu::Device, queue: &wgpu::Queue)
+    {
+        self.queue_reloads();
+
+        while let std::result::Result::Ok(finished) = self.finished.try_recv()
+        {
+            let uploaded = match finished.result
+            {
+                std::result::Result::Ok(decoded) => self.upload(device, queue, finished.slot, decoded),
+                Err(e) => Err(e),
+            };
+
+            let (path, status) = match finished.slot
+            {
+                Slot::Texture(i) =>
+                {
+                    let slot = &mut self.textures[i];
+                    (&slot.path, &mut slot.status)
+                }
+                Slot::Mesh(i) =>
+                {
+                    let slot = &mut self.meshes[i];
+                    (&slot.path, &mut slot.status)
+                }
+            };
+            match uploaded
+            {
+                // a failed reload keeps the old version of the asset around
+                std::result::Result::Ok(()) if finished.reload =>
+                {
+                    log::info!("reloaded {}", path.display());
+                    // the file was broken the first time and has been fixed since
+                    if *status == AssetStatus::Failed
+                    {
+                        *status = AssetStatus::Loaded;
+                        self.progress.failed -= 1;
+                        self.progress.loaded += 1;
+                    }
+                }
+                Err(e) if finished.reload => log::error!("{:#}", e),
+                std::result::Result::Ok(()) =>
+                {
+                    log::info!("loaded {}", path.display());
+                    *status = AssetStatus::Loaded;
+                    self.progress.loaded += 1;
+                }
+                Err(e) =>
+                {
+                    log::error!("{:#}", e);
+                    *status = AssetStatus::Failed;
+                    self.progress.failed += 1;
+                }
+            }
+        }
+    }
+
+    fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, slot: Slot, decoded: Decoded) -> Result<()>
+    {
+        match (slot, decoded)
+        {
+            (Slot::Texture(i), Decoded::Texture(rgba)) =>
+            {
+                let label = self.textures[i].path.display().to_string();
+                let texture = texture::Texture::from_rgba(device, queue, &rgba, Some(&label))?;
+                let bind_group = Self::create_texture_bind_group(device, &self.texture_bind_group_layout, &texture);
+                // on a reload this drops the old texture and bind group, anyone drawing
+                // with the handle picks up the new bind group on their next frame
+                self.textures[i].texture = Some(texture);
+                self.textures[i].bind_group = Some(bind_group);
+            }
+            (Slot::Mesh(i), Decoded::Mesh(data, shape)) =>
+            {
+                let label = self.meshes[i].path.display().to_string();
+                self.meshes[i].mesh = Some(mesh::Mesh::from_data(device, &data, &label));
+                self.meshes[i].shape = Some(shape);
+                // made again from the new shape the next time it's needed
+                self.meshes[i].wireframe = None;
+            }
+            _ => unreachable!("workers always decode into the kind of asset the slot holds"),
+        }
+        Ok(())
+    }
+
+    pub fn root(&self) -> &Path
+    {
+        &self.root
+    }
+
+    // where a texture was loaded from, relative to the asset root
+    pub fn texture_path(&self, handle: TextureHandle) -> &Path
+    {
+        let path = &self.textures[handle.0].path;
+        path.strip_prefix(&self.root).unwrap_or(path)
+    }
+
+    // where a mesh was loaded from, relative to the asset root
+    pub fn mesh_path(&self, handle: MeshHandle) -> &Path
+    {
+        let path = &self.meshes[handle.0].path;
+        path.strip_prefix(&self.root).unwrap_or(path)
+    }
+
+    pub fn progress(&self) -> LoadProgress
+    {
+        self.progress
+    }
+
+    // the texture's bind group, or the placeholder one while it's loading (or if it failed)
+    pub fn texture_bind_group(&self, handle: TextureHandle) -> &wgpu::BindGroup
+    {
+        self.textures[handle.0].bind_group.as_ref().unwrap_or(&self.placeholder_bind_group)
+    }
+
+    // meshes have no placeholder, there is just nothing to draw until they're loaded
+    pub fn mesh(&self, handle: MeshHandle) -> Option<&mesh::Mesh>
+    {
+        self.meshes[handle.0].mesh.as_ref()
+    }
+
+    // makes the wireframe version of a mesh if there isn't one yet, does nothing while the mesh is loading
+    pub fn prepare_wireframe(&mut self, device: &wgpu::Device, handle: MeshHandle)
+    {
+        let slot = &mut self.meshes[handle.0];
+        if slot.wireframe.is_none()
+        {
+            if let Some(shape) = &slot.shape
+            {
+                slot.wireframe = Some(mesh::WireframeMesh::from_shape(device, shape, &slot.path.display().to_string()));
+            }
+        }
+    }
+
+    pub fn wireframe_mesh(&self, handle: MeshHandle) -> Option<&mesh::WireframeMesh>
+    {
+        self.meshes[handle.0].wireframe.as_ref()
+    }
+
+    // the cpu copy of a mesh's triangles, for picking (see 'picking.rs')
+    pub fn mesh_shape(&self, handle: MeshHandle) -> Option<&mesh::MeshShape>
+    {
+        self.meshes[handle.0].shape.as_ref()
+    }
+}
+
+impl Drop for AssetManager
+{
+    fn drop(&mut self)
+    {
+        // dropping the sender makes every worker's recv() fail, so they all exit
+        self.jobs = None;
+        for worker in self.workers.drain(..)
+        {
+            let _ = worker.join();
+        }
+    }
+}
+
+
+/*   <--------Loading Levels Without a GPU-------->   */
+// what loading or saving a level needs from the assets: handles for paths and back,
+// AssetManager loads the files, HeadlessAssets just hands out handles (for replays, see 'replay.rs')
+pub trait AssetLoader
+{
+    fn root(&self) -> &Path;
+    fn load_texture(&mut self, path: &Path) -> TextureHandle;
+    fn load_mesh(&mut self, path: &Path) -> MeshHandle;
+    fn texture_path(&self, handle: TextureHandle) -> &Path;
+    fn mesh_path(&self, handle: MeshHandle) -> &Path;
+}
+
+impl AssetLoader for AssetManager
+{
+    fn root(&self) -> &Path
+    {
+        AssetManager::root(self)
+    }
+
+    fn load_texture(&mut self, path: &Path) -> TextureHandle
+    {
+        AssetManager::load_texture(self, path)
+    }
+
+    fn load_mesh(&mut self, path: &Path) -> MeshHandle
+    {
+        AssetManager::load_mesh(self, path)
+    }
+
+    fn texture_path(&self, handle: TextureHandle) -> &Path
+    {
+        AssetManager::texture_path(self, handle)
+    }
+
+    fn mesh_path(&self, handle: MeshHandle) -> &Path
+    {
+        AssetManager::mesh_path(self, handle)
+    }
+}
+
+// the same handles AssetManager would give out, without reading any files
+pub struct HeadlessAssets
+{
+    root: PathBuf,
+    textures: Vec<PathBuf>,
+    meshes: Vec<PathBuf>,
+}
+
+impl HeadlessAssets
+{
+    pub fn new(root: impl Into<PathBuf>) -> Self
+    {
+        Self { root: canonical(root.into()), textures: Vec::new(), meshes: Vec::new() }
+    }
+
+    fn index_of(paths: &mut Vec<PathBuf>, path: PathBuf) -> usize
+    {
+        match paths.iter().position(|known| *known == path)
+        {
+            Some(index) => index,
+            None =>
+            {
+                paths.push(path);
+                paths.len() - 1
+            }
+        }
+    }
+}
+
+impl AssetLoader for HeadlessAssets
+{
+    fn root(&self) -> &Path
+    {
+        &self.root
+    }
+
+    fn load_texture(&mut self, path: &Path) -> TextureHandle
+    {
+        let path = canonical(self.root.join(path));
+        TextureHandle(Self::index_of(&mut self.textures, path))
+    }
+
+    fn load_mesh(&mut self, path: &Path) -> MeshHandle
+    {
+        let path = canonical(self.root.join(path));
+        MeshHandle(Self::index_of(&mut self.meshes, path))
+    }
+
+    fn texture_path(&self, handle: TextureHandle) -> &Path
+    {
+        let path = &self.textures[handle.0];
+        path.strip_prefix(&self.root).unwrap_or(path)
+    }
+
+    fn mesh_path(&self, handle: MeshHandle) -> &Path
+    {
+        let path = &self.meshes[handle.0];
+        path.strip_prefix(&self.root).unwrap_or(path)
+    }
+}
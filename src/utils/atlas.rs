@@ -0,0 +1,302 @@
+use std::
+{
+    collections::HashMap,
+    path::Path,
+};
+use anyhow::*;
+use serde::{ Deserialize, Serialize };
+
+use super::
+{
+    assets,
+    sprite,
+};
+
+
+/*
+    Texture atlases: lots of small images packed into one texture, so
+    sprites using any of them share a bind group and get drawn together
+    (see 'sprite.rs').
+
+        let mut builder = AtlasBuilder::new();
+        builder.add_file(assets.root(), "textures/coin.png")?;
+        builder.add_file(assets.root(), "textures/key.png")?;
+        let atlas = state.build_atlas(&builder, "items")?;
+        let coin = atlas.sprite("textures/coin.png", position, size);
+
+    Images are packed onto shelves, tallest first, in the smallest power of
+    two texture they fit in. Every image gets a border copied from its own
+    edge pixels so filtering never picks up the image next to it.
+*/
+
+// border around every image, in pixels
+const PADDING: u32 = 1;
+// the smallest atlas tried
+const MIN_ATLAS_SIZE: u32 = 64;
+
+
+// a rectangle of pixels in a texture, (0, 0) is the top left
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PixelRect
+{
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl PixelRect
+{
+    pub fn uv(&self, texture_size: (u32, u32)) -> sprite::UvRect
+    {
+        sprite::UvRect::from_pixels(self.x, self.y, self.width, self.height, texture_size)
+    }
+}
+
+
+/*   <--------Packing-------->   */
+#[derive(Default)]
+pub struct AtlasBuilder
+{
+    images: Vec<(String, image::RgbaImage)>,
+}
+
+impl AtlasBuilder
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    // a name that's already in the atlas gets replaced
+    pub fn add(&mut self, name: impl Into<String>, image: image::RgbaImage)
+    {
+        let name = name.into();
+        self.images.retain(|(existing, _)| *existing != name);
+        self.images.push((name, image));
+    }
+
+    // reads an image right away (path is relative to root), it's named by its path
+    pub fn add_file(&mut self, root: &Path, path: impl AsRef<Path>) -> Result<()>
+    {
+        let path = path.as_ref();
+        let image = image::open(root.join(path))
+            .with_context(|| format!("couldn't load {} into an atlas", path.display()))?
+            .to_rgba8();
+        self.add(path.display().to_string(), image);
+        Ok(())
+    }
+
+    // lays out every image in the smallest atlas they fit in, up to max_size pixels across
+    pub fn pack(&self, max_size: u32) -> Result<PackedAtlas>
+    {
+        // tallest first keeps the shelves from wasting much space
+        let mut order: Vec<usize> = (0..self.images.len()).collect();
+        order.sort_by_key(|i|
+        {
+            let (width, height) = self.images[*i].1.dimensions();
+            (std::cmp::Reverse(height), std::cmp::Reverse(width))
+        });
+
+        let (mut width, mut height) = (MIN_ATLAS_SIZE, MIN_ATLAS_SIZE);
+        loop
+        {
+            if let Some(places) = self.place(&order, width, height)
+            {
+                return Ok(self.copy_into(places, width, height));
+            }
+            // grow the shorter side, so the atlas stays about square
+            if width <= height && width < max_size
+            {
+                width *= 2;
+            }
+            else if height < max_size
+            {
+                height *= 2;
+            }
+            else
+            {
+                bail!("{} images don't fit in a {}x{} atlas", self.images.len(), max_size, max_size);
+            }
+        }
+    }
+
+    // where the top left of each image (padding included) goes, None if they don't all fit
+    fn place(&self, order: &[usize], width: u32, height: u32) -> Option<Vec<(u32, u32)>>
+    {
+        let mut places = vec![(0, 0); self.images.len()];
+        let (mut x, mut shelf_y, mut shelf_height) = (0, 0, 0);
+        for &i in order
+        {
+            let (image_width, image_height) = self.images[i].1.dimensions();
+            let (padded_width, padded_height) = (image_width + PADDING * 2, image_height + PADDING * 2);
+            if padded_width > width
+            {
+                return None;
+            }
+            // start a new shelf when this one is full
+            if x + padded_width > width
+            {
+                x = 0;
+                shelf_y += shelf_height;
+                shelf_height = 0;
+            }
+            if shelf_y + padded_height > height
+            {
+                return None;
+            }
+            places[i] = (x, shelf_y);
+            x += padded_width;
+            shelf_height = shelf_height.max(padded_height);
+        }
+        Some(places)
+    }
+
+    fn copy_into(&self, places: Vec<(u32, u32)>, width: u32, height: u32) -> PackedAtlas
+    {
+        let mut atlas = image::RgbaImage::new(width, height);
+        let mut rects = HashMap::new();
+        for ((name, image), (x, y)) in self.images.iter().zip(places)
+        {
+            let (image_width, image_height) = image.dimensions();
+            rects.insert(name.clone(), PixelRect { x: x + PADDING, y: y + PADDING, width: image_width, height: image_height });
+            if image_width == 0 || image_height == 0
+            {
+                continue;
+            }
+            // every pixel of the padded area, the border takes the closest edge pixel
+            for padded_y in 0..image_height + PADDING * 2
+            {
+                for padded_x in 0..image_width + PADDING * 2
+                {
+                    let source_x = padded_x.saturating_sub(PADDING).min(image_width - 1);
+                    let source_y = padded_y.saturating_sub(PADDING).min(image_height - 1);
+                    atlas.put_pixel(x + padded_x, y + padded_y, *image.get_pixel(source_x, source_y));
+                }
+            }
+        }
+        PackedAtlas { image: atlas, rects }
+    }
+}
+
+// the cpu side of an atlas, before it's on the gpu
+pub struct PackedAtlas
+{
+    pub image: image::RgbaImage,
+    pub rects: HashMap<String, PixelRect>,
+}
+
+impl PackedAtlas
+{
+    pub fn upload(self, device: &wgpu::Device, queue: &wgpu::Queue, assets: &mut assets::AssetManager, name: &str) -> Result<TextureAtlas>
+    {
+        let texture = assets.add_texture(device, queue, &format!("atlas {}", name), &self.image)?;
+        Ok(TextureAtlas { texture, size: self.image.dimensions(), rects: self.rects })
+    }
+}
+
+
+/*   <--------Texture Atlas-------->   */
+pub struct TextureAtlas
+{
+    pub texture: assets::TextureHandle,
+    // in pixels
+    pub size: (u32, u32),
+    rects: HashMap<String, PixelRect>,
+}
+
+impl TextureAtlas
+{
+    // where an image ended up, in pixels
+    pub fn rect(&self, name: &str) -> Option<PixelRect>
+    {
+        self.rects.get(name).copied()
+    }
+
+    // the whole of an image
+    pub fn uv(&self, name: &str) -> Option<sprite::UvRect>
+    {
+        self.rect(name).map(|rect| rect.uv(self.size))
+    }
+
+    // part of an image (like one frame of a sprite sheet), rect is in the image's own pixels
+    pub fn uv_within(&self, name: &str, rect: PixelRect) -> Option<sprite::UvRect>
+    {
+        let image = self.rect(name)?;
+        let moved = PixelRect { x: image.x + rect.x, y: image.y + rect.y, ..rect };
+        Some(moved.uv(self.size))
+    }
+
+    // a sprite showing one of the images, None if the atlas doesn't have it
+    pub fn sprite(&self, name: &str, position: cgmath::Vector2<f32>, size: cgmath::Vector2<f32>) -> Option<sprite::Sprite>
+    {
+        let uv = self.uv(name)?;
+        Some(sprite::Sprite { uv, ..sprite::Sprite::new(self.texture, position, size) })
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn builder(sizes: &[(u32, u32)]) -> AtlasBuilder
+    {
+        let mut builder = AtlasBuilder::new();
+        for (i, (width, height)) in sizes.iter().enumerate()
+        {
+            let shade = i as u8 * 40;
+            builder.add(format!("image {}", i), image::RgbaImage::from_pixel(*width, *height, image::Rgba([shade, shade, shade, 255])));
+        }
+        builder
+    }
+
+    fn padded(rect: &PixelRect) -> (u32, u32, u32, u32)
+    {
+        (rect.x - PADDING, rect.y - PADDING, rect.x + rect.width + PADDING, rect.y + rect.height + PADDING)
+    }
+
+    #[test]
+    fn small_images_fit_the_smallest_atlas()
+    {
+        let packed = builder(&[(16, 16), (8, 30), (20, 4)]).pack(1024).unwrap();
+        assert_eq!(packed.image.dimensions(), (MIN_ATLAS_SIZE, MIN_ATLAS_SIZE));
+        assert_eq!(packed.rects.len(), 3);
+        assert_eq!((packed.rects["image 1"].width, packed.rects["image 1"].height), (8, 30));
+    }
+
+    #[test]
+    fn packed_images_dont_overlap_and_keep_their_pixels()
+    {
+        let sizes: Vec<_> = (0..6).map(|i| (10 + i * 7, 40 - i * 5)).collect();
+        let packed = builder(&sizes).pack(1024).unwrap();
+        let (width, height) = packed.image.dimensions();
+        assert!(width > MIN_ATLAS_SIZE || height > MIN_ATLAS_SIZE);
+
+        let rects: Vec<_> = (0..sizes.len()).map(|i| packed.rects[&format!("image {}", i)]).collect();
+        for (i, rect) in rects.iter().enumerate()
+        {
+            let (left, top, right, bottom) = padded(rect);
+            assert!(right <= width && bottom <= height);
+            for other in &rects[i + 1..]
+            {
+                let (other_left, other_top, other_right, other_bottom) = padded(other);
+                assert!(right <= other_left || other_right <= left || bottom <= other_top || other_bottom <= top, "{:?} overlaps {:?}", rect, other);
+            }
+            // the image and its border are all its own colour
+            let shade = i as u8 * 40;
+            assert_eq!(*packed.image.get_pixel(left, top), image::Rgba([shade, shade, shade, 255]));
+            assert_eq!(*packed.image.get_pixel(right - 1, bottom - 1), image::Rgba([shade, shade, shade, 255]));
+        }
+    }
+
+    #[test]
+    fn images_too_big_for_max_size_dont_pack()
+    {
+        assert!(builder(&[(200, 10)]).pack(128).is_err());
+        assert!(builder(&[(100, 100); 4]).pack(128).is_err());
+        assert_eq!(builder(&[(100, 100); 4]).pack(256).unwrap().image.dimensions(), (256, 256));
+    }
+}
@@ -0,0 +1,416 @@
+use cgmath::
+{
+    EuclideanSpace,
+    InnerSpace,
+    Transform,
+};
+
+
+/*
+    Bounding volumes, rays and view frusta.
+
+    Rays don't need a normalized direction: a ray moved into a mesh's local
+    space with the inverse of its world matrix still gives the same t for the
+    same point, so hits in different meshes can be compared directly.
+*/
+
+/*   <--------Ray-------->   */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ray
+{
+    pub origin: cgmath::Point3<f32>,
+    pub direction: cgmath::Vector3<f32>,
+}
+
+// where a ray hit something, t is how far along the ray (in lengths of its direction)
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RayHit
+{
+    pub t: f32,
+    // the surface normal at the hit, facing back towards the ray
+    pub normal: cgmath::Vector3<f32>,
+}
+
+impl Ray
+{
+    pub fn new(origin: cgmath::Point3<f32>, direction: cgmath::Vector3<f32>) -> Self
+    {
+        Self { origin, direction }
+    }
+
+    pub fn at(&self, t: f32) -> cgmath::Point3<f32>
+    {
+        self.origin + self.direction * t
+    }
+
+    // the same ray in the space a matrix maps into
+    pub fn transformed(&self, matrix: &cgmath::Matrix4<f32>) -> Self
+    {
+        Self
+        {
+            origin: matrix.transform_point(self.origin),
+            direction: matrix.transform_vector(self.direction),
+        }
+    }
+
+    // Möller-Trumbore, both sides of the triangle count
+    pub fn intersect_triangle(&self, a: cgmath::Point3<f32>, b: cgmath::Point3<f32>, c: cgmath::Point3<f32>) -> Option<RayHit>
+    {
+        let edge1 = b - a;
+        let edge2 = c - a;
+        let p = self.direction.cross(edge2);
+        let determinant = edge1.dot(p);
+        // parallel to the triangle (or the triangle has no area)
+        if determinant.abs() < f32::EPSILON * edge1.magnitude() * edge2.magnitude() * self.direction.magnitude()
+        {
+            return None;
+        }
+
+        let inverse = 1.0 / determinant;
+        let s = self.origin - a;
+        let u = s.dot(p) * inverse;
+        if !(0.0..=1.0).contains(&u)
+        {
+            return None;
+        }
+        let q = s.cross(edge1);
+        let v = self.direction.dot(q) * inverse;
+        if v < 0.0 || u + v > 1.0
+        {
+            return None;
+        }
+
+        let t = edge2.dot(q) * inverse;
+        if t < 0.0
+        {
+            return None;
+        }
+        Some(RayHit { t, normal: facing(edge1.cross(edge2).normalize(), self.direction) })
+    }
+}
+
+// flips a normal to point against the ray
+fn facing(normal: cgmath::Vector3<f32>, direction: cgmath::Vector3<f32>) -> cgmath::Vector3<f32>
+{
+    if normal.dot(direction) > 0.0 { -normal } else { normal }
+}
+
+
+/*   <--------Axis Aligned Box-------->   */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb
+{
+    pub min: cgmath::Point3<f32>,
+    pub max: cgmath::Point3<f32>,
+}
+
+impl Aabb
+{
+    // None when there are no points
+    pub fn from_points(points: impl IntoIterator<Item = cgmath::Point3<f32>>) -> Option<Self>
+    {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(Self { min: first, max: first }, |aabb, point| aabb.including(point)))
+    }
+
+    pub fn including(self, point: cgmath::Point3<f32>) -> Self
+    {
+        Self
+        {
+            min: cgmath::Point3::new(self.min.x.min(point.x), self.min.y.min(point.y), self.min.z.min(point.z)),
+            max: cgmath::Point3::new(self.max.x.max(point.x), self.max.y.max(point.y), self.max.z.max(point.z)),
+        }
+    }
+
+    pub fn center(&self) -> cgmath::Point3<f32>
+    {
+        self.min.midpoint(self.max)
+    }
+
+    // half the size along each axis
+    pub fn extents(&self) -> cgmath::Vector3<f32>
+    {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn corners(&self) -> [cgmath::Point3<f32>; 8]
+    {
+        let (min, max) = (self.min, self.max);
+        [
+            cgmath::Point3::new(min.x, min.y, min.z),
+            cgmath::Point3::new(max.x, min.y, min.z),
+            cgmath::Point3::new(min.x, max.y, min.z),
+            cgmath::Point3::new(max.x, max.y, min.z),
+            cgmath::Point3::new(min.x, min.y, max.z),
+            cgmath::Point3::new(max.x, min.y, max.z),
+            cgmath::Point3::new(min.x, max.y, max.z),
+            cgmath::Point3::new(max.x, max.y, max.z),
+        ]
+    }
+
+    // the box around this box after it's been moved by a matrix, (a bit) bigger than the box itself when rotated
+    pub fn transformed(&self, matrix: &cgmath::Matrix4<f32>) -> Self
+    {
+        let corners = self.corners().map(|corner| matrix.transform_point(corner));
+        Self::from_points(corners).unwrap_or(*self)
+    }
+
+    // slab test, a ray starting inside the box hits it at t = 0
+    pub fn intersect_ray(&self, ray: &Ray) -> Option<RayHit>
+    {
+        let mut t_min = 0.0f32;
+        let mut t_max = f32::INFINITY;
+        let mut normal = cgmath::Vector3::new(0.0, 0.0, 0.0);
+        for axis in 0..3
+        {
+            let (origin, direction) = (ray.origin[axis], ray.direction[axis]);
+            if direction == 0.0
+            {
+                // parallel to this pair of faces, it has to start between them
+                if origin < self.min[axis] || origin > self.max[axis]
+                {
+                    return None;
+                }
+                continue;
+            }
+            let mut near = (self.min[axis] - origin) / direction;
+            let mut far = (self.max[axis] - origin) / direction;
+            if near > far
+            {
+                std::mem::swap(&mut near, &mut far);
+            }
+            if near > t_min
+            {
+                t_min = near;
+                normal = cgmath::Vector3::new(0.0, 0.0, 0.0);
+                normal[axis] = -direction.signum();
+            }
+            t_max = t_max.min(far);
+            if t_min > t_max
+            {
+                return None;
+            }
+        }
+        // started inside, there's no face to speak of
+        if normal == cgmath::Vector3::new(0.0, 0.0, 0.0)
+        {
+            normal = -ray.direction.normalize();
+        }
+        Some(RayHit { t: t_min, normal })
+    }
+}
+
+
+/*   <--------Bounding Sphere-------->   */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BoundingSphere
+{
+    pub center: cgmath::Point3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere
+{
+    // centered on the box around the points, big enough for every one of them
+    pub fn around(aabb: &Aabb, points: impl IntoIterator<Item = cgmath::Point3<f32>>) -> Self
+    {
+        let center = aabb.center();
+        let radius = points.into_iter()
+            .map(|point| (point - center).magnitude2())
+            .fold(0.0f32, f32::max)
+            .sqrt();
+        Self { center, radius }
+    }
+
+    // the sphere around this sphere after it's been moved by a matrix (non-uniform scale makes it an ellipsoid, this covers it)
+    pub fn transformed(&self, matrix: &cgmath::Matrix4<f32>) -> Self
+    {
+        let scale = matrix.x.truncate().magnitude()
+            .max(matrix.y.truncate().magnitude())
+            .max(matrix.z.truncate().magnitude());
+        Self { center: matrix.transform_point(self.center), radius: self.radius * scale }
+    }
+
+    // a ray starting inside the sphere hits it at t = 0
+    pub fn intersect_ray(&self, ray: &Ray) -> Option<RayHit>
+    {
+        let to_center = ray.origin - self.center;
+        let a = ray.direction.magnitude2();
+        let b = to_center.dot(ray.direction);
+        let c = to_center.magnitude2() - self.radius * self.radius;
+        if c <= 0.0
+        {
+            return Some(RayHit { t: 0.0, normal: -ray.direction.normalize() });
+        }
+        // pointing away from the sphere, or missing it
+        let discriminant = b * b - a * c;
+        if b > 0.0 || discriminant < 0.0 || a == 0.0
+        {
+            return None;
+        }
+        let t = (-b - discriminant.sqrt()) / a;
+        Some(RayHit { t, normal: (ray.at(t) - self.center).normalize() })
+    }
+}
+
+
+/*   <--------Frustum-------->   */
+// a plane is the points where normal . point + distance = 0, the normal points to the inside
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Plane
+{
+    pub normal: cgmath::Vector3<f32>,
+    pub distance: f32,
+}
+
+impl Plane
+{
+    // from a, b, c, d in ax + by + cz + d = 0, scaled so the normal has length 1
+    fn from_coefficients(coefficients: cgmath::Vector4<f32>) -> Self
+    {
+        let normal = coefficients.truncate();
+        let length = normal.magnitude();
+        if length == 0.0
+        {
+            return Self { normal, distance: coefficients.w };
+        }
+        Self { normal: normal / length, distance: coefficients.w / length }
+    }
+
+    // how far in front of the plane a point is, negative behind it
+    pub fn signed_distance(&self, point: cgmath::Point3<f32>) -> f32
+    {
+        self.normal.dot(point.to_vec()) + self.distance
+    }
+}
+
+// the six planes around what a camera can see
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frustum
+{
+    // left, right, bottom, top, near, far
+    pub planes: [Plane; 6],
+}
+
+impl Frustum
+{
+    // from a view projection matrix with wgpu's 0..1 depth (see camera::Camera::build_view_projection_matrix)
+    pub fn from_view_projection(matrix: &cgmath::Matrix4<f32>) -> Self
+    {
+        use cgmath::Matrix;
+        // a point is inside when -w <= x <= w, -w <= y <= w and 0 <= z <= w in clip space,
+        // each of those is a plane made from the matrix's rows
+        let row = |i: usize| matrix.row(i);
+        let planes = [
+            row(3) + row(0),
+            row(3) - row(0),
+            row(3) + row(1),
+            row(3) - row(1),
+            row(2),
+            row(3) - row(2),
+        ];
+        Self { planes: planes.map(Plane::from_coefficients) }
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool
+    {
+        self.planes.iter().all(|plane| plane.signed_distance(sphere.center) >= -sphere.radius)
+    }
+
+    // conservative, a box right outside a corner of the frustum can still count as inside
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool
+    {
+        self.planes.iter().all(|plane|
+        {
+            // the corner furthest along the plane's normal, if that one's behind the plane they all are
+            let furthest = cgmath::Point3::new(
+                if plane.normal.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if plane.normal.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if plane.normal.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            );
+            plane.signed_distance(furthest) >= 0.0
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn triangle() -> [cgmath::Point3<f32>; 3]
+    {
+        [cgmath::Point3::new(-1.0, -1.0, 0.0), cgmath::Point3::new(1.0, -1.0, 0.0), cgmath::Point3::new(0.0, 1.0, 0.0)]
+    }
+
+    fn hit(ray: Ray) -> Option<RayHit>
+    {
+        let [a, b, c] = triangle();
+        ray.intersect_triangle(a, b, c)
+    }
+
+    #[test]
+    fn rays_hit_triangles_from_either_side()
+    {
+        let front = hit(Ray::new(cgmath::Point3::new(0.0, 0.0, 2.0), cgmath::Vector3::new(0.0, 0.0, -2.0))).unwrap();
+        assert!((front.t - 1.0).abs() < 1e-6);
+        assert_eq!(front.normal, cgmath::Vector3::unit_z());
+
+        let back = hit(Ray::new(cgmath::Point3::new(0.0, 0.0, -3.0), cgmath::Vector3::unit_z())).unwrap();
+        assert!((back.t - 3.0).abs() < 1e-6);
+        assert_eq!(back.normal, -cgmath::Vector3::unit_z());
+    }
+
+    #[test]
+    fn rays_miss_triangles_beside_behind_or_parallel_to_them()
+    {
+        // beside it
+        assert_eq!(hit(Ray::new(cgmath::Point3::new(2.0, 0.0, 2.0), -cgmath::Vector3::unit_z())), None);
+        // the triangle is behind where the ray starts
+        assert_eq!(hit(Ray::new(cgmath::Point3::new(0.0, 0.0, 2.0), cgmath::Vector3::unit_z())), None);
+        // in its plane, and just above it
+        assert_eq!(hit(Ray::new(cgmath::Point3::new(-5.0, 0.0, 0.0), cgmath::Vector3::unit_x())), None);
+        assert_eq!(hit(Ray::new(cgmath::Point3::new(-5.0, 0.0, 0.1), cgmath::Vector3::unit_x())), None);
+    }
+
+    // a camera at the origin looking down -z, seeing from 1 to 100 units away
+    fn frustum() -> Frustum
+    {
+        let view = cgmath::Matrix4::look_at_rh(cgmath::Point3::new(0.0, 0.0, 0.0), cgmath::Point3::new(0.0, 0.0, -1.0), cgmath::Vector3::unit_y());
+        let projection = cgmath::perspective(cgmath::Deg(90.0), 1.0, 1.0, 100.0);
+        Frustum::from_view_projection(&(crate::utils::camera::OPENGL_TO_WGPU_MATRIX * projection * view))
+    }
+
+    #[test]
+    fn frustum_near_and_far_planes_are_at_wgpus_depth_range()
+    {
+        let [.., near, far] = frustum().planes;
+        assert!(near.signed_distance(cgmath::Point3::new(0.0, 0.0, -1.0)).abs() < 1e-4);
+        assert!(far.signed_distance(cgmath::Point3::new(0.0, 0.0, -100.0)).abs() < 1e-3);
+        // with opengl's -1..1 depth the near plane would end up behind the camera
+        assert!(near.signed_distance(cgmath::Point3::new(0.0, 0.0, -0.5)) < 0.0);
+        assert!(far.signed_distance(cgmath::Point3::new(0.0, 0.0, -101.0)) < 0.0);
+    }
+
+    #[test]
+    fn frustum_culls_what_the_camera_cant_see()
+    {
+        let frustum = frustum();
+        let sphere = |x, z| BoundingSphere { center: cgmath::Point3::new(x, 0.0, z), radius: 0.25 };
+        assert!(frustum.intersects_sphere(&sphere(0.0, -50.0)));
+        // poking in past the near plane and the side
+        assert!(frustum.intersects_sphere(&sphere(0.0, -0.8)));
+        assert!(frustum.intersects_sphere(&sphere(10.1, -10.0)));
+        // before the near plane, past the far one, off to the side and behind the camera
+        assert!(!frustum.intersects_sphere(&sphere(0.0, -0.5)));
+        assert!(!frustum.intersects_sphere(&sphere(0.0, -101.0)));
+        assert!(!frustum.intersects_sphere(&sphere(11.0, -10.0)));
+        assert!(!frustum.intersects_sphere(&sphere(0.0, 5.0)));
+
+        let aabb = |min: [f32; 3], max: [f32; 3]| Aabb { min: min.into(), max: max.into() };
+        assert!(frustum.intersects_aabb(&aabb([-1.0, -1.0, -6.0], [1.0, 1.0, -4.0])));
+        assert!(!frustum.intersects_aabb(&aabb([-1.0, -1.0, 1.0], [1.0, 1.0, 3.0])));
+    }
+}
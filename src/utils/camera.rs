@@ -7,6 +7,7 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     0.0, 0.0, 0.5, 1.0,
 );
 
+#[derive(Copy, Clone, Debug)]
 pub struct Camera
 {
     pub eye: cgmath::Point3<f32>,
@@ -27,7 +28,23 @@ impl Camera
         let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
 
         // This matrix will scale and translate our scene from OpenGL's coordinate system to WGPU's
-        return OPENGL_TO_WGPU_MATRIX * proj * view;
+        OPENGL_TO_WGPU_MATRIX * proj * view
+    }
+
+    // blends between two simulation ticks, t = 0 -> self, t = 1 -> other
+    pub fn lerp(&self, other: &Camera, t: f32) -> Camera
+    {
+        use cgmath::{ EuclideanSpace, VectorSpace };
+        Camera
+        {
+            eye: cgmath::Point3::from_vec(self.eye.to_vec().lerp(other.eye.to_vec(), t)),
+            target: cgmath::Point3::from_vec(self.target.to_vec().lerp(other.target.to_vec(), t)),
+            up: self.up.lerp(other.up, t),
+            aspect: other.aspect,
+            fovy: self.fovy + (other.fovy - self.fovy) * t,
+            znear: other.znear,
+            zfar: other.zfar,
+        }
     }
 }
 
@@ -41,6 +58,9 @@ pub struct CameraUniform
 {
     // can't use cgmath with bytemuck directly so have to convert Matrix4 to 4x4 f32 array
     view_proj: [[f32; 4]; 4],
+    // the render modes that show depth need these to make it linear again (see 'render_mode.rs'),
+    // x = znear, y = zfar, the rest is padding
+    planes: [f32; 4],
 }
 
 impl CameraUniform
@@ -52,11 +72,21 @@ impl CameraUniform
         Self 
         {
             view_proj: cgmath::Matrix4::identity().into(),
+            planes: [0.1, 100.0, 0.0, 0.0],
         }
     }
 
     pub fn update_view_proj(&mut self, camera: &Camera)
     {
         self.view_proj = camera.build_view_projection_matrix().into();
+        self.planes = [camera.znear, camera.zfar, 0.0, 0.0];
     }
-}
\ No newline at end of file
+}
+
+impl Default for CameraUniform
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
@@ -1,19 +1,13 @@
-use winit::
-{
-    event::*,
-};
 use super::
 {
     camera,
+    input,
 };
 
+// moves the camera around its target, driven by the zoom and orbit axes (see 'input.rs')
 pub struct CameraController
 {
     pub speed: f32,
-    pub is_forward_pressed: bool,
-    pub is_backward_pressed: bool,
-    pub is_left_pressed: bool,
-    pub is_right_pressed: bool,
 }
 
 impl CameraController
@@ -23,73 +17,27 @@ impl CameraController
         Self
         {
             speed,
-            is_forward_pressed: false,
-            is_backward_pressed: false,
-            is_left_pressed: false,
-            is_right_pressed: false,
         }
     }
 
-    pub fn process_events(&mut self, event: &WindowEvent) -> bool
-    {
-        match event
-        {
-            WindowEvent::KeyboardInput
-            {
-                input: KeyboardInput
-                {
-                    state,
-                    virtual_keycode: Some(keycode),
-                    ..
-                },
-                ..
-            } =>
-            {
-                let is_pressed = *state == ElementState::Pressed;
-                match keycode
-                {
-                    VirtualKeyCode::W | VirtualKeyCode::Up =>
-                    {
-                        self.is_forward_pressed = is_pressed;
-                        true
-                    }
-                    VirtualKeyCode::A | VirtualKeyCode::Left =>
-                    {
-                        self.is_left_pressed = is_pressed;
-                        true
-                    }
-                    VirtualKeyCode::S | VirtualKeyCode::Down =>
-                    {
-                        self.is_backward_pressed = is_pressed;
-                        true
-                    }
-                    VirtualKeyCode::D | VirtualKeyCode::Right =>
-                    {
-                        self.is_right_pressed = is_pressed;
-                        true
-                    }
-                    _ => false,
-                }
-            }
-            _ => false,
-        }
-    }
-
-    pub fn update_camera(&self, camera: &mut camera::Camera)
+    pub fn update_camera(&self, camera: &mut camera::Camera, input: &input::Input)
     {
         use cgmath::InnerSpace;
         let forward = camera.target - camera.eye;
         let forward_norm = forward.normalize();
         let formward_mag = forward.magnitude();
 
+        // zoom > 0 moves in, zoom < 0 moves out, the scroll wheel can go past 1
+        let zoom = input.axis(input::ZOOM) * self.speed;
+
         // prevents glitching when camera gets too close to center of screen
-        if self.is_forward_pressed && formward_mag > self.speed
+        if zoom > 0.0 && formward_mag > zoom
         {
-            camera.eye += forward_norm * self.speed;
+            camera.eye += forward_norm * zoom;
         }
-        if self.is_backward_pressed
+        if zoom < 0.0
         {
-            camera.eye -= forward_norm * self.speed;
+            camera.eye += forward_norm * zoom;
         }
 
         let right = forward_norm.cross(camera.up);
@@ -98,16 +46,13 @@ impl CameraController
         let forward = camera.target - camera.eye;
         let forward_mag = forward.magnitude();
 
-        if self.is_right_pressed
+        let orbit = input.axis(input::ORBIT) * self.speed;
+        if orbit != 0.0
         {
             // Rescale the distance between the target and eye so 
             // that it doesn't change. The eye therefore still 
             // lies on the circle made by the target and eye.
-            camera.eye = camera.target - (forward + right * self.speed).normalize() * forward_mag;
-        }
-        if self.is_left_pressed
-        {
-            camera.eye = camera.target - (forward - right * self.speed).normalize() * forward_mag;
+            camera.eye = camera.target - (forward + right * orbit).normalize() * forward_mag;
         }
     }
-}
\ No newline at end of file
+}
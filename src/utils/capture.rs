@@ -0,0 +1,551 @@
+use std::
+{
+    future::Future,
+    path::{ Path, PathBuf },
+    pin::Pin,
+    sync::mpsc,
+    task::{ self, Poll, Waker },
+    thread,
+};
+
+
+/*
+    Screenshots and frame sequences, saved as PNGs.
+
+    A capture copies a texture with COPY_SRC into a buffer as part of the
+    frame's commands. The buffer is read back once the gpu is done with it,
+    without waiting, and a worker thread writes the file so saving never
+    holds up a frame:
+
+        F12 -> screenshots/screenshot-<time>.png
+        F9  -> every CAPTURE_EVERY'th frame to captures/<time>/frame-00001.png, ...
+               until F9 again, for trailers and bug reports
+
+    Surfaces can't always be copied from (Metal and GL ones are only ever
+    drawn to), so a frame that's being saved is drawn into an offscreen
+    target instead, copied from there, and then drawn onto the surface.
+    The target is only around while a screenshot or sequence is pending.
+
+    Rows in a buffer copy have to start on COPY_BYTES_PER_ROW_ALIGNMENT,
+    so the padding at the end of each row is cut off before saving.
+*/
+
+// where screenshots go, relative to the working directory
+pub const SCREENSHOT_DIR: &str = "screenshots";
+// where frame sequences go, each in a folder of its own
+pub const SEQUENCE_DIR: &str = "captures";
+// the hotkey records one frame out of this many
+pub const CAPTURE_EVERY: u32 = 2;
+
+type MapFuture = Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>> + Send>>;
+
+
+// bytes per row in a buffer copy of a texture width pixels across, padding included
+pub fn padded_bytes_per_row(width: u32) -> u32
+{
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    (width * 4).div_ceil(align) * align
+}
+
+// the pixels of a buffer copy as rgba rows with nothing in between, bgra formats get swapped around
+pub fn unpad_rows(data: &[u8], width: u32, height: u32, format: wgpu::TextureFormat) -> Vec<u8>
+{
+    let padded = padded_bytes_per_row(width) as usize;
+    let unpadded = width as usize * 4;
+    let bgra = matches!(format, wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb);
+    let mut pixels = Vec::with_capacity(unpadded * height as usize);
+    for row in data.chunks(padded).take(height as usize)
+    {
+        pixels.extend_from_slice(&row[..unpadded]);
+    }
+    if bgra
+    {
+        for pixel in pixels.chunks_exact_mut(4)
+        {
+            pixel.swap(0, 2);
+        }
+    }
+    pixels
+}
+
+// only 8 bit rgba and bgra can be saved
+pub fn can_capture(format: wgpu::TextureFormat) -> bool
+{
+    matches!(format,
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb |
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb)
+}
+
+
+/*   <--------Capture-------->   */
+struct Readback
+{
+    buffer: wgpu::Buffer,
+    path: PathBuf,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    // None until the frame is submitted
+    mapping: Option<MapFuture>,
+}
+
+struct Sequence
+{
+    directory: PathBuf,
+    every: u32,
+    // frames seen and images written since it started
+    frames: u64,
+    written: u32,
+}
+
+// where a frame that's being captured is drawn, instead of the surface
+struct CaptureTarget
+{
+    texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+    size: (u32, u32),
+    format: wgpu::TextureFormat,
+}
+
+// draws the capture target onto the surface
+struct Blit
+{
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    format: wgpu::TextureFormat,
+}
+
+impl Blit
+{
+    fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self
+    {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor
+        {
+            label: Some("blit_bind_group_layout"),
+            entries:
+            &[
+                wgpu::BindGroupLayoutEntry
+                {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture
+                    {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor
+        {
+            label: Some("Blit Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/blit.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor
+        {
+            label: Some("Blit Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor
+        {
+            label: Some("Blit Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState
+            {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState
+            {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState { format, blend: None, write_mask: wgpu::ColorWrites::ALL }],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        Self { pipeline, bind_group_layout, format }
+    }
+
+    fn create_target(&self, device: &wgpu::Device, (width, height): (u32, u32)) -> CaptureTarget
+    {
+        let texture = device.create_texture(&wgpu::TextureDescriptor
+        {
+            label: Some("Capture Target"),
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor
+        {
+            label: Some("capture_target_bind_group"),
+            layout: &self.bind_group_layout,
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&view) }],
+        });
+        CaptureTarget { texture, bind_group, size: (width, height), format: self.format }
+    }
+}
+
+// a frame being drawn into the capture target, from Capture::begin_frame() to Capture::end_frame()
+pub struct FrameCapture
+{
+    path: PathBuf,
+    // where the frame goes once it's been copied
+    surface_view: wgpu::TextureView,
+}
+
+struct SaveJob
+{
+    path: PathBuf,
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+pub struct Capture
+{
+    // Some(path) -> the next frame is saved there
+    screenshot: Option<PathBuf>,
+    sequence: Option<Sequence>,
+    readbacks: Vec<Readback>,
+    saver: Option<(mpsc::Sender<SaveJob>, thread::JoinHandle<()>)>,
+    // made when a capture is pending, the target goes again once nothing is
+    target: Option<CaptureTarget>,
+    blit: Option<Blit>,
+}
+
+impl Default for Capture
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl Capture
+{
+    pub fn new() -> Self
+    {
+        let (sender, receiver) = mpsc::channel::<SaveJob>();
+        let saver = thread::Builder::new()
+            .name(String::from("capture saver"))
+            .spawn(move ||
+            {
+                for job in receiver
+                {
+                    if let Some(parent) = job.path.parent()
+                    {
+                        let _ = std::fs::create_dir_all(parent);
+                    }
+                    match image::save_buffer(&job.path, &job.pixels, job.width, job.height, image::ColorType::Rgba8)
+                    {
+                        Ok(()) => log::info!("saved {}", job.path.display()),
+                        Err(e) => log::error!("couldn't save {}: {}", job.path.display(), e),
+                    }
+                }
+            })
+            .map_err(|e| log::error!("no capture thread, captures won't be saved: {}", e))
+            .ok();
+        Self
+        {
+            screenshot: None,
+            sequence: None,
+            readbacks: Vec::new(),
+            saver: saver.map(|saver| (sender, saver)),
+            target: None,
+            blit: None,
+        }
+    }
+
+    // the next frame goes to path, or to a new file in SCREENSHOT_DIR
+    pub fn screenshot(&mut self, path: Option<PathBuf>)
+    {
+        self.screenshot = Some(path.unwrap_or_else(|| Path::new(SCREENSHOT_DIR).join(format!("screenshot-{}.png", timestamp()))));
+    }
+
+    // saves one frame in every `every` as numbered images in directory, until stop_sequence()
+    pub fn start_sequence(&mut self, directory: PathBuf, every: u32)
+    {
+        log::info!("capturing every {} frames to {}", every.max(1), directory.display());
+        self.sequence = Some(Sequence { directory, every: every.max(1), frames: 0, written: 0 });
+    }
+
+    // start_sequence into a new folder in SEQUENCE_DIR, or stop if it's already going
+    pub fn toggle_sequence(&mut self)
+    {
+        if self.is_recording()
+        {
+            self.stop_sequence();
+        }
+        else
+        {
+            self.start_sequence(Path::new(SEQUENCE_DIR).join(timestamp().to_string()), CAPTURE_EVERY);
+        }
+    }
+
+    pub fn stop_sequence(&mut self)
+    {
+        if let Some(sequence) = self.sequence.take()
+        {
+            log::info!("captured {} frames to {}", sequence.written, sequence.directory.display());
+        }
+    }
+
+    pub fn is_recording(&self) -> bool
+    {
+        self.sequence.is_some()
+    }
+
+    // where this frame should be saved, if anywhere
+    fn take_path(&mut self) -> Option<PathBuf>
+    {
+        if let Some(path) = self.screenshot.take()
+        {
+            return Some(path);
+        }
+        let sequence = self.sequence.as_mut()?;
+        let capture = sequence.frames % sequence.every as u64 == 0;
+        sequence.frames += 1;
+        if !capture
+        {
+            return None;
+        }
+        sequence.written += 1;
+        Some(sequence.directory.join(format!("frame-{:05}.png", sequence.written)))
+    }
+
+    // the view to draw this frame into: the surface's own, or the capture target's when the frame is being saved,
+    // then the FrameCapture goes to end_frame() before the encoder is finished
+    pub fn begin_frame(&mut self, device: &wgpu::Device, surface_view: wgpu::TextureView, size: (u32, u32), format: wgpu::TextureFormat) -> (wgpu::TextureView, Option<FrameCapture>)
+    {
+        if self.screenshot.is_none() && self.sequence.is_none()
+        {
+            self.target = None;
+            return (surface_view, None);
+        }
+        if !can_capture(format)
+        {
+            log::error!("can't capture {:?} textures", format);
+            self.screenshot = None;
+            self.sequence = None;
+            return (surface_view, None);
+        }
+        let path = match self.take_path()
+        {
+            Some(path) => path,
+            None => return (surface_view, None),
+        };
+
+        // made again when the window changes size or the surface changes format
+        let blit = match &mut self.blit
+        {
+            Some(blit) if blit.format == format => blit,
+            blit => blit.insert(Blit::new(device, format)),
+        };
+        let target = match &mut self.target
+        {
+            Some(target) if target.size == size && target.format == format => target,
+            target => target.insert(blit.create_target(device, size)),
+        };
+        let view = target.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        (view, Some(FrameCapture { path, surface_view }))
+    }
+
+    // copies the frame from the capture target into a buffer, then onto the surface
+    pub fn end_frame(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, frame: FrameCapture)
+    {
+        let (target, blit) = match (self.target.take(), &self.blit)
+        {
+            (Some(target), Some(blit)) => (target, blit),
+            _ => return,
+        };
+        {
+            let mut blit_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor
+            {
+                label: Some("Blit Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment
+                {
+                    view: &frame.surface_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: true },
+                }],
+                depth_stencil_attachment: None,
+            });
+            blit_pass.set_pipeline(&blit.pipeline);
+            blit_pass.set_bind_group(0, &target.bind_group, &[]);
+            blit_pass.draw(0..3, 0..1);
+        }
+        self.capture(device, encoder, &target.texture, target.size, target.format, frame.path);
+        self.target = Some(target);
+    }
+
+    // copies texture into a buffer, to be saved at path once the gpu is done with it
+    pub fn capture(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, texture: &wgpu::Texture, (width, height): (u32, u32), format: wgpu::TextureFormat, path: PathBuf)
+    {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor
+        {
+            label: Some("Capture Buffer"),
+            size: (padded_bytes_per_row(width) * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture
+            {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer
+            {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout
+                {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row(width)),
+                    rows_per_image: std::num::NonZeroU32::new(height),
+                },
+            },
+            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        );
+        self.readbacks.push(Readback { buffer, path, width, height, format, mapping: None });
+    }
+
+    // the frame's commands are on their way, its captures can be mapped once they're done
+    pub fn submitted(&mut self)
+    {
+        for readback in self.readbacks.iter_mut().filter(|readback| readback.mapping.is_none())
+        {
+            readback.mapping = Some(Box::pin(readback.buffer.slice(..).map_async(wgpu::MapMode::Read)));
+        }
+    }
+
+    // hands every capture the gpu has finished to the saver, wait -> blocks until they're all done
+    pub fn poll(&mut self, device: &wgpu::Device, wait: bool)
+    {
+        if self.readbacks.is_empty()
+        {
+            return;
+        }
+        device.poll(if wait { wgpu::Maintain::Wait } else { wgpu::Maintain::Poll });
+        let mut context = task::Context::from_waker(Waker::noop());
+        let mut still_waiting = Vec::new();
+        for mut readback in self.readbacks.drain(..)
+        {
+            let ready = match readback.mapping.as_mut().map(|mapping| mapping.as_mut().poll(&mut context))
+            {
+                Some(Poll::Ready(result)) => result,
+                _ =>
+                {
+                    still_waiting.push(readback);
+                    continue;
+                }
+            };
+            if let Err(e) = ready
+            {
+                log::error!("couldn't read back {}: {}", readback.path.display(), e);
+                continue;
+            }
+            let pixels =
+            {
+                let data = readback.buffer.slice(..).get_mapped_range();
+                unpad_rows(&data, readback.width, readback.height, readback.format)
+            };
+            readback.buffer.unmap();
+            if let Some((sender, _)) = &self.saver
+            {
+                let _ = sender.send(SaveJob { path: readback.path, width: readback.width, height: readback.height, pixels });
+            }
+        }
+        self.readbacks = still_waiting;
+    }
+
+    // the device the captures were being read back from is gone, and them with it
+    pub fn device_lost(&mut self)
+    {
+        self.target = None;
+        self.blit = None;
+        if !self.readbacks.is_empty()
+        {
+            log::warn!("lost {} captures with the device", self.readbacks.len());
+            self.readbacks.clear();
+        }
+    }
+
+    // saves everything that's been captured before the game exits
+    pub fn finish(&mut self, device: &wgpu::Device)
+    {
+        self.stop_sequence();
+        self.submitted();
+        self.poll(device, true);
+        if let Some((sender, saver)) = self.saver.take()
+        {
+            drop(sender);
+            let _ = saver.join();
+        }
+    }
+}
+
+// milliseconds since 1970, to keep file names apart
+fn timestamp() -> u128
+{
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|time| time.as_millis())
+        .unwrap_or(0)
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // rows of (row, column, 0, 255) pixels, with the padding filled in so it shows if it ends up in the image
+    fn padded_copy(width: u32, height: u32) -> Vec<u8>
+    {
+        let mut data = vec![0xee; (padded_bytes_per_row(width) * height) as usize];
+        for (y, row) in data.chunks_mut(padded_bytes_per_row(width) as usize).enumerate()
+        {
+            for x in 0..width as usize
+            {
+                row[x * 4..x * 4 + 4].copy_from_slice(&[y as u8, x as u8, 0, 255]);
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn rows_are_padded_to_the_copy_alignment()
+    {
+        assert_eq!(padded_bytes_per_row(1), 256);
+        assert_eq!(padded_bytes_per_row(64), 256);
+        assert_eq!(padded_bytes_per_row(65), 512);
+        assert_eq!(padded_bytes_per_row(100), 512);
+    }
+
+    #[test]
+    fn unpadding_drops_the_padding_and_swaps_bgra()
+    {
+        let (width, height) = (100, 3);
+        let data = padded_copy(width, height);
+        let expected: Vec<u8> = (0..height as u8)
+            .flat_map(|y| (0..width as u8).flat_map(move |x| [y, x, 0, 255]))
+            .collect();
+
+        assert_eq!(unpad_rows(&data, width, height, wgpu::TextureFormat::Rgba8UnormSrgb), expected);
+        let swapped: Vec<u8> = expected.chunks(4).flat_map(|pixel| [pixel[2], pixel[1], pixel[0], pixel[3]]).collect();
+        assert_eq!(unpad_rows(&data, width, height, wgpu::TextureFormat::Bgra8Unorm), swapped);
+    }
+}
@@ -0,0 +1,521 @@
+use std::
+{
+    collections::BTreeMap,
+    fmt,
+    path::Path,
+    str::FromStr,
+};
+
+use super::
+{
+    assets,
+    data_file::DataError,
+    ecs::World,
+    input,
+    simulation::Simulation,
+    timestep,
+};
+
+
+/*
+    The developer console: commands and console variables (cvars) that
+    subsystems register when the simulation starts (see add_commands in
+    'systems.rs'). One command per line, split on whitespace:
+
+        spawn cube 0 1 0
+        teleport 0 2 5
+        set camera.fovy 60
+        camera.fovy 60      <- the same thing
+        camera.fovy         <- prints it
+        help camera.fovy
+
+    The console is a resource in the world, and commands get the world to
+    change. In game it's typed into the overlay (the ` key opens it). It
+    also runs from a script file without a window, for automated tests:
+
+        cargo run -- --script assets/scripts/smoke.txt
+
+    Scripts can also tick the simulation, load levels and check cvars:
+
+        level scenes/main.ron
+        tick 60
+        expect camera.fovy 60
+
+    A script stops at the first line that fails. Commands change the world
+    from outside the recorded inputs, so a recording made while using them
+    won't replay the same (see 'replay.rs').
+*/
+
+// what a command prints, or why it failed
+pub type CommandResult = Result<String, String>;
+
+
+
+/*   <--------Console-------->   */
+type RunFn = dyn Fn(&mut World, &[&str]) -> CommandResult;
+type SetFn = dyn Fn(&mut World, &str) -> Result<(), String>;
+
+struct Command
+{
+    help: String,
+    run: Box<RunFn>,
+}
+
+struct Cvar
+{
+    help: String,
+    get: Box<dyn Fn(&mut World) -> CommandResult>,
+    set: Box<SetFn>,
+    // a value as the cvar would print it, so 60 and 60.0 compare the same
+    normalize: Box<dyn Fn(&str) -> CommandResult>,
+}
+
+#[derive(Default)]
+pub struct Console
+{
+    // sorted, so help and autocomplete list them in order
+    commands: BTreeMap<String, Command>,
+    cvars: BTreeMap<String, Cvar>,
+}
+
+impl Console
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    // run gets the words after the command's name
+    pub fn add_command(&mut self, name: &str, help: &str, run: impl Fn(&mut World, &[&str]) -> CommandResult + 'static) -> &mut Self
+    {
+        self.commands.insert(name.to_string(), Command { help: help.to_string(), run: Box::new(run) });
+        self
+    }
+
+    // a value somewhere in the world, access finds it (None -> it isn't there right now, like before a level loads)
+    pub fn add_cvar<T>(&mut self, name: &str, help: &str, access: fn(&mut World) -> Option<&mut T>) -> &mut Self
+    where
+        T: FromStr + fmt::Display + 'static,
+        T::Err: fmt::Display,
+    {
+        let missing = format!("{} isn't available right now", name);
+        let missing_too = missing.clone();
+        let get = move |world: &mut World| access(world).map(|value| value.to_string()).ok_or_else(|| missing.clone());
+        let set = move |world: &mut World, text: &str|
+        {
+            let parsed = parse::<T>(text)?;
+            *access(world).ok_or_else(|| missing_too.clone())? = parsed;
+            Ok(())
+        };
+        let normalize = |text: &str| parse::<T>(text).map(|value| value.to_string());
+        self.cvars.insert(name.to_string(), Cvar { help: help.to_string(), get: Box::new(get), set: Box::new(set), normalize: Box::new(normalize) });
+        self
+    }
+
+    pub fn execute(&self, world: &mut World, line: &str) -> CommandResult
+    {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (name, args) = match words.split_first()
+        {
+            Some((name, args)) => (*name, args),
+            None => return Ok(String::new()),
+        };
+        match name
+        {
+            "help" => self.help(args.first().copied()),
+            "set" => match args
+            {
+                [cvar, value] => self.set(world, cvar, value),
+                _ => Err(String::from("usage: set <cvar> <value>")),
+            },
+            _ if self.commands.contains_key(name) => (self.commands[name].run)(world, args),
+            _ if self.cvars.contains_key(name) => match args
+            {
+                [] => self.get(world, name),
+                [value] => self.set(world, name, value),
+                _ => Err(format!("usage: {} [value]", name)),
+            },
+            _ => Err(format!("unknown command {}, try help", name)),
+        }
+    }
+
+    pub fn get(&self, world: &mut World, name: &str) -> CommandResult
+    {
+        let cvar = self.cvars.get(name).ok_or_else(|| format!("no cvar called {}", name))?;
+        (cvar.get)(world)
+    }
+
+    pub fn set(&self, world: &mut World, name: &str, value: &str) -> CommandResult
+    {
+        let cvar = self.cvars.get(name).ok_or_else(|| format!("no cvar called {}", name))?;
+        (cvar.set)(world, value)?;
+        Ok(format!("{} = {}", name, (cvar.get)(world)?))
+    }
+
+    // Err with both values when the cvar isn't what was expected
+    pub fn expect(&self, world: &mut World, name: &str, expected: &str) -> Result<(), String>
+    {
+        let cvar = self.cvars.get(name).ok_or_else(|| format!("no cvar called {}", name))?;
+        let (actual, expected) = ((cvar.get)(world)?, (cvar.normalize)(expected)?);
+        if actual == expected
+        {
+            Ok(())
+        }
+        else
+        {
+            Err(format!("expected {} to be {}, it's {}", name, expected, actual))
+        }
+    }
+
+    // every command and cvar name, in order
+    pub fn names(&self) -> impl Iterator<Item = &str> + '_
+    {
+        ["help", "set"].into_iter()
+            .chain(self.commands.keys().map(String::as_str))
+            .chain(self.cvars.keys().map(String::as_str))
+    }
+
+    // the lines a partly typed line could become, the first word is any name and after set it's a cvar
+    pub fn complete(&self, line: &str) -> Vec<String>
+    {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let typing_new_word = line.is_empty() || line.ends_with(char::is_whitespace);
+        match (words.as_slice(), typing_new_word)
+        {
+            ([], _) => self.names().map(String::from).collect(),
+            ([first], false) => self.names().filter(|name| name.starts_with(first)).map(String::from).collect(),
+            (["set" | "help"], true) => self.cvar_names_after(words[0], ""),
+            (["set" | "help", partial], false) => self.cvar_names_after(words[0], partial),
+            _ => Vec::new(),
+        }
+    }
+
+    fn cvar_names_after(&self, command: &str, partial: &str) -> Vec<String>
+    {
+        let names: Vec<&str> = if command == "help" { self.names().collect() } else { self.cvars.keys().map(String::as_str).collect() };
+        names.into_iter()
+            .filter(|name| name.starts_with(partial))
+            .map(|name| format!("{} {}", command, name))
+            .collect()
+    }
+
+    fn help(&self, name: Option<&str>) -> CommandResult
+    {
+        let name = match name
+        {
+            Some(name) => name,
+            None => return Ok(format!("commands: help, set, {}\ncvars: {}",
+                self.commands.keys().cloned().collect::<Vec<_>>().join(", "),
+                self.cvars.keys().cloned().collect::<Vec<_>>().join(", "))),
+        };
+        match (name, self.commands.get(name), self.cvars.get(name))
+        {
+            ("help", _, _) => Ok(String::from("help [name]: what a command or cvar does")),
+            ("set", _, _) => Ok(String::from("set <cvar> <value>: changes a cvar")),
+            (_, Some(command), _) => Ok(format!("{}: {}", name, command.help)),
+            (_, _, Some(cvar)) => Ok(format!("{}: {}", name, cvar.help)),
+            _ => Err(format!("no command or cvar called {}", name)),
+        }
+    }
+}
+
+fn parse<T>(text: &str) -> Result<T, String>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    text.parse().map_err(|e| format!("{}: {}", text, e))
+}
+
+// the numbers after a command, like the position in 'teleport 0 2 5'
+pub fn parse_floats<const N: usize>(args: &[&str]) -> Result<[f32; N], String>
+{
+    if args.len() != N
+    {
+        return Err(format!("expected {} numbers, got {}", N, args.len()));
+    }
+    let mut floats = [0.0; N];
+    for (float, arg) in floats.iter_mut().zip(args)
+    {
+        *float = parse(arg)?;
+    }
+    Ok(floats)
+}
+
+
+/*   <--------Scripts-------->   */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScriptFailure
+{
+    // counting from 1, like an editor
+    pub line: usize,
+    pub command: String,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScriptReport
+{
+    pub commands: usize,
+    pub ticks: u64,
+    // the line the script stopped on
+    pub failure: Option<ScriptFailure>,
+}
+
+impl fmt::Display for ScriptReport
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match &self.failure
+        {
+            Some(failure) => write!(f, "script failed on line {} ({}): {}", failure.line, failure.command, failure.message),
+            None => write!(f, "script passed: {} commands, {} ticks", self.commands, self.ticks),
+        }
+    }
+}
+
+// runs a script without a window, starting on level (relative to asset_root),
+// blank lines and lines starting with # are skipped
+pub fn run_script(script: &str, asset_root: &Path, level: &str) -> Result<ScriptReport, DataError>
+{
+    let mut assets = assets::HeadlessAssets::new(asset_root);
+    let mut simulation = Simulation::new(input::Bindings::default());
+    simulation.load_level(&mut assets, level, 1.0)?;
+
+    let mut report = ScriptReport { commands: 0, ticks: 0, failure: None };
+    for (number, line) in script.lines().enumerate()
+    {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#')
+        {
+            continue;
+        }
+        report.commands += 1;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let result = match words.as_slice()
+        {
+            ["tick"] => tick(&mut simulation, &mut report, "1"),
+            ["tick", count] => tick(&mut simulation, &mut report, count),
+            ["level", level] =>
+            {
+                let aspect = simulation.world().resource::<super::camera::Camera>().aspect;
+                simulation.load_level(&mut assets, level, aspect).map_err(|e| e.to_string())
+            }
+            ["expect", cvar, value] => simulation.world_mut().resource_scope(|world, console: &mut Console| console.expect(world, cvar, value)),
+            ["tick" | "level" | "expect", ..] => Err(String::from("usage: tick [count], level <path>, expect <cvar> <value>")),
+            _ => simulation.run_command(line).map(|output| if !output.is_empty() { log::info!("{}", output) }),
+        };
+        if let Err(message) = result
+        {
+            report.failure = Some(ScriptFailure { line: number + 1, command: line.to_string(), message });
+            break;
+        }
+    }
+    Ok(report)
+}
+
+fn tick(simulation: &mut Simulation, report: &mut ScriptReport, count: &str) -> Result<(), String>
+{
+    let count: u64 = parse(count)?;
+    // as long as the game's fixed tick (see 'timestep.rs')
+    let tick = timestep::FixedTimestep::tick_for(timestep::TICK_RATE_HZ);
+    for _ in 0..count
+    {
+        simulation.update(tick);
+    }
+    report.ticks += count;
+    Ok(())
+}
+
+pub fn run_script_file(path: &Path, asset_root: &Path, level: &str) -> Result<ScriptReport, DataError>
+{
+    let script = std::fs::read_to_string(path).map_err(|e| DataError::new(path, "", e.to_string()))?;
+    run_script(&script, asset_root, level)
+}
+
+
+/*   <--------Overlay-------->   */
+// the console's window over the game, drawn with the ui (see 'ui.rs')
+#[derive(Default)]
+pub struct ConsoleOverlay
+{
+    open: bool,
+    input: String,
+    // what's been typed and what came back, oldest first
+    log: Vec<(String, egui::Color32)>,
+    history: Vec<String>,
+    // how far back up the history the arrow keys are
+    browsing: Option<usize>,
+}
+
+// lines kept in the log
+const MAX_LOG: usize = 200;
+
+impl ConsoleOverlay
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    pub fn is_open(&self) -> bool
+    {
+        self.open
+    }
+
+    pub fn toggle(&mut self)
+    {
+        self.open = !self.open;
+    }
+
+    fn print(&mut self, line: impl Into<String>, color: egui::Color32)
+    {
+        self.log.push((line.into(), color));
+        let extra = self.log.len().saturating_sub(MAX_LOG);
+        self.log.drain(..extra);
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context, simulation: &mut Simulation)
+    {
+        if !self.open
+        {
+            return;
+        }
+        egui::TopBottomPanel::top("console").show(ctx, |ui|
+        {
+            egui::ScrollArea::vertical().max_height(200.0).stick_to_bottom().show(ui, |ui|
+            {
+                ui.set_width(ui.available_width());
+                for (line, color) in &self.log
+                {
+                    ui.label(egui::RichText::new(line).monospace().color(*color));
+                }
+            });
+
+            let output = egui::TextEdit::singleline(&mut self.input)
+                .font(egui::TextStyle::Monospace)
+                .desired_width(f32::INFINITY)
+                // tab completes instead of moving on to the next widget
+                .lock_focus(true)
+                .hint_text("help for a list of commands")
+                .show(ui);
+            let response = output.response;
+            let (enter, tab, up, down) =
+            {
+                let input = ui.input();
+                (input.key_pressed(egui::Key::Enter), input.key_pressed(egui::Key::Tab), input.key_pressed(egui::Key::ArrowUp), input.key_pressed(egui::Key::ArrowDown))
+            };
+
+            let replaced = if response.lost_focus() && enter
+            {
+                self.submit(simulation);
+                true
+            }
+            else if response.has_focus() && tab
+            {
+                self.complete(simulation.console());
+                true
+            }
+            else if response.has_focus() && (up || down)
+            {
+                self.browse(up);
+                true
+            }
+            else
+            {
+                false
+            };
+
+            // typing into the console is the only reason to open it
+            response.request_focus();
+            if replaced
+            {
+                // the cursor goes after whatever was just put in
+                let mut state = output.state;
+                let end = egui::epaint::text::cursor::CCursor::new(self.input.chars().count());
+                state.set_ccursor_range(Some(egui::text_edit::CCursorRange::one(end)));
+                state.store(ctx, response.id);
+            }
+        });
+    }
+
+    fn submit(&mut self, simulation: &mut Simulation)
+    {
+        let line = std::mem::take(&mut self.input);
+        self.browsing = None;
+        if line.trim().is_empty()
+        {
+            return;
+        }
+        self.print(format!("> {}", line), egui::Color32::LIGHT_GRAY);
+        match simulation.run_command(&line)
+        {
+            Ok(output) => for output_line in output.lines()
+            {
+                self.print(output_line, egui::Color32::WHITE);
+            },
+            Err(message) => self.print(message, egui::Color32::LIGHT_RED),
+        }
+        if self.history.last() != Some(&line)
+        {
+            self.history.push(line);
+        }
+    }
+
+    // one match fills it in, more than one fills in what they share and lists them
+    fn complete(&mut self, console: &Console)
+    {
+        let matches = console.complete(&self.input);
+        match matches.as_slice()
+        {
+            [] => {}
+            [only] => self.input = format!("{} ", only),
+            [first, rest @ ..] =>
+            {
+                let shared = rest.iter().fold(first.as_str(), |shared, other|
+                {
+                    let length = shared.chars().zip(other.chars()).take_while(|(a, b)| a == b).map(|(a, _)| a.len_utf8()).sum();
+                    &shared[..length]
+                });
+                if shared.len() > self.input.len()
+                {
+                    self.input = shared.to_string();
+                }
+                self.print(matches.join("  "), egui::Color32::GRAY);
+            }
+        }
+    }
+
+    fn browse(&mut self, up: bool)
+    {
+        if self.history.is_empty()
+        {
+            return;
+        }
+        self.browsing = match (self.browsing, up)
+        {
+            (None, true) => Some(self.history.len() - 1),
+            (None, false) => None,
+            (Some(i), true) => Some(i.saturating_sub(1)),
+            (Some(i), false) if i + 1 < self.history.len() => Some(i + 1),
+            (Some(_), false) => None,
+        };
+        self.input = self.browsing.map(|i| self.history[i].clone()).unwrap_or_default();
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn smoke_script_passes()
+    {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let report = run_script_file(&root.join("assets/scripts/smoke.txt"), &root.join("assets"), "scenes/main.ron").unwrap();
+        assert!(report.failure.is_none(), "{}", report);
+        assert_eq!(report.ticks, 60);
+    }
+}
@@ -0,0 +1,163 @@
+use std::
+{
+    fmt,
+    path::{ Path, PathBuf },
+};
+use serde::{ de::DeserializeOwned, Serialize };
+
+
+/*
+    Loading and saving game data (scenes, settings, ...) as RON or JSON,
+    picked by the file's extension. When something in the file is wrong
+    the error says which field it was, e.g.
+
+        assets/scenes/main.ron:21:62: entities[1].transform.scale: Expected opening `(`
+*/
+
+#[derive(Debug)]
+pub struct DataError
+{
+    pub path: PathBuf,
+    // path to the bad value inside the file, like "entities[1].parent" (empty -> the whole file)
+    pub field: String,
+    // (line, column) when the parser knows it
+    pub location: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl DataError
+{
+    pub fn new(path: &Path, field: impl Into<String>, message: impl Into<String>) -> Self
+    {
+        Self
+        {
+            path: path.to_path_buf(),
+            field: field.into(),
+            location: None,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for DataError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "{}", self.path.display())?;
+        if let Some((line, column)) = self.location
+        {
+            write!(f, ":{}:{}", line, column)?;
+        }
+        if !self.field.is_empty()
+        {
+            write!(f, ": {}", self.field)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl std::error::Error for DataError {}
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Format
+{
+    Ron,
+    Json,
+}
+
+fn format_of(path: &Path) -> Result<Format, DataError>
+{
+    match path.extension().and_then(|extension| extension.to_str())
+    {
+        Some("ron") => Ok(Format::Ron),
+        Some("json") => Ok(Format::Json),
+        _ => Err(DataError::new(path, "", "unknown file type, expected .ron or .json")),
+    }
+}
+
+// serde_path_to_error writes paths like "entities[1].parent", or "." for the top level
+fn field_name<E>(error: &serde_path_to_error::Error<E>) -> String
+{
+    let field = error.path().to_string();
+    if field == "." { String::new() } else { field }
+}
+
+pub fn from_str<T: DeserializeOwned>(text: &str, path: &Path) -> Result<T, DataError>
+{
+    match format_of(path)?
+    {
+        Format::Ron =>
+        {
+            let mut deserializer = ron::Deserializer::from_str(text).map_err(|e| DataError
+            {
+                location: Some((e.position.line, e.position.col)),
+                ..DataError::new(path, "", e.code.to_string())
+            })?;
+            let value = serde_path_to_error::deserialize(&mut deserializer).map_err(|e|
+            {
+                let field = field_name(&e);
+                let spanned = deserializer.span_error(e.into_inner());
+                DataError
+                {
+                    location: Some((spanned.position.line, spanned.position.col)),
+                    ..DataError::new(path, field, spanned.code.to_string())
+                }
+            })?;
+            // anything after the value is a mistake too
+            deserializer.end().map_err(|e|
+            {
+                let spanned = deserializer.span_error(e);
+                DataError
+                {
+                    location: Some((spanned.position.line, spanned.position.col)),
+                    ..DataError::new(path, "", spanned.code.to_string())
+                }
+            })?;
+            Ok(value)
+        }
+        Format::Json =>
+        {
+            let mut deserializer = serde_json::Deserializer::from_str(text);
+            // serde_json's messages already end with "at line x column y"
+            let value = serde_path_to_error::deserialize(&mut deserializer).map_err(|e|
+            {
+                let field = field_name(&e);
+                DataError::new(path, field, e.into_inner().to_string())
+            })?;
+            deserializer.end().map_err(|e| DataError::new(path, "", e.to_string()))?;
+            Ok(value)
+        }
+    }
+}
+
+pub fn to_string<T: Serialize>(value: &T, path: &Path) -> Result<String, DataError>
+{
+    match format_of(path)?
+    {
+        Format::Ron => ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())
+            .map_err(|e| DataError::new(path, "", e.to_string())),
+        Format::Json => serde_json::to_string_pretty(value)
+            .map_err(|e| DataError::new(path, "", e.to_string())),
+    }
+}
+
+pub fn load<T: DeserializeOwned>(path: &Path) -> Result<T, DataError>
+{
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| DataError::new(path, "", format!("could not read file: {}", e)))?;
+    from_str(&text, path)
+}
+
+pub fn save<T: Serialize>(value: &T, path: &Path) -> Result<(), DataError>
+{
+    let text = to_string(value, path)?;
+    // like the player's config folder the first time anything is saved to it (see 'paths.rs')
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty())
+    {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| DataError::new(path, "", format!("could not make the folder: {}", e)))?;
+    }
+    std::fs::write(path, text)
+        .map_err(|e| DataError::new(path, "", format!("could not write file: {}", e)))
+}
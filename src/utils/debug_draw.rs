@@ -0,0 +1,394 @@
+use cgmath::
+{
+    InnerSpace,
+    SquareMatrix,
+};
+
+use super::
+{
+    bounds,
+    texture,
+};
+
+
+/*
+    Immediate mode debug drawing: lines, boxes, spheres, arrows, grids...
+    for seeing paths, colliders and frusta while working on the game.
+
+    DebugDraw is a resource in the world so systems can draw from anywhere,
+    render code gets at it through State::debug_draw(). Everything is
+    batched into one line list and drawn over the world after the main pass.
+
+    Lines drawn during a simulation tick stay up until the next tick (there
+    isn't always a tick every frame), lines drawn while a frame is being
+    drawn only last for that frame. It's all off until toggled (F3 by default).
+*/
+
+pub type Color = [f32; 3];
+
+pub const RED: Color = [1.0, 0.2, 0.2];
+pub const GREEN: Color = [0.2, 1.0, 0.2];
+pub const BLUE: Color = [0.3, 0.4, 1.0];
+pub const YELLOW: Color = [1.0, 0.9, 0.2];
+pub const CYAN: Color = [0.2, 0.9, 1.0];
+pub const WHITE: Color = [1.0, 1.0, 1.0];
+pub const GREY: Color = [0.4, 0.4, 0.4];
+
+// segments in a circle of a sphere
+const CIRCLE_SEGMENTS: usize = 24;
+
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LineVertex
+{
+    position: [f32; 3],
+    color: [f32; 3],
+}
+
+impl LineVertex
+{
+    const ATTRIBUTES: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3];
+
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a>
+    {
+        wgpu::VertexBufferLayout
+        {
+            array_stride: std::mem::size_of::<LineVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+
+/*   <--------Debug Draw-------->   */
+#[derive(Default)]
+pub struct DebugDraw
+{
+    enabled: bool,
+    // two vertices per line
+    vertices: Vec<LineVertex>,
+    // how many of the vertices were drawn during the last tick, the rest are this frame's
+    tick_vertices: usize,
+}
+
+impl DebugDraw
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    pub fn is_enabled(&self) -> bool
+    {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool)
+    {
+        self.enabled = enabled;
+        if !enabled
+        {
+            self.vertices.clear();
+            self.tick_vertices = 0;
+        }
+    }
+
+    pub fn toggle(&mut self)
+    {
+        self.set_enabled(!self.enabled);
+    }
+
+    pub fn vertices(&self) -> &[LineVertex]
+    {
+        &self.vertices
+    }
+
+    // the Simulation calls these around every tick
+    pub fn begin_tick(&mut self)
+    {
+        self.vertices.clear();
+    }
+
+    pub fn end_tick(&mut self)
+    {
+        self.tick_vertices = self.vertices.len();
+    }
+
+    // State calls this once a frame is drawn, throws away that frame's lines
+    pub fn end_frame(&mut self)
+    {
+        self.vertices.truncate(self.tick_vertices);
+    }
+
+    pub fn line(&mut self, from: cgmath::Point3<f32>, to: cgmath::Point3<f32>, color: Color)
+    {
+        if self.enabled
+        {
+            self.vertices.push(LineVertex { position: from.into(), color });
+            self.vertices.push(LineVertex { position: to.into(), color });
+        }
+    }
+
+    // lines between each point and the next, closed -> back to the first one at the end
+    pub fn polyline(&mut self, points: &[cgmath::Point3<f32>], closed: bool, color: Color)
+    {
+        for pair in points.windows(2)
+        {
+            self.line(pair[0], pair[1], color);
+        }
+        if closed && points.len() > 2
+        {
+            self.line(points[points.len() - 1], points[0], color);
+        }
+    }
+
+    pub fn aabb(&mut self, aabb: &bounds::Aabb, color: Color)
+    {
+        self.box_corners(aabb.corners(), color);
+    }
+
+    // a box in a mesh's own space, drawn where a matrix puts it (so it turns with the mesh)
+    pub fn oriented_box(&mut self, aabb: &bounds::Aabb, matrix: &cgmath::Matrix4<f32>, color: Color)
+    {
+        use cgmath::Transform;
+        self.box_corners(aabb.corners().map(|corner| matrix.transform_point(corner)), color);
+    }
+
+    // corners in the order Aabb::corners() gives them (x changes fastest, then y, then z)
+    fn box_corners(&mut self, corners: [cgmath::Point3<f32>; 8], color: Color)
+    {
+        const EDGES: [(usize, usize); 12] =
+        [
+            (0, 1), (2, 3), (4, 5), (6, 7),     // along x
+            (0, 2), (1, 3), (4, 6), (5, 7),     // along y
+            (0, 4), (1, 5), (2, 6), (3, 7),     // along z
+        ];
+        for (a, b) in EDGES
+        {
+            self.line(corners[a], corners[b], color);
+        }
+    }
+
+    // three circles, one around each axis
+    pub fn sphere(&mut self, center: cgmath::Point3<f32>, radius: f32, color: Color)
+    {
+        let x = cgmath::Vector3::unit_x() * radius;
+        let y = cgmath::Vector3::unit_y() * radius;
+        let z = cgmath::Vector3::unit_z() * radius;
+        self.circle(center, x, y, color);
+        self.circle(center, y, z, color);
+        self.circle(center, z, x, color);
+    }
+
+    // a circle in the plane of u and v, which are as long as the radius
+    fn circle(&mut self, center: cgmath::Point3<f32>, u: cgmath::Vector3<f32>, v: cgmath::Vector3<f32>, color: Color)
+    {
+        let point = |i: usize|
+        {
+            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+            center + u * angle.cos() + v * angle.sin()
+        };
+        for i in 0..CIRCLE_SEGMENTS
+        {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    // a line with a little pyramid for a head at the 'to' end
+    pub fn arrow(&mut self, from: cgmath::Point3<f32>, to: cgmath::Point3<f32>, color: Color)
+    {
+        self.line(from, to, color);
+        let direction = to - from;
+        let length = direction.magnitude();
+        if length <= f32::EPSILON
+        {
+            return;
+        }
+        let direction = direction / length;
+        // anything not parallel to the arrow will do to get the sides of the head
+        let other = if direction.y.abs() < 0.9 { cgmath::Vector3::unit_y() } else { cgmath::Vector3::unit_x() };
+        let side = direction.cross(other).normalize();
+        let up = side.cross(direction);
+        let head = length.min(1.0) * 0.2;
+        let base = to - direction * head;
+        for offset in [side, -side, up, -up]
+        {
+            self.line(to, base + offset * head * 0.5, color);
+        }
+    }
+
+    // a square grid on the xz plane around center, half_size out to each side
+    pub fn grid(&mut self, center: cgmath::Point3<f32>, half_size: f32, spacing: f32, color: Color)
+    {
+        if spacing <= 0.0
+        {
+            return;
+        }
+        let lines = (half_size / spacing).floor() as i32;
+        for i in -lines..=lines
+        {
+            let offset = i as f32 * spacing;
+            self.line(center + cgmath::Vector3::new(offset, 0.0, -half_size), center + cgmath::Vector3::new(offset, 0.0, half_size), color);
+            self.line(center + cgmath::Vector3::new(-half_size, 0.0, offset), center + cgmath::Vector3::new(half_size, 0.0, offset), color);
+        }
+    }
+
+    // the x (red), y (green) and z (blue) axes of a matrix, like a node's world matrix
+    pub fn axes(&mut self, matrix: &cgmath::Matrix4<f32>, length: f32)
+    {
+        let origin = cgmath::Point3::new(matrix.w.x, matrix.w.y, matrix.w.z);
+        self.arrow(origin, origin + matrix.x.truncate() * length, RED);
+        self.arrow(origin, origin + matrix.y.truncate() * length, GREEN);
+        self.arrow(origin, origin + matrix.z.truncate() * length, BLUE);
+    }
+
+    // the volume a camera sees, from its view projection matrix (see camera::Camera::build_view_projection_matrix)
+    pub fn frustum(&mut self, view_projection: &cgmath::Matrix4<f32>, color: Color)
+    {
+        let inverse = match view_projection.invert()
+        {
+            Some(inverse) => inverse,
+            None => return,
+        };
+        // the corners of wgpu's clip space box, in the same order as Aabb::corners()
+        let ndc = bounds::Aabb { min: cgmath::Point3::new(-1.0, -1.0, 0.0), max: cgmath::Point3::new(1.0, 1.0, 1.0) };
+        let corners = ndc.corners().map(|corner|
+        {
+            let point = inverse * corner.to_homogeneous();
+            cgmath::Point3::new(point.x / point.w, point.y / point.w, point.z / point.w)
+        });
+        self.box_corners(corners, color);
+    }
+}
+
+
+/*   <--------Debug Renderer-------->   */
+// the gpu side, draws whatever is in DebugDraw over the world
+pub struct DebugRenderer
+{
+    pipeline: wgpu::RenderPipeline,
+    camera_bind_group: wgpu::BindGroup,
+    vertex_buffer: wgpu::Buffer,
+    // in vertices
+    capacity: usize,
+}
+
+impl DebugRenderer
+{
+    // sample_count has to match the world's, the lines are drawn into the same target
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, sample_count: u32, camera_buffer: &wgpu::Buffer) -> Self
+    {
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor
+        {
+            label: Some("Debug Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/debug.wgsl").into()),
+        });
+
+        // no layout, wgpu works it out from the shader (just the camera)
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor
+        {
+            label: Some("Debug Pipeline"),
+            layout: None,
+            vertex: wgpu::VertexState
+            {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[LineVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState
+            {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState
+                {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState
+            {
+                topology: wgpu::PrimitiveTopology::LineList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            // hidden behind the world like everything else, but lines don't hide each other
+            depth_stencil: Some(wgpu::DepthStencilState
+            {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState { count: sample_count, ..Default::default() },
+            multiview: None,
+        });
+
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor
+        {
+            label: Some("debug_camera_bind_group"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: camera_buffer.as_entire_binding() }],
+        });
+
+        let capacity = 1024;
+        Self
+        {
+            pipeline,
+            camera_bind_group,
+            vertex_buffer: Self::create_vertex_buffer(device, capacity),
+            capacity,
+        }
+    }
+
+    fn create_vertex_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer
+    {
+        device.create_buffer(&wgpu::BufferDescriptor
+        {
+            label: Some("Debug Vertex Buffer"),
+            size: (capacity * std::mem::size_of::<LineVertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    // draws the lines on top of what's in view, color and depth are where the world was drawn
+    pub fn draw(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, color: wgpu::RenderPassColorAttachment, depth: &wgpu::TextureView, lines: &DebugDraw)
+    {
+        let vertices = lines.vertices();
+        if vertices.is_empty()
+        {
+            return;
+        }
+        // the buffer grows with the number of lines, same as the instance buffer
+        if vertices.len() > self.capacity
+        {
+            self.capacity = vertices.len().next_power_of_two();
+            self.vertex_buffer = Self::create_vertex_buffer(device, self.capacity);
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(vertices));
+
+        let mut debug_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor
+        {
+            label: Some("Debug Pass"),
+            color_attachments: &[color],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment
+            {
+                view: depth,
+                depth_ops: Some(wgpu::Operations { load: wgpu::LoadOp::Load, store: false }),
+                stencil_ops: None,
+            }),
+        });
+        debug_pass.set_pipeline(&self.pipeline);
+        debug_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        debug_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        debug_pass.draw(0..vertices.len() as u32, 0..1);
+    }
+}
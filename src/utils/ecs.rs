@@ -0,0 +1,422 @@
+use std::
+{
+    any::{ Any, TypeId },
+    collections::HashMap,
+};
+use winit::event::WindowEvent;
+
+
+/*
+    A small entity-component-system.
+
+    Entities are just ids. Components are plain structs stored per type,
+    indexed by the entity's index. Resources are singletons that belong
+    to the world instead of an entity (the camera, the scene graph, ...).
+    Systems are functions over the world, run in order by a Schedule.
+    Window events go to event handlers as they arrive, everything else
+    happens once per update in the stages below.
+*/
+
+// the generation makes ids of despawned entities stop working when the index is reused
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Entity
+{
+    index: u32,
+    generation: u32,
+}
+
+impl Entity
+{
+    pub fn index(&self) -> usize
+    {
+        self.index as usize
+    }
+}
+
+
+/*   <--------Component Storage-------->   */
+// one Vec per component type, slot i belongs to the entity with index i
+pub struct ComponentStorage<T>
+{
+    components: Vec<Option<(u32, T)>>,     // (generation of the owner, component)
+}
+
+impl<T> Default for ComponentStorage<T>
+{
+    fn default() -> Self
+    {
+        Self { components: Vec::new() }
+    }
+}
+
+impl<T> ComponentStorage<T>
+{
+    fn insert(&mut self, entity: Entity, component: T) -> Option<T>
+    {
+        let index = entity.index();
+        if index >= self.components.len()
+        {
+            self.components.resize_with(index + 1, || None);
+        }
+        self.components[index].replace((entity.generation, component))
+            .filter(|(generation, _)| *generation == entity.generation)
+            .map(|(_, old)| old)
+    }
+
+    fn remove(&mut self, entity: Entity) -> Option<T>
+    {
+        let slot = self.components.get_mut(entity.index())?;
+        match slot
+        {
+            Some((generation, _)) if *generation == entity.generation => slot.take().map(|(_, component)| component),
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T>
+    {
+        match self.components.get(entity.index())?
+        {
+            Some((generation, component)) if *generation == entity.generation => Some(component),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T>
+    {
+        match self.components.get_mut(entity.index())?
+        {
+            Some((generation, component)) if *generation == entity.generation => Some(component),
+            _ => None,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)>
+    {
+        self.components.iter().enumerate().filter_map(|(index, slot)|
+        {
+            slot.as_ref().map(|(generation, component)| (Entity { index: index as u32, generation: *generation }, component))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)>
+    {
+        self.components.iter_mut().enumerate().filter_map(|(index, slot)|
+        {
+            slot.as_mut().map(|(generation, component)| (Entity { index: index as u32, generation: *generation }, component))
+        })
+    }
+}
+
+// lets the world hold storages of every type in one map and still remove
+// an entity's components without knowing their types
+trait AnyStorage
+{
+    fn remove_entity(&mut self, entity: Entity);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyStorage for ComponentStorage<T>
+{
+    fn remove_entity(&mut self, entity: Entity)
+    {
+        self.remove(entity);
+    }
+
+    fn as_any(&self) -> &dyn Any
+    {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any
+    {
+        self
+    }
+}
+
+
+/*   <--------World-------->   */
+#[derive(Default)]
+pub struct World
+{
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free: Vec<u32>,
+    storages: HashMap<TypeId, Box<dyn AnyStorage>>,
+    resources: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl World
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    // <----- Entities ----->
+    pub fn spawn(&mut self) -> Entity
+    {
+        match self.free.pop()
+        {
+            Some(index) =>
+            {
+                let i = index as usize;
+                self.generations[i] += 1;
+                self.alive[i] = true;
+                Entity { index, generation: self.generations[i] }
+            }
+            None =>
+            {
+                self.generations.push(0);
+                self.alive.push(true);
+                Entity { index: (self.generations.len() - 1) as u32, generation: 0 }
+            }
+        }
+    }
+
+    // removes the entity and all of its components
+    pub fn despawn(&mut self, entity: Entity)
+    {
+        if !self.is_alive(entity)
+        {
+            return;
+        }
+        for storage in self.storages.values_mut()
+        {
+            storage.remove_entity(entity);
+        }
+        self.alive[entity.index()] = false;
+        self.free.push(entity.index);
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool
+    {
+        self.generations.get(entity.index()) == Some(&entity.generation) && self.alive[entity.index()]
+    }
+
+    pub fn entities(&self) -> impl Iterator<Item = Entity> + '_
+    {
+        self.alive.iter().enumerate()
+            .filter(|(_, alive)| **alive)
+            .map(|(index, _)| Entity { index: index as u32, generation: self.generations[index] })
+    }
+
+    // <----- Components ----->
+    pub fn storage<T: 'static>(&self) -> Option<&ComponentStorage<T>>
+    {
+        self.storages.get(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any().downcast_ref())
+    }
+
+    pub fn storage_mut<T: 'static>(&mut self) -> &mut ComponentStorage<T>
+    {
+        self.storages.entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(ComponentStorage::<T>::default()))
+            .as_any_mut()
+            .downcast_mut()
+            .expect("storage is keyed by its component's TypeId")
+    }
+
+    // adds (or replaces) a component, returns the old one
+    pub fn insert<T: 'static>(&mut self, entity: Entity, component: T) -> Option<T>
+    {
+        if !self.is_alive(entity)
+        {
+            log::warn!("inserting a {} into dead entity {:?}", std::any::type_name::<T>(), entity);
+            return None;
+        }
+        self.storage_mut::<T>().insert(entity, component)
+    }
+
+    pub fn remove<T: 'static>(&mut self, entity: Entity) -> Option<T>
+    {
+        self.storage_mut::<T>().remove(entity)
+    }
+
+    pub fn get<T: 'static>(&self, entity: Entity) -> Option<&T>
+    {
+        self.storage::<T>()?.get(entity)
+    }
+
+    pub fn get_mut<T: 'static>(&mut self, entity: Entity) -> Option<&mut T>
+    {
+        self.storage_mut::<T>().get_mut(entity)
+    }
+
+    // <----- Queries ----->
+    // every entity with a T
+    pub fn query<T: 'static>(&self) -> impl Iterator<Item = (Entity, &T)>
+    {
+        self.storage::<T>().into_iter().flat_map(|storage| storage.iter())
+    }
+
+    pub fn query_mut<T: 'static>(&mut self) -> impl Iterator<Item = (Entity, &mut T)>
+    {
+        self.storage_mut::<T>().iter_mut()
+    }
+
+    // every entity with both an A and a B
+    pub fn query2<A: 'static, B: 'static>(&self) -> impl Iterator<Item = (Entity, &A, &B)>
+    {
+        let b = self.storage::<B>();
+        self.query::<A>().filter_map(move |(entity, a)| b?.get(entity).map(|b| (entity, a, b)))
+    }
+
+    // runs f for every entity with both an A and a B, with mutable access to both
+    pub fn for_each2_mut<A: 'static, B: 'static>(&mut self, mut f: impl FnMut(Entity, &mut A, &mut B))
+    {
+        assert_ne!(TypeId::of::<A>(), TypeId::of::<B>(), "for_each2_mut needs two different component types");
+
+        // take A's storage out of the map so both storages can be borrowed mutably
+        let mut a = match self.storages.remove(&TypeId::of::<A>())
+        {
+            Some(a) => a,
+            None => return,
+        };
+        {
+            let a = a.as_any_mut().downcast_mut::<ComponentStorage<A>>().expect("storage is keyed by its component's TypeId");
+            let b = self.storage_mut::<B>();
+            for (entity, a) in a.iter_mut()
+            {
+                if let Some(b) = b.get_mut(entity)
+                {
+                    f(entity, a, b);
+                }
+            }
+        }
+        self.storages.insert(TypeId::of::<A>(), a);
+    }
+
+    // <----- Resources ----->
+    pub fn insert_resource<R: 'static>(&mut self, resource: R)
+    {
+        self.resources.insert(TypeId::of::<R>(), Box::new(resource));
+    }
+
+    pub fn remove_resource<R: 'static>(&mut self) -> Option<R>
+    {
+        self.resources.remove(&TypeId::of::<R>())
+            .and_then(|resource| resource.downcast().ok())
+            .map(|resource| *resource)
+    }
+
+    pub fn has_resource<R: 'static>(&self) -> bool
+    {
+        self.resources.contains_key(&TypeId::of::<R>())
+    }
+
+    pub fn get_resource<R: 'static>(&self) -> Option<&R>
+    {
+        self.resources.get(&TypeId::of::<R>()).and_then(|resource| resource.downcast_ref())
+    }
+
+    pub fn get_resource_mut<R: 'static>(&mut self) -> Option<&mut R>
+    {
+        self.resources.get_mut(&TypeId::of::<R>()).and_then(|resource| resource.downcast_mut())
+    }
+
+    // for resources every system expects to be there, panics with the type's name if it isn't
+    pub fn resource<R: 'static>(&self) -> &R
+    {
+        self.get_resource().unwrap_or_else(|| panic!("missing resource {}", std::any::type_name::<R>()))
+    }
+
+    pub fn resource_mut<R: 'static>(&mut self) -> &mut R
+    {
+        self.get_resource_mut().unwrap_or_else(|| panic!("missing resource {}", std::any::type_name::<R>()))
+    }
+
+    // takes a resource out of the world for the length of f, so f can use it
+    // and the rest of the world at the same time
+    pub fn resource_scope<R: 'static, T>(&mut self, f: impl FnOnce(&mut World, &mut R) -> T) -> T
+    {
+        let mut resource = self.remove_resource::<R>()
+            .unwrap_or_else(|| panic!("missing resource {}", std::any::type_name::<R>()));
+        let result = f(self, &mut resource);
+        self.insert_resource(resource);
+        result
+    }
+}
+
+
+/*   <--------Schedule-------->   */
+// systems run stage by stage in this order
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage
+{
+    // turns the input gathered by the event handlers into movement, actions, ...
+    Input,
+    Ai,
+    Physics,
+    TransformPropagation,
+    RenderExtraction,
+}
+
+impl Stage
+{
+    pub const ALL: [Stage; 5] = [Stage::Input, Stage::Ai, Stage::Physics, Stage::TransformPropagation, Stage::RenderExtraction];
+}
+
+pub type System = Box<dyn FnMut(&mut World)>;
+// returns true if it used up the event, later handlers (and main.rs) won't see it
+pub type EventHandler = Box<dyn FnMut(&mut World, &WindowEvent) -> bool>;
+
+#[derive(Default)]
+pub struct Schedule
+{
+    event_handlers: Vec<(&'static str, EventHandler)>,
+    // systems inside a stage run in the order they were added
+    stages: HashMap<Stage, Vec<(&'static str, System)>>,
+}
+
+impl Schedule
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    pub fn add_system(&mut self, stage: Stage, name: &'static str, system: impl FnMut(&mut World) + 'static) -> &mut Self
+    {
+        self.stages.entry(stage).or_default().push((name, Box::new(system)));
+        self
+    }
+
+    pub fn add_event_handler(&mut self, name: &'static str, handler: impl FnMut(&mut World, &WindowEvent) -> bool + 'static) -> &mut Self
+    {
+        self.event_handlers.push((name, Box::new(handler)));
+        self
+    }
+
+    pub fn system_names(&self, stage: Stage) -> impl Iterator<Item = &'static str> + '_
+    {
+        self.stages.get(&stage).into_iter().flatten().map(|(name, _)| *name)
+    }
+
+    pub fn run_stage(&mut self, stage: Stage, world: &mut World)
+    {
+        if let Some(systems) = self.stages.get_mut(&stage)
+        {
+            for (_, system) in systems
+            {
+                system(world);
+            }
+        }
+    }
+
+    // gives the event to each handler in turn until one of them uses it up
+    pub fn handle_event(&mut self, world: &mut World, event: &WindowEvent) -> bool
+    {
+        self.event_handlers.iter_mut().any(|(_, handler)| handler(world, event))
+    }
+
+    // one update, every stage in order
+    pub fn run_update(&mut self, world: &mut World)
+    {
+        for stage in Stage::ALL
+        {
+            self.run_stage(stage, world);
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use std::time::Duration;
+use winit::event::{ ElementState, KeyboardInput, VirtualKeyCode, WindowEvent };
+
+use super::
+{
+    state::{ Frame, State },
+};
+
+
+/*
+    A stack of game states: menus, gameplay, the pause screen, ...
+    (the states themselves are in 'screens.rs').
+
+    Only the state on top gets input and updates, so pushing the pause
+    screen on top of the game freezes it. Gamepads are polled every tick
+    whichever state is on top, so a pad works in the menus and can
+    unpause a game that isn't ticking. Rendering goes from the top down
+    through overlays, so the frozen game still shows under the pause screen.
+
+    States change the stack by returning a Transition from their hooks.
+*/
+
+pub enum Transition
+{
+    None,
+    // put a state on top of this one
+    Push(Box<dyn GameState>),
+    // go back to the state underneath
+    Pop,
+    // replace this state
+    Switch(Box<dyn GameState>),
+    // throw the whole stack away and start over from this state
+    Reset(Box<dyn GameState>),
+    Quit,
+}
+
+pub trait GameState
+{
+    fn name(&self) -> &'static str;
+
+    // window title while this state is on top
+    fn title(&self, _engine: &State) -> String
+    {
+        String::from("With Me")
+    }
+
+    // true -> drawn over the state underneath, false -> covers it completely
+    fn is_overlay(&self) -> bool
+    {
+        false
+    }
+
+    fn on_enter(&mut self, _engine: &mut State) -> Transition
+    {
+        Transition::None
+    }
+
+    fn on_exit(&mut self, _engine: &mut State) {}
+
+    // another state went on top of this one
+    fn on_cover(&mut self, _engine: &mut State) {}
+
+    // this state is on top again
+    fn on_uncover(&mut self, _engine: &mut State) {}
+
+    fn input(&mut self, _engine: &mut State, _event: &WindowEvent) -> Transition
+    {
+        Transition::None
+    }
+
+    // after every window or gamepad event has gone into the world's Input resource,
+    // Input::triggered() says whether it pressed an action (see 'input.rs')
+    fn actions(&mut self, _engine: &mut State) -> Transition
+    {
+        Transition::None
+    }
+
+    // once per fixed simulation tick (see 'timestep.rs')
+    fn update(&mut self, _engine: &mut State, _tick: Duration) -> Transition
+    {
+        Transition::None
+    }
+
+    // alpha is how far between the last two ticks to draw,
+    // states that aren't on top are frozen and always get 1.0
+    fn render(&mut self, engine: &mut State, frame: &mut Frame, alpha: f32);
+}
+
+// the key that was just pressed, if the event is a key press
+pub fn pressed_key(event: &WindowEvent) -> Option<VirtualKeyCode>
+{
+    match event
+    {
+        WindowEvent::KeyboardInput
+        {
+            input: KeyboardInput
+            {
+                state: ElementState::Pressed,
+                virtual_keycode: Some(keycode),
+                ..
+            },
+            ..
+        } => Some(*keycode),
+        _ => None,
+    }
+}
+
+
+/*   <--------Stack-------->   */
+pub struct GameStateStack
+{
+    states: Vec<Box<dyn GameState>>,
+}
+
+impl GameStateStack
+{
+    pub fn new(engine: &mut State, first: Box<dyn GameState>) -> Self
+    {
+        let mut stack = Self { states: Vec::new() };
+        stack.apply(engine, Transition::Push(first));
+        stack
+    }
+
+    // empty after a Quit
+    pub fn is_empty(&self) -> bool
+    {
+        self.states.is_empty()
+    }
+
+    // bottom to top
+    pub fn names(&self) -> Vec<&'static str>
+    {
+        self.states.iter().map(|state| state.name()).collect()
+    }
+
+    pub fn title(&self, engine: &State) -> String
+    {
+        self.states.last().map(|state| state.title(engine)).unwrap_or_default()
+    }
+
+    pub fn input(&mut self, engine: &mut State, event: &WindowEvent)
+    {
+        // the ui hears first, a click on a button shouldn't also turn the camera or pick something (see 'ui.rs')
+        if engine.ui_input(event)
+        {
+            return;
+        }
+        // the world's input state hears about everything, so keys held through a pause are still held after
+        engine.input(event);
+        if let Some(top) = self.states.last_mut()
+        {
+            let transition = match top.input(engine, event)
+            {
+                Transition::None => top.actions(engine),
+                transition => transition,
+            };
+            self.apply(engine, transition);
+        }
+    }
+
+    pub fn update(&mut self, engine: &mut State, tick: Duration)
+    {
+        // one event at a time, so a press can't get lost behind the next event in the same poll
+        for event in engine.poll_gamepads()
+        {
+            engine.gamepad_event(event);
+            if let Some(top) = self.states.last_mut()
+            {
+                let transition = top.actions(engine);
+                self.apply(engine, transition);
+            }
+        }
+        if let Some(top) = self.states.last_mut()
+        {
+            let transition = top.update(engine, tick);
+            self.apply(engine, transition);
+        }
+    }
+
+    pub fn render(&mut self, engine: &mut State, alpha: f32) -> Result<(), wgpu::SurfaceError>
+    {
+        let mut frame = engine.begin_frame()?;
+
+        // start from the lowest state that's visible
+        let top = self.states.len().saturating_sub(1);
+        let mut first = top;
+        while first > 0 && self.states[first].is_overlay()
+        {
+            first -= 1;
+        }
+        for (i, state) in self.states.iter_mut().enumerate().skip(first)
+        {
+            state.render(engine, &mut frame, if i == top { alpha } else { 1.0 });
+        }
+
+        engine.end_frame(frame);
+        Ok(())
+    }
+
+    fn apply(&mut self, engine: &mut State, transition: Transition)
+    {
+        // entering a state can lead straight into another transition (like a level failing to load)
+        let mut next = transition;
+        loop
+        {
+            next = match next
+            {
+                Transition::None => return,
+                Transition::Push(mut state) =>
+                {
+                    if let Some(top) = self.states.last_mut()
+                    {
+                        top.on_cover(engine);
+                    }
+                    log::info!("game state: push {}", state.name());
+                    let next = state.on_enter(engine);
+                    self.states.push(state);
+                    next
+                }
+                Transition::Pop =>
+                {
+                    if let Some(mut state) = self.states.pop()
+                    {
+                        log::info!("game state: pop {}", state.name());
+                        state.on_exit(engine);
+                    }
+                    if let Some(top) = self.states.last_mut()
+                    {
+                        top.on_uncover(engine);
+                    }
+                    Transition::None
+                }
+                Transition::Switch(mut state) =>
+                {
+                    if let Some(mut old) = self.states.pop()
+                    {
+                        old.on_exit(engine);
+                    }
+                    log::info!("game state: switch to {}", state.name());
+                    let next = state.on_enter(engine);
+                    self.states.push(state);
+                    next
+                }
+                Transition::Reset(state) =>
+                {
+                    self.clear(engine);
+                    Transition::Push(state)
+                }
+                Transition::Quit =>
+                {
+                    self.clear(engine);
+                    Transition::None
+                }
+            }
+        }
+    }
+
+    fn clear(&mut self, engine: &mut State)
+    {
+        while let Some(mut state) = self.states.pop()
+        {
+            state.on_exit(engine);
+        }
+    }
+}
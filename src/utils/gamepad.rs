@@ -0,0 +1,242 @@
+use std::sync::{ Arc, Mutex };
+use serde::{ Deserialize, Serialize };
+
+
+/*
+    Gamepads. A backend turns whatever the platform gives us into
+    GamepadEvents, the Simulation polls it every tick and hands the events to
+    the Input resource, where buttons and sticks are bound to actions and
+    axes like keys are (see 'input.rs').
+
+    GilrsBackend talks to real controllers (cargo feature "gilrs"),
+    VirtualGamepad is driven from code, for tests and bots.
+*/
+
+/*   <--------Events-------->   */
+// which pad, in the order they were connected
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct GamepadId(pub usize);
+
+// named by position, South is A on an Xbox pad and Cross on a PlayStation one
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum GamepadButton
+{
+    South,
+    East,
+    West,
+    North,
+    LeftBumper,
+    RightBumper,
+    Select,
+    Start,
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+// sticks go from -1 to 1 (up and right are positive), triggers from 0 to 1
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum GamepadAxis
+{
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+impl GamepadAxis
+{
+    // the other axis of the same stick, dead zones are applied to the stick as a whole
+    pub fn stick_partner(self) -> Option<GamepadAxis>
+    {
+        match self
+        {
+            GamepadAxis::LeftStickX => Some(GamepadAxis::LeftStickY),
+            GamepadAxis::LeftStickY => Some(GamepadAxis::LeftStickX),
+            GamepadAxis::RightStickX => Some(GamepadAxis::RightStickY),
+            GamepadAxis::RightStickY => Some(GamepadAxis::RightStickX),
+            GamepadAxis::LeftTrigger | GamepadAxis::RightTrigger => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum GamepadEvent
+{
+    Connected(GamepadId),
+    Disconnected(GamepadId),
+    Button { id: GamepadId, button: GamepadButton, pressed: bool },
+    Axis { id: GamepadId, axis: GamepadAxis, value: f32 },
+}
+
+
+/*   <--------Backends-------->   */
+pub trait GamepadBackend
+{
+    fn name(&self) -> &str;
+    // adds everything that happened since the last poll to events
+    fn poll(&mut self, events: &mut Vec<GamepadEvent>);
+}
+
+// a gamepad that only does what it's told, clones share the same pad
+// so a test can keep one and give the other to the Simulation
+#[derive(Clone)]
+pub struct VirtualGamepad
+{
+    id: GamepadId,
+    queued: Arc<Mutex<Vec<GamepadEvent>>>,
+}
+
+impl VirtualGamepad
+{
+    // shows up as connected on the first poll
+    pub fn new(id: GamepadId) -> Self
+    {
+        let pad = Self { id, queued: Arc::default() };
+        pad.send(GamepadEvent::Connected(id));
+        pad
+    }
+
+    pub fn send(&self, event: GamepadEvent)
+    {
+        self.queued.lock().unwrap().push(event);
+    }
+
+    pub fn press(&self, button: GamepadButton)
+    {
+        self.send(GamepadEvent::Button { id: self.id, button, pressed: true });
+    }
+
+    pub fn release(&self, button: GamepadButton)
+    {
+        self.send(GamepadEvent::Button { id: self.id, button, pressed: false });
+    }
+
+    pub fn set_axis(&self, axis: GamepadAxis, value: f32)
+    {
+        self.send(GamepadEvent::Axis { id: self.id, axis, value });
+    }
+
+    pub fn disconnect(&self)
+    {
+        self.send(GamepadEvent::Disconnected(self.id));
+    }
+}
+
+impl GamepadBackend for VirtualGamepad
+{
+    fn name(&self) -> &str
+    {
+        "virtual"
+    }
+
+    fn poll(&mut self, events: &mut Vec<GamepadEvent>)
+    {
+        events.append(&mut self.queued.lock().unwrap());
+    }
+}
+
+
+#[cfg(feature = "gilrs")]
+pub use self::gilrs_backend::GilrsBackend;
+
+#[cfg(feature = "gilrs")]
+mod gilrs_backend
+{
+    use super::{ GamepadAxis, GamepadBackend, GamepadButton, GamepadEvent, GamepadId };
+
+    // real controllers through the gilrs crate
+    pub struct GilrsBackend
+    {
+        gilrs: gilrs::Gilrs,
+    }
+
+    impl GilrsBackend
+    {
+        pub fn new() -> anyhow::Result<Self>
+        {
+            let gilrs = gilrs::Gilrs::new().map_err(|e| anyhow::anyhow!("could not start gilrs: {}", e))?;
+            Ok(Self { gilrs })
+        }
+    }
+
+    fn button(button: gilrs::Button) -> Option<GamepadButton>
+    {
+        use gilrs::Button;
+        Some(match button
+        {
+            Button::South => GamepadButton::South,
+            Button::East => GamepadButton::East,
+            Button::West => GamepadButton::West,
+            Button::North => GamepadButton::North,
+            Button::LeftTrigger => GamepadButton::LeftBumper,
+            Button::RightTrigger => GamepadButton::RightBumper,
+            Button::Select => GamepadButton::Select,
+            Button::Start => GamepadButton::Start,
+            Button::LeftThumb => GamepadButton::LeftStick,
+            Button::RightThumb => GamepadButton::RightStick,
+            Button::DPadUp => GamepadButton::DPadUp,
+            Button::DPadDown => GamepadButton::DPadDown,
+            Button::DPadLeft => GamepadButton::DPadLeft,
+            Button::DPadRight => GamepadButton::DPadRight,
+            _ => return None,
+        })
+    }
+
+    fn axis(axis: gilrs::Axis) -> Option<GamepadAxis>
+    {
+        use gilrs::Axis;
+        Some(match axis
+        {
+            Axis::LeftStickX => GamepadAxis::LeftStickX,
+            Axis::LeftStickY => GamepadAxis::LeftStickY,
+            Axis::RightStickX => GamepadAxis::RightStickX,
+            Axis::RightStickY => GamepadAxis::RightStickY,
+            _ => return None,
+        })
+    }
+
+    // gilrs reports the analog triggers as buttons with a value
+    fn trigger(button: gilrs::Button) -> Option<GamepadAxis>
+    {
+        match button
+        {
+            gilrs::Button::LeftTrigger2 => Some(GamepadAxis::LeftTrigger),
+            gilrs::Button::RightTrigger2 => Some(GamepadAxis::RightTrigger),
+            _ => None,
+        }
+    }
+
+    impl GamepadBackend for GilrsBackend
+    {
+        fn name(&self) -> &str
+        {
+            "gilrs"
+        }
+
+        fn poll(&mut self, events: &mut Vec<GamepadEvent>)
+        {
+            use gilrs::EventType;
+            while let Some(gilrs::Event { id, event, .. }) = self.gilrs.next_event()
+            {
+                let id = GamepadId(usize::from(id));
+                let converted = match event
+                {
+                    EventType::Connected => Some(GamepadEvent::Connected(id)),
+                    EventType::Disconnected => Some(GamepadEvent::Disconnected(id)),
+                    EventType::ButtonPressed(pressed, _) => button(pressed).map(|button| GamepadEvent::Button { id, button, pressed: true }),
+                    EventType::ButtonReleased(released, _) => button(released).map(|button| GamepadEvent::Button { id, button, pressed: false }),
+                    EventType::ButtonChanged(changed, value, _) => trigger(changed).map(|axis| GamepadEvent::Axis { id, axis, value }),
+                    EventType::AxisChanged(changed, value, _) => axis(changed).map(|axis| GamepadEvent::Axis { id, axis, value }),
+                    _ => None,
+                };
+                events.extend(converted);
+            }
+        }
+    }
+}
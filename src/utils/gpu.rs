@@ -0,0 +1,254 @@
+use std::
+{
+    fmt,
+    sync::{ atomic::{ AtomicBool, Ordering }, Arc },
+};
+use winit::window::Window;
+
+use super::settings::{ Backend, Settings };
+
+
+/*
+    Finding a gpu to draw with. Not every machine has one that does what
+    the settings ask for (see 'settings.rs'), so this goes down a list
+    until something works:
+
+        the settings' backend and power preference
+        the same backend's software adapter
+        any backend, then any software adapter      (unless the settings already said any)
+        OpenGL                                      (unless the settings already said it)
+
+    and asks each adapter for a device with the default limits, then the
+    downlevel ones, then the WebGL2 ones. Whatever it ends up with is
+    logged. When nothing works the error lists what was tried and why
+    each one failed.
+
+    A device can be lost later on (a driver update or crash, a laptop
+    switching gpus), watch_for_loss() notices and State goes through
+    all of this again (see State::recover_device).
+*/
+
+type LimitsFn = fn() -> wgpu::Limits;
+
+// the limits to ask for, from what the game would like down to what nearly anything has
+const LIMITS: [(&str, LimitsFn); 3] =
+[
+    ("default", wgpu::Limits::default),
+    ("downlevel", wgpu::Limits::downlevel_defaults),
+    ("webgl2", wgpu::Limits::downlevel_webgl2_defaults),
+];
+
+
+/*   <--------Errors-------->   */
+// why one way of getting a gpu didn't work
+#[derive(Debug)]
+pub enum GpuError
+{
+    NoAdapter,
+    // the adapter can't draw to this window
+    UnsupportedSurface { adapter: String },
+    // not even with the lowest limits there are
+    RequestDevice { adapter: String },
+}
+
+impl fmt::Display for GpuError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self
+        {
+            GpuError::NoAdapter => write!(f, "no adapter"),
+            GpuError::UnsupportedSurface { adapter } => write!(f, "{} can't draw to the window", adapter),
+            GpuError::RequestDevice { adapter } => write!(f, "{} turned down every device request", adapter),
+        }
+    }
+}
+
+impl std::error::Error for GpuError {}
+
+
+/*   <--------Attempts-------->   */
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Attempt
+{
+    backends: wgpu::Backends,
+    power_preference: wgpu::PowerPreference,
+    software: bool,
+}
+
+impl fmt::Display for Attempt
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        if self.backends == wgpu::Backends::all()
+        {
+            write!(f, "any backend")?;
+        }
+        else
+        {
+            write!(f, "{:?}", self.backends)?;
+        }
+        if self.software
+        {
+            write!(f, " (software)")?;
+        }
+        Ok(())
+    }
+}
+
+// the ways to try, in order (see the top of the file)
+fn attempts(settings: &Settings) -> Vec<Attempt>
+{
+    let power_preference = settings.power_preference.to_wgpu();
+    let mut backends = vec![settings.backend.to_wgpu()];
+    if settings.backend != Backend::Auto
+    {
+        backends.push(wgpu::Backends::all());
+    }
+    if settings.backend != Backend::Gl
+    {
+        backends.push(wgpu::Backends::GL);
+    }
+    // OpenGL doesn't have a software adapter of its own
+    let mut attempts = Vec::new();
+    for backends in backends
+    {
+        attempts.push(Attempt { backends, power_preference, software: false });
+        if backends != wgpu::Backends::GL
+        {
+            attempts.push(Attempt { backends, power_preference, software: true });
+        }
+    }
+    attempts
+}
+
+
+/*   <--------Gpu-------->   */
+// what State::new() needs to start drawing
+pub struct Gpu
+{
+    pub surface: wgpu::Surface,
+    pub adapter: wgpu::Adapter,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    // the surface's preferred format on this adapter
+    pub format: wgpu::TextureFormat,
+}
+
+// the first gpu that works, with the features in optional that it has,
+// Err has every attempt and why it failed
+pub async fn request(window: &Window, settings: &Settings, optional: wgpu::Features) -> Result<Gpu, Vec<(String, GpuError)>>
+{
+    let mut failures = Vec::new();
+    for attempt in attempts(settings)
+    {
+        match try_attempt(window, attempt, optional).await
+        {
+            Ok(gpu) =>
+            {
+                let info = gpu.adapter.get_info();
+                log::info!("drawing with {} ({:?}, {:?}), found with {}", info.name, info.backend, info.device_type, attempt);
+                if !failures.is_empty()
+                {
+                    log::warn!("the gpu asked for didn't work, fell back to {}", attempt);
+                }
+                return Ok(gpu);
+            }
+            Err(e) =>
+            {
+                log::warn!("{}: {}", attempt, e);
+                failures.push((attempt.to_string(), e));
+            }
+        }
+    }
+    Err(failures)
+}
+
+// set once an error from the device says it was lost, any other error is fatal like it is without a handler.
+// wgpu panics straight away when writing to a queue, submitting to it or presenting on a lost device,
+// those can't be caught here, so State checks the flag and drops the frame before doing any of them
+pub fn watch_for_loss(device: &wgpu::Device) -> Arc<AtomicBool>
+{
+    let lost = Arc::new(AtomicBool::new(false));
+    let flag = Arc::clone(&lost);
+    device.on_uncaptured_error(move |error|
+    {
+        if is_loss(&error)
+        {
+            log::error!("{}", error);
+            flag.store(true, Ordering::Relaxed);
+        }
+        else
+        {
+            panic!("wgpu error: {}\n", error);
+        }
+    });
+    lost
+}
+
+// how wgpu-core 0.12 words DeviceError::Lost (wgpu-core's device/mod.rs), wgpu::Error doesn't say
+// what kind of error it is any other way. this has to be checked again when wgpu is updated
+const LOST_MESSAGE: &str = "parent device is lost";
+
+// the message is somewhere down the error's sources
+fn is_loss(error: &wgpu::Error) -> bool
+{
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error);
+    while let Some(e) = source
+    {
+        if e.to_string().contains(LOST_MESSAGE)
+        {
+            return true;
+        }
+        source = e.source();
+    }
+    false
+}
+
+async fn try_attempt(window: &Window, attempt: Attempt, optional: wgpu::Features) -> Result<Gpu, GpuError>
+{
+    // The instance is a handle to our GPU
+    // Its main purpose is to create Adapters and Surfaces.
+    let instance = wgpu::Instance::new(attempt.backends);
+
+    // The surface is the part of the window that we draw to
+    let surface = unsafe { instance.create_surface(window) };
+
+    // The adapter is a handle to the actual graphics card
+    // You can use it to get info about the card
+    let adapter = instance.request_adapter(
+        &wgpu::RequestAdapterOptions {
+            power_preference: attempt.power_preference,
+            compatible_surface: Some(&surface),
+            force_fallback_adapter: attempt.software,
+        },
+    ).await.ok_or(GpuError::NoAdapter)?;
+    let name = adapter.get_info().name;
+
+    let format = surface.get_preferred_format(&adapter)
+        .ok_or_else(|| GpuError::UnsupportedSurface { adapter: name.clone() })?;
+
+    // Get device and queue from adapter, with the biggest limits it takes
+    let features = adapter.features() & optional;
+    for (limits_name, limits) in LIMITS
+    {
+        let request = adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                features,
+                limits: limits().using_resolution(adapter.limits()),
+                label: None,
+            },
+            None, // Trace path
+        ).await;
+        match request
+        {
+            Ok((device, queue)) =>
+            {
+                log::info!("{} has a device with the {} limits", name, limits_name);
+                return Ok(Gpu { surface, adapter, device, queue, format });
+            }
+            Err(e) => log::warn!("{} has no device with the {} limits: {}", name, limits_name, e),
+        }
+    }
+    Err(GpuError::RequestDevice { adapter: name })
+}
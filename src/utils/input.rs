@@ -0,0 +1,538 @@
+use std::
+{
+    collections::{ BTreeMap, HashMap, HashSet },
+    path::Path,
+};
+use serde::{ Deserialize, Serialize };
+use winit::event::{ ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent };
+
+use super::
+{
+    data_file,
+    gamepad::{ GamepadAxis, GamepadButton, GamepadEvent, GamepadId },
+};
+
+
+/*
+    Action based input. Game code asks about named actions ("pause") and
+    axes ("zoom") instead of raw keys, and the bindings that map keys,
+    mouse buttons, the scroll wheel and gamepads (see 'gamepad.rs') onto
+    them come from a config file (input.ron in the player's config folder,
+    or the defaults in assets/config, see 'paths.rs') that players can
+    edit to remap the controls.
+
+    pressed/released are true for exactly one simulation tick: the Input
+    resource is told about every window event as it comes in (whatever
+    game state is on top), and State::update clears the edges after
+    every tick.
+*/
+
+/*   <--------Game Controls-------->   */
+pub const PAUSE: &str = "pause";
+pub const ZOOM: &str = "zoom";
+pub const ORBIT: &str = "orbit";
+// clicking on things in the world (see 'picking.rs')
+pub const SELECT: &str = "select";
+// turns debug drawing on and off (see 'debug_draw.rs')
+pub const DEBUG_DRAW: &str = "debug draw";
+// going through the render modes and turning back face culling off and on (see 'render_mode.rs')
+pub const RENDER_MODE: &str = "render mode";
+pub const CULLING: &str = "culling";
+// shows and hides the profiler window (see 'profiler.rs')
+pub const PROFILER: &str = "profiler";
+// saving the screen, once or every few frames until it's pressed again (see 'capture.rs')
+pub const SCREENSHOT: &str = "screenshot";
+pub const CAPTURE_FRAMES: &str = "capture frames";
+// getting around the menus (see 'screens.rs')
+pub const CONFIRM: &str = "confirm";
+pub const BACK: &str = "back";
+pub const QUIT_TO_MENU: &str = "quit to menu";
+
+
+/*   <--------Bindings-------->   */
+// the player's bindings, in their config folder or the defaults in assets/config (see 'paths.rs'),
+// the console's bind, unbind and save_bindings commands change and save them (see 'systems.rs')
+pub const BINDINGS_FILE: &str = "input.ron";
+
+// a button that can be bound to an action
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Binding
+{
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+    // one notch of the wheel is a press and a release in the same tick
+    ScrollUp,
+    ScrollDown,
+    // on any connected gamepad
+    Gamepad(GamepadButton),
+}
+
+impl Binding
+{
+    // which binding a window event is about, and whether it went down (true) or up (false),
+    // also handy for a "press the key you want" screen when remapping
+    pub fn from_event(event: &WindowEvent) -> Option<(Binding, bool)>
+    {
+        match event
+        {
+            WindowEvent::KeyboardInput { input: KeyboardInput { state, virtual_keycode: Some(keycode), .. }, .. } =>
+                Some((Binding::Key(*keycode), *state == ElementState::Pressed)),
+            WindowEvent::MouseInput { state, button, .. } =>
+                Some((Binding::Mouse(*button), *state == ElementState::Pressed)),
+            WindowEvent::MouseWheel { .. } => match scroll_lines(event)
+            {
+                lines if lines > 0.0 => Some((Binding::ScrollUp, true)),
+                lines if lines < 0.0 => Some((Binding::ScrollDown, true)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+// written the way the bindings file writes them, like Key(F1) or Gamepad(Start)
+impl std::str::FromStr for Binding
+{
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err>
+    {
+        ron::from_str(text).map_err(|_| format!("{} isn't a binding, try something like Key(P), Mouse(Right) or Gamepad(North)", text))
+    }
+}
+
+// what moves an axis, the axis' value is the sum over all of them
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AxisBinding
+{
+    // -1 while negative is held, +1 while positive is
+    Buttons { negative: Binding, positive: Binding },
+    // notches scrolled during the tick, up is positive
+    Scroll,
+    // a stick or trigger (after the dead zone) times scale, -1 flips it
+    Gamepad { axis: GamepadAxis, scale: f32 },
+}
+
+fn default_stick_dead_zone() -> f32
+{
+    0.2
+}
+
+fn default_trigger_dead_zone() -> f32
+{
+    0.05
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Bindings
+{
+    #[serde(default)]
+    pub actions: BTreeMap<String, Vec<Binding>>,
+    #[serde(default)]
+    pub axes: BTreeMap<String, Vec<AxisBinding>>,
+    // how far a stick has to move before it counts, worn sticks never quite come back to 0
+    #[serde(default = "default_stick_dead_zone")]
+    pub stick_dead_zone: f32,
+    #[serde(default = "default_trigger_dead_zone")]
+    pub trigger_dead_zone: f32,
+}
+
+impl Default for Bindings
+{
+    fn default() -> Self
+    {
+        let mut bindings = Self
+        {
+            actions: BTreeMap::new(),
+            axes: BTreeMap::new(),
+            stick_dead_zone: default_stick_dead_zone(),
+            trigger_dead_zone: default_trigger_dead_zone(),
+        };
+        bindings.bind(PAUSE, Binding::Key(VirtualKeyCode::Escape));
+        bindings.bind(PAUSE, Binding::Gamepad(GamepadButton::Start));
+        bindings.bind(SELECT, Binding::Mouse(MouseButton::Left));
+        bindings.bind(DEBUG_DRAW, Binding::Key(VirtualKeyCode::F3));
+        bindings.bind(RENDER_MODE, Binding::Key(VirtualKeyCode::F4));
+        bindings.bind(CULLING, Binding::Key(VirtualKeyCode::F5));
+        bindings.bind(PROFILER, Binding::Key(VirtualKeyCode::F6));
+        bindings.bind(SCREENSHOT, Binding::Key(VirtualKeyCode::F12));
+        bindings.bind(CAPTURE_FRAMES, Binding::Key(VirtualKeyCode::F9));
+        bindings.bind(CONFIRM, Binding::Key(VirtualKeyCode::Return));
+        bindings.bind(CONFIRM, Binding::Key(VirtualKeyCode::Space));
+        bindings.bind(CONFIRM, Binding::Gamepad(GamepadButton::South));
+        bindings.bind(BACK, Binding::Key(VirtualKeyCode::Escape));
+        bindings.bind(BACK, Binding::Gamepad(GamepadButton::East));
+        bindings.bind(QUIT_TO_MENU, Binding::Key(VirtualKeyCode::Q));
+        bindings.bind(QUIT_TO_MENU, Binding::Gamepad(GamepadButton::Select));
+        bindings.bind_axis(ZOOM, AxisBinding::Buttons { negative: Binding::Key(VirtualKeyCode::S), positive: Binding::Key(VirtualKeyCode::W) });
+        bindings.bind_axis(ZOOM, AxisBinding::Buttons { negative: Binding::Key(VirtualKeyCode::Down), positive: Binding::Key(VirtualKeyCode::Up) });
+        bindings.bind_axis(ZOOM, AxisBinding::Scroll);
+        bindings.bind_axis(ZOOM, AxisBinding::Gamepad { axis: GamepadAxis::LeftStickY, scale: 1.0 });
+        bindings.bind_axis(ZOOM, AxisBinding::Gamepad { axis: GamepadAxis::RightTrigger, scale: 1.0 });
+        bindings.bind_axis(ZOOM, AxisBinding::Gamepad { axis: GamepadAxis::LeftTrigger, scale: -1.0 });
+        bindings.bind_axis(ORBIT, AxisBinding::Buttons { negative: Binding::Key(VirtualKeyCode::A), positive: Binding::Key(VirtualKeyCode::D) });
+        bindings.bind_axis(ORBIT, AxisBinding::Buttons { negative: Binding::Key(VirtualKeyCode::Left), positive: Binding::Key(VirtualKeyCode::Right) });
+        bindings.bind_axis(ORBIT, AxisBinding::Gamepad { axis: GamepadAxis::LeftStickX, scale: 1.0 });
+        bindings.bind_axis(ORBIT, AxisBinding::Buttons { negative: Binding::Gamepad(GamepadButton::DPadLeft), positive: Binding::Gamepad(GamepadButton::DPadRight) });
+        bindings
+    }
+}
+
+impl Bindings
+{
+    // .ron or .json, see 'data_file.rs'
+    // actions and axes the file doesn't mention get their default bindings, so a file saved before
+    // they were added still has every control (an empty list in the file leaves one unbound)
+    pub fn load(path: &Path) -> Result<Self, data_file::DataError>
+    {
+        let mut bindings: Self = data_file::load(path)?;
+        let defaults = Self::default();
+        for (action, default) in defaults.actions
+        {
+            bindings.actions.entry(action).or_insert(default);
+        }
+        for (axis, default) in defaults.axes
+        {
+            bindings.axes.entry(axis).or_insert(default);
+        }
+        Ok(bindings)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), data_file::DataError>
+    {
+        data_file::save(self, path)
+    }
+
+    // adds a binding, an action can have any number of them
+    pub fn bind(&mut self, action: &str, binding: Binding)
+    {
+        let bindings = self.actions.entry(action.to_string()).or_default();
+        if !bindings.contains(&binding)
+        {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn unbind(&mut self, action: &str, binding: Binding)
+    {
+        if let Some(bindings) = self.actions.get_mut(action)
+        {
+            bindings.retain(|bound| *bound != binding);
+        }
+    }
+
+    // swaps one binding for another, keeping its place in the list
+    pub fn rebind(&mut self, action: &str, old: Binding, new: Binding)
+    {
+        match self.actions.get_mut(action).and_then(|bindings| bindings.iter_mut().find(|bound| **bound == old))
+        {
+            Some(bound) => *bound = new,
+            None => self.bind(action, new),
+        }
+    }
+
+    pub fn bind_axis(&mut self, axis: &str, binding: AxisBinding)
+    {
+        let bindings = self.axes.entry(axis.to_string()).or_default();
+        if !bindings.contains(&binding)
+        {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn unbind_axis(&mut self, axis: &str, binding: AxisBinding)
+    {
+        if let Some(bindings) = self.axes.get_mut(axis)
+        {
+            bindings.retain(|bound| *bound != binding);
+        }
+    }
+
+    fn action(&self, action: &str) -> &[Binding]
+    {
+        self.actions.get(action).map(Vec::as_slice).unwrap_or(&[])
+    }
+}
+
+
+/*   <--------Input State-------->   */
+// what's held down at some moment, recordings start from one of these (see 'replay.rs')
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputSnapshot
+{
+    // keys and mouse buttons
+    pub held: Vec<Binding>,
+    pub gamepad_buttons: Vec<(GamepadId, GamepadButton)>,
+    pub gamepad_axes: Vec<(GamepadId, GamepadAxis, f32)>,
+}
+
+// a resource in the world (see 'systems.rs')
+#[derive(Default)]
+pub struct Input
+{
+    bindings: Bindings,
+    // keys and mouse buttons, gamepad buttons are tracked per pad below
+    held: HashSet<Binding>,
+    pressed: HashSet<Binding>,
+    released: HashSet<Binding>,
+    scroll: f32,
+    gamepad_buttons: HashSet<(GamepadId, GamepadButton)>,
+    gamepad_axes: HashMap<(GamepadId, GamepadAxis), f32>,
+    // set when the latest event was a new press (not a key repeat)
+    latest_press: Option<Binding>,
+}
+
+// pixels of touchpad scrolling that count as one notch of a wheel
+const PIXELS_PER_LINE: f64 = 40.0;
+
+pub fn scroll_lines(event: &WindowEvent) -> f32
+{
+    match event
+    {
+        WindowEvent::MouseWheel { delta: MouseScrollDelta::LineDelta(_, y), .. } => *y,
+        WindowEvent::MouseWheel { delta: MouseScrollDelta::PixelDelta(position), .. } => (position.y / PIXELS_PER_LINE) as f32,
+        _ => 0.0,
+    }
+}
+
+// 0 inside the dead zone, then rescaled so the output still goes all the way from 0 to 1
+fn past_dead_zone(amount: f32, dead_zone: f32) -> f32
+{
+    if amount <= dead_zone
+    {
+        0.0
+    }
+    else
+    {
+        ((amount - dead_zone) / (1.0 - dead_zone).max(f32::EPSILON)).min(1.0)
+    }
+}
+
+impl Input
+{
+    pub fn new(bindings: Bindings) -> Self
+    {
+        Self { bindings, ..Default::default() }
+    }
+
+    pub fn bindings(&self) -> &Bindings
+    {
+        &self.bindings
+    }
+
+    pub fn bindings_mut(&mut self) -> &mut Bindings
+    {
+        &mut self.bindings
+    }
+
+    // returns whether the event was bound to anything
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool
+    {
+        self.latest_press = None;
+        if let WindowEvent::Focused(false) = event
+        {
+            self.release_all();
+            return false;
+        }
+        let (binding, down) = match Binding::from_event(event)
+        {
+            Some(change) => change,
+            None => return false,
+        };
+
+        if let WindowEvent::MouseWheel { .. } = event
+        {
+            self.scroll += scroll_lines(event);
+            self.pressed.insert(binding);
+            self.released.insert(binding);
+            self.latest_press = Some(binding);
+        }
+        else if down
+        {
+            // key repeat sends more presses while the key is held, those aren't new presses
+            if self.held.insert(binding)
+            {
+                self.pressed.insert(binding);
+                self.latest_press = Some(binding);
+            }
+        }
+        else if self.held.remove(&binding)
+        {
+            self.released.insert(binding);
+        }
+
+        self.is_bound(binding)
+    }
+
+    pub fn handle_gamepad_event(&mut self, event: &GamepadEvent)
+    {
+        self.latest_press = None;
+        match *event
+        {
+            GamepadEvent::Connected(id) => log::info!("gamepad {} connected", id.0),
+            GamepadEvent::Disconnected(id) =>
+            {
+                log::info!("gamepad {} disconnected", id.0);
+                let buttons: Vec<GamepadButton> = self.gamepad_buttons.iter()
+                    .filter(|(pad, _)| *pad == id)
+                    .map(|(_, button)| *button)
+                    .collect();
+                for button in buttons
+                {
+                    self.set_gamepad_button(id, button, false);
+                }
+                self.gamepad_axes.retain(|(pad, _), _| *pad != id);
+            }
+            GamepadEvent::Button { id, button, pressed } => self.set_gamepad_button(id, button, pressed),
+            GamepadEvent::Axis { id, axis, value } =>
+            {
+                self.gamepad_axes.insert((id, axis), value.clamp(-1.0, 1.0));
+            }
+        }
+    }
+
+    fn set_gamepad_button(&mut self, id: GamepadId, button: GamepadButton, down: bool)
+    {
+        let binding = Binding::Gamepad(button);
+        let was_held = self.is_held(&binding);
+        if down
+        {
+            self.gamepad_buttons.insert((id, button));
+        }
+        else
+        {
+            self.gamepad_buttons.remove(&(id, button));
+        }
+
+        // with two pads the button is held until both let go
+        match (was_held, self.is_held(&binding))
+        {
+            (false, true) =>
+            {
+                self.pressed.insert(binding);
+                self.latest_press = Some(binding);
+            }
+            (true, false) =>
+            {
+                self.released.insert(binding);
+            }
+            _ => {}
+        }
+    }
+
+    fn is_bound(&self, binding: Binding) -> bool
+    {
+        self.bindings.actions.values().flatten().any(|bound| *bound == binding)
+            || self.bindings.axes.values().flatten().any(|axis| match axis
+            {
+                AxisBinding::Buttons { negative, positive } => *negative == binding || *positive == binding,
+                AxisBinding::Scroll => matches!(binding, Binding::ScrollUp | Binding::ScrollDown),
+                AxisBinding::Gamepad { .. } => false,
+            })
+    }
+
+    fn is_held(&self, binding: &Binding) -> bool
+    {
+        match binding
+        {
+            Binding::Gamepad(button) => self.gamepad_buttons.iter().any(|(_, held)| held == button),
+            _ => self.held.contains(binding),
+        }
+    }
+
+    // a stick or trigger on one pad, with the dead zone taken out
+    fn gamepad_axis(&self, id: GamepadId, axis: GamepadAxis) -> f32
+    {
+        let value = |axis| self.gamepad_axes.get(&(id, axis)).copied().unwrap_or(0.0);
+        match axis.stick_partner()
+        {
+            // the dead zone is a circle, so pushing the stick diagonally isn't harder than straight
+            Some(partner) =>
+            {
+                let (x, y) = (value(axis), value(partner));
+                let length = (x * x + y * y).sqrt();
+                if length == 0.0 { 0.0 } else { x / length * past_dead_zone(length, self.bindings.stick_dead_zone) }
+            }
+            None => past_dead_zone(value(axis).max(0.0), self.bindings.trigger_dead_zone),
+        }
+    }
+
+    // went down this tick
+    pub fn pressed(&self, action: &str) -> bool
+    {
+        self.bindings.action(action).iter().any(|binding| self.pressed.contains(binding))
+    }
+
+    // is down right now
+    pub fn held(&self, action: &str) -> bool
+    {
+        self.bindings.action(action).iter().any(|binding| self.is_held(binding))
+    }
+
+    // went up this tick
+    pub fn released(&self, action: &str) -> bool
+    {
+        self.bindings.action(action).iter().any(|binding| self.released.contains(binding))
+    }
+
+    pub fn axis(&self, axis: &str) -> f32
+    {
+        let button = |binding: &Binding| if self.is_held(binding) { 1.0 } else { 0.0 };
+        let pads: HashSet<GamepadId> = self.gamepad_axes.keys().map(|(id, _)| *id).collect();
+        self.bindings.axes.get(axis).into_iter().flatten()
+            .map(|axis| match axis
+            {
+                AxisBinding::Buttons { negative, positive } => button(positive) - button(negative),
+                AxisBinding::Scroll => self.scroll,
+                // whichever pad pushes it furthest
+                AxisBinding::Gamepad { axis, scale } => pads.iter()
+                    .map(|id| self.gamepad_axis(*id, *axis))
+                    .fold(0.0, |furthest: f32, value| if value.abs() > furthest.abs() { value } else { furthest }) * scale,
+            })
+            .sum()
+    }
+
+    // whether the event that was just handled pressed the action, for things that react
+    // to input right away instead of on the next tick (like pausing, which stops the ticks)
+    pub fn triggered(&self, action: &str) -> bool
+    {
+        self.latest_press.is_some_and(|binding| self.bindings.action(action).contains(&binding))
+    }
+
+    // forgets the edges once a tick has seen them
+    pub fn end_tick(&mut self)
+    {
+        self.pressed.clear();
+        self.released.clear();
+        self.scroll = 0.0;
+    }
+
+    // what's down right now, in a stable order
+    pub fn snapshot(&self) -> InputSnapshot
+    {
+        let mut held: Vec<Binding> = self.held.iter().copied().collect();
+        held.sort_by_key(|binding| format!("{:?}", binding));
+        let mut gamepad_buttons: Vec<(GamepadId, GamepadButton)> = self.gamepad_buttons.iter().copied().collect();
+        gamepad_buttons.sort();
+        let mut gamepad_axes: Vec<(GamepadId, GamepadAxis, f32)> = self.gamepad_axes.iter().map(|((id, axis), value)| (*id, *axis, *value)).collect();
+        gamepad_axes.sort_by_key(|(id, axis, _)| (*id, *axis));
+        InputSnapshot { held, gamepad_buttons, gamepad_axes }
+    }
+
+    // puts buttons down and sticks where they were, without anything counting as pressed this tick
+    pub fn restore(&mut self, snapshot: &InputSnapshot)
+    {
+        self.held = snapshot.held.iter().copied().collect();
+        self.gamepad_buttons = snapshot.gamepad_buttons.iter().copied().collect();
+        self.gamepad_axes = snapshot.gamepad_axes.iter().map(|(id, axis, value)| ((*id, *axis), *value)).collect();
+    }
+
+    // lets go of the keyboard and mouse, like when the window loses focus and won't hear about key releases
+    // (gamepads keep working without focus)
+    pub fn release_all(&mut self)
+    {
+        self.held.clear();
+        self.end_tick();
+    }
+}
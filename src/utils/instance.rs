@@ -0,0 +1,55 @@
+use cgmath::
+{
+    Matrix,
+    SquareMatrix,
+};
+
+
+/*   <--------Instance Buffer-------->   */
+// per object data, stepped once per instance instead of once per vertex
+// so the same mesh can be drawn many times in one draw call
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct InstanceRaw
+{
+    // can't use cgmath with bytemuck directly, same as CameraUniform
+    model: [[f32; 4]; 4],
+    // inverse transpose of the model matrix, keeps normals right under non-uniform scale
+    normal: [[f32; 3]; 3],
+}
+
+impl InstanceRaw
+{
+    pub fn from_matrix(model: cgmath::Matrix4<f32>) -> Self
+    {
+        let model3 = cgmath::Matrix3::from_cols(model.x.truncate(), model.y.truncate(), model.z.truncate());
+        let normal = model3.invert().map(|m| m.transpose()).unwrap_or_else(cgmath::Matrix3::identity);
+        Self
+        {
+            model: model.into(),
+            normal: normal.into(),
+        }
+    }
+
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a>
+    {
+        use std::mem;
+        wgpu::VertexBufferLayout
+        {
+            array_stride: mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
+            // the shader only moves to the next InstanceRaw when it starts a new instance
+            step_mode: wgpu::VertexStepMode::Instance,
+            // a mat4 takes up 4 vertex slots (one per column), the shader puts them back together
+            // locations start at 5 to leave room for more Vertex attributes
+            attributes: &[
+                wgpu::VertexAttribute { offset: 0, shader_location: 5, format: wgpu::VertexFormat::Float32x4 },
+                wgpu::VertexAttribute { offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress, shader_location: 6, format: wgpu::VertexFormat::Float32x4 },
+                wgpu::VertexAttribute { offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress, shader_location: 7, format: wgpu::VertexFormat::Float32x4 },
+                wgpu::VertexAttribute { offset: mem::size_of::<[f32; 12]>() as wgpu::BufferAddress, shader_location: 8, format: wgpu::VertexFormat::Float32x4 },
+                wgpu::VertexAttribute { offset: mem::size_of::<[f32; 16]>() as wgpu::BufferAddress, shader_location: 9, format: wgpu::VertexFormat::Float32x3 },
+                wgpu::VertexAttribute { offset: mem::size_of::<[f32; 19]>() as wgpu::BufferAddress, shader_location: 10, format: wgpu::VertexFormat::Float32x3 },
+                wgpu::VertexAttribute { offset: mem::size_of::<[f32; 22]>() as wgpu::BufferAddress, shader_location: 11, format: wgpu::VertexFormat::Float32x3 },
+            ],
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use cgmath::InnerSpace;
+use serde::{ Deserialize, Serialize };
+
+use super::
+{
+    scene,
+};
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LightKind
+{
+    // shines in every direction from the node's position
+    Point,
+    // shines along the node's -z axis, the position doesn't matter
+    Directional,
+}
+
+// attached to a scene node, the node decides where the light is
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Light
+{
+    pub kind: LightKind,
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+
+// -----Uniform buffer with the light the fragment shader uses-----
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightUniform
+{
+    // xyz is the position for point lights (w = 1)
+    // or the direction the light travels in for directional ones (w = 0)
+    position: [f32; 4],
+    // rgb color, a is the intensity
+    color: [f32; 4],
+    // light everything gets no matter where it faces
+    ambient: [f32; 4],
+}
+
+impl LightUniform
+{
+    pub fn new() -> Self
+    {
+        // no light in the scene -> fully lit, so textures show their own colors
+        Self
+        {
+            position: [0.0; 4],
+            color: [0.0; 4],
+            ambient: [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+
+    // the shader handles one light for now, the first one found in the scene
+    pub fn update_from_scene(&mut self, scene: &scene::SceneGraph)
+    {
+        *self = Self::new();
+        let lit = scene.ids()
+            .filter_map(|id| scene.get(id))
+            .find_map(|node| node.light.map(|light| (node, light)));
+        if let Some((node, light)) = lit
+        {
+            self.position = match light.kind
+            {
+                LightKind::Point => node.world_position().to_homogeneous().into(),
+                LightKind::Directional => node.world_forward().normalize().extend(0.0).into(),
+            };
+            self.color = [light.color[0], light.color[1], light.color[2], light.intensity];
+            self.ambient = [0.1, 0.1, 0.1, 1.0];
+        }
+    }
+}
+
+impl Default for LightUniform
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
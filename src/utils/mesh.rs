@@ -0,0 +1,235 @@
+use wgpu::util::DeviceExt;
+use anyhow::*;
+
+use super::
+{
+    bounds,
+    vertex,
+};
+
+
+/*   <--------Mesh Data-------->   */
+// cpu side copy of a mesh, this is what the asset workers decode into
+// so that nothing here needs a device to be built
+pub struct MeshData
+{
+    pub vertices: Vec<vertex::Vertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData
+{
+    // parse a Wavefront .obj file, every model in the file is merged into one mesh
+    pub fn from_obj_bytes(bytes: &[u8]) -> Result<Self>
+    {
+        let mut reader = std::io::BufReader::new(bytes);
+        let (models, _materials) = tobj::load_obj_buf(
+            &mut reader,
+            &tobj::LoadOptions
+            {
+                single_index: true,         // one index buffer for positions, uvs (and normals)
+                triangulate: true,          // turn quads and polygons into triangles
+                ..Default::default()
+            },
+            // materials are handled by the texture side of the asset manager
+            |_| std::result::Result::Ok((Vec::new(), Default::default())),
+        )?;
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        for model in models
+        {
+            let mesh = model.mesh;
+            // indices of this model start after the vertices of the previous ones
+            let base = vertices.len() as u32;
+            for i in 0..mesh.positions.len() / 3
+            {
+                let tex_coords = if mesh.texcoords.len() >= i * 2 + 2
+                {
+                    // obj has (0, 0) at the bottom left, wgpu has it at the top left
+                    [mesh.texcoords[i * 2], 1.0 - mesh.texcoords[i * 2 + 1]]
+                }
+                else
+                {
+                    [0.0, 0.0]
+                };
+                let normal = if mesh.normals.len() >= i * 3 + 3
+                {
+                    [mesh.normals[i * 3], mesh.normals[i * 3 + 1], mesh.normals[i * 3 + 2]]
+                }
+                else
+                {
+                    // filled in from the triangles below
+                    [0.0, 0.0, 0.0]
+                };
+                vertices.push(vertex::Vertex
+                {
+                    position: [mesh.positions[i * 3], mesh.positions[i * 3 + 1], mesh.positions[i * 3 + 2]],
+                    tex_coords,
+                    normal,
+                });
+            }
+            indices.extend(mesh.indices.iter().map(|index| base + index));
+
+            if mesh.normals.is_empty()
+            {
+                compute_normals(&mut vertices[base as usize..], &mesh.indices);
+            }
+        }
+
+        if indices.is_empty()
+        {
+            bail!("obj file contains no triangles");
+        }
+
+        Ok(Self { vertices, indices })
+    }
+}
+
+
+// smooth normals for files that don't have any: every vertex gets
+// the average of the faces around it (weighted by their area)
+fn compute_normals(vertices: &mut [vertex::Vertex], indices: &[u32])
+{
+    use cgmath::InnerSpace;
+
+    let mut normals = vec![cgmath::Vector3::new(0.0f32, 0.0, 0.0); vertices.len()];
+    for triangle in indices.chunks_exact(3)
+    {
+        let [a, b, c] = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+        let pa = cgmath::Vector3::from(vertices[a].position);
+        let pb = cgmath::Vector3::from(vertices[b].position);
+        let pc = cgmath::Vector3::from(vertices[c].position);
+        // not normalized on purpose, bigger faces count for more
+        let face_normal = (pb - pa).cross(pc - pa);
+        normals[a] += face_normal;
+        normals[b] += face_normal;
+        normals[c] += face_normal;
+    }
+    for (vertex, normal) in vertices.iter_mut().zip(normals)
+    {
+        if normal.magnitude2() > 0.0
+        {
+            vertex.normal = normal.normalize().into();
+        }
+    }
+}
+
+
+/*   <--------Mesh Shape-------->   */
+// what's left of a mesh on the cpu once it's on the gpu: the triangles
+// and bounding volumes, for clicking on things (see 'picking.rs')
+pub struct MeshShape
+{
+    pub positions: Vec<cgmath::Point3<f32>>,
+    pub indices: Vec<u32>,
+    pub aabb: bounds::Aabb,
+    pub sphere: bounds::BoundingSphere,
+}
+
+impl MeshShape
+{
+    pub fn from_data(data: &MeshData) -> Self
+    {
+        let positions: Vec<cgmath::Point3<f32>> = data.vertices.iter().map(|vertex| cgmath::Point3::from(vertex.position)).collect();
+        // from_obj_bytes never gives back a mesh without triangles
+        let aabb = bounds::Aabb::from_points(positions.iter().copied())
+            .unwrap_or(bounds::Aabb { min: cgmath::Point3::new(0.0, 0.0, 0.0), max: cgmath::Point3::new(0.0, 0.0, 0.0) });
+        let sphere = bounds::BoundingSphere::around(&aabb, positions.iter().copied());
+        Self { positions, indices: data.indices.clone(), aabb, sphere }
+    }
+
+    // the closest triangle the ray hits, the ray is in the mesh's own space
+    pub fn intersect_ray(&self, ray: &bounds::Ray) -> Option<bounds::RayHit>
+    {
+        // most rays miss most meshes, the sphere and box are a lot cheaper than the triangles
+        self.sphere.intersect_ray(ray)?;
+        self.aabb.intersect_ray(ray)?;
+        self.indices.chunks_exact(3)
+            .filter_map(|triangle| ray.intersect_triangle(
+                self.positions[triangle[0] as usize],
+                self.positions[triangle[1] as usize],
+                self.positions[triangle[2] as usize],
+            ))
+            .min_by(|a, b| a.t.total_cmp(&b.t))
+    }
+}
+
+
+/*   <--------Mesh-------->   */
+// gpu side mesh, the vertex and index buffers that a render pass draws
+pub struct Mesh
+{
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub num_indices: u32,
+}
+
+impl Mesh
+{
+    pub fn from_data(device: &wgpu::Device, data: &MeshData, label: &str) -> Self
+    {
+        let vertex_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor
+            {
+                label: Some(&format!("{} Vertex Buffer", label)),
+                contents: bytemuck::cast_slice(&data.vertices),     // using bytemuck to cast the vertices as a &[u8]
+                usage: wgpu::BufferUsages::VERTEX,
+            }
+        );
+
+        let index_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor
+            {
+                label: Some(&format!("{} Index Buffer", label)),
+                contents: bytemuck::cast_slice(&data.indices),
+                usage: wgpu::BufferUsages::INDEX,
+            }
+        );
+
+        Self
+        {
+            vertex_buffer,
+            index_buffer,
+            num_indices: data.indices.len() as u32,
+        }
+    }
+}
+
+
+/*   <--------Wireframe Mesh-------->   */
+// every triangle with its own three vertices (positions only, no index buffer),
+// for drawing wireframes on gpus that can't draw lines (see 'render_mode.rs')
+pub struct WireframeMesh
+{
+    pub vertex_buffer: wgpu::Buffer,
+    pub num_vertices: u32,
+}
+
+impl WireframeMesh
+{
+    pub fn from_shape(device: &wgpu::Device, shape: &MeshShape, label: &str) -> Self
+    {
+        let positions: Vec<[f32; 3]> = shape.indices.iter().map(|index| shape.positions[*index as usize].into()).collect();
+        let vertex_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor
+            {
+                label: Some(&format!("{} Wireframe Vertex Buffer", label)),
+                contents: bytemuck::cast_slice(&positions),
+                usage: wgpu::BufferUsages::VERTEX,
+            }
+        );
+        Self { vertex_buffer, num_vertices: positions.len() as u32 }
+    }
+
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a>
+    {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![0 => Float32x3];
+        wgpu::VertexBufferLayout
+        {
+            array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
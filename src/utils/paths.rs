@@ -0,0 +1,97 @@
+use std::
+{
+    env,
+    path::{ Path, PathBuf },
+};
+
+use super::data_file::DataError;
+
+
+/*
+    Where the game's files are, worked out when it runs rather than when
+    it's built, so the game still finds them after being moved or copied
+    to another machine.
+
+    The assets (textures, models, levels, fonts and the default config)
+    ship next to the executable in an "assets" folder. Running from the
+    repo with cargo finds the one at the top of the repo the same way,
+    since target/debug is a couple of folders below it. Setting
+    WITH_ME_ASSETS points the game somewhere else.
+
+    The player's own config (key bindings, window and graphics settings)
+    is saved to a folder of their own and read from there first, the
+    files in assets/config are only the defaults:
+
+        linux    $XDG_CONFIG_HOME/with-me, or ~/.config/with-me
+        macos    ~/Library/Application Support/with-me
+        windows  %APPDATA%\with-me
+
+    WITH_ME_CONFIG points it somewhere else.
+*/
+
+// overrides where the assets are
+pub const ASSETS_VAR: &str = "WITH_ME_ASSETS";
+// overrides where the player's config is saved
+pub const CONFIG_VAR: &str = "WITH_ME_CONFIG";
+// the name of the folders the game looks for and makes
+const ASSETS_DIR: &str = "assets";
+const APP_DIR: &str = "with-me";
+// where the default config files are, relative to the asset root
+const DEFAULT_CONFIG_DIR: &str = "config";
+// folders up from the executable to look for the assets in, enough to get out of target/debug/deps
+const SEARCH_DEPTH: usize = 4;
+
+
+// the folder everything the game loads from disk lives under
+pub fn asset_root() -> PathBuf
+{
+    if let Some(root) = env::var_os(ASSETS_VAR)
+    {
+        return PathBuf::from(root);
+    }
+    // the executable's folder and a few above it, then wherever the game was started from
+    let exe = env::current_exe().ok();
+    exe.iter()
+        .flat_map(|exe| exe.ancestors().skip(1).take(SEARCH_DEPTH))
+        .map(|dir| dir.join(ASSETS_DIR))
+        .find(|assets| assets.is_dir())
+        .unwrap_or_else(|| PathBuf::from(ASSETS_DIR))
+}
+
+// the player's config folder, None -> the system doesn't say where it should be
+pub fn config_dir() -> Option<PathBuf>
+{
+    if let Some(dir) = env::var_os(CONFIG_VAR)
+    {
+        return Some(PathBuf::from(dir));
+    }
+    let var = |name| env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+    let base = if cfg!(target_os = "windows")
+    {
+        var("APPDATA")
+    }
+    else if cfg!(target_os = "macos")
+    {
+        var("HOME").map(|home| home.join("Library/Application Support"))
+    }
+    else
+    {
+        var("XDG_CONFIG_HOME").or_else(|| var("HOME").map(|home| home.join(".config")))
+    };
+    base.map(|base| base.join(APP_DIR))
+}
+
+// where the player's copy of a config file is saved
+pub fn user_config(name: &str) -> Result<PathBuf, DataError>
+{
+    config_dir().map(|dir| dir.join(name)).ok_or_else(|| DataError::new(Path::new(name), "",
+        format!("there's no config folder to save to, set {} to choose one", CONFIG_VAR)))
+}
+
+// the player's copy of a config file if they have one, otherwise the default one in the assets
+pub fn config_file(asset_root: &Path, name: &str) -> PathBuf
+{
+    user_config(name).ok()
+        .filter(|path| path.exists())
+        .unwrap_or_else(|| asset_root.join(DEFAULT_CONFIG_DIR).join(name))
+}
@@ -0,0 +1,354 @@
+use cgmath::
+{
+    InnerSpace,
+    Matrix,
+    SquareMatrix,
+};
+
+use super::
+{
+    assets,
+    bounds::{ self, Ray },
+    camera,
+    ecs::{ Entity, World },
+    mesh,
+    scene,
+    systems,
+    texture,
+    vertex,
+    instance,
+};
+
+
+/*
+    Clicking on things in the world.
+
+    The cursor becomes a ray from the camera (screen_ray) which is tested
+    against the bounding sphere, then the box, then the triangles of every
+    mesh in the RenderList (pick). Everything is tested where it was drawn,
+    so alpha should be the one the last frame was drawn with.
+
+    For scenes with lots of objects the IdBuffer does the same on the gpu:
+    every object is drawn with its index instead of its color and the pixel
+    under the cursor says which one it was (see State::pick).
+*/
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PickHit
+{
+    pub node: scene::NodeId,
+    // None for nodes that aren't driven by an entity
+    pub entity: Option<Entity>,
+    pub point: cgmath::Point3<f32>,
+    pub normal: cgmath::Vector3<f32>,
+    // from the ray's origin (the camera) to the point, in world units
+    pub distance: f32,
+}
+
+// x and y are in pixels from the top left of the window, the ray's direction is normalized
+pub fn screen_ray(camera: &camera::Camera, x: f32, y: f32, size: winit::dpi::PhysicalSize<u32>) -> Option<Ray>
+{
+    if size.width == 0 || size.height == 0
+    {
+        return None;
+    }
+    let inverse = camera.build_view_projection_matrix().invert()?;
+    // to normalized device coordinates, where y points up and depth goes from 0 (near) to 1 (far)
+    let ndc_x = x / size.width as f32 * 2.0 - 1.0;
+    let ndc_y = 1.0 - y / size.height as f32 * 2.0;
+    let unproject = |depth: f32|
+    {
+        let point = inverse * cgmath::Vector4::new(ndc_x, ndc_y, depth, 1.0);
+        cgmath::Point3::new(point.x / point.w, point.y / point.w, point.z / point.w)
+    };
+    let near = unproject(0.0);
+    let far = unproject(1.0);
+    Some(Ray::new(near, (far - near).normalize()))
+}
+
+// the entity whose scene::Transform drives a node (see systems::SceneNode)
+pub fn entity_for_node(world: &World, node: scene::NodeId) -> Option<Entity>
+{
+    world.query::<systems::SceneNode>()
+        .find(|(_, scene_node)| scene_node.0 == node)
+        .map(|(entity, _)| entity)
+}
+
+// the closest thing in the RenderList the ray hits, meshes without a shape (still loading) can't be hit
+pub fn pick<'a>(world: &World, ray: &Ray, alpha: f32, shape: impl Fn(assets::MeshHandle) -> Option<&'a mesh::MeshShape>) -> Option<PickHit>
+{
+    let render_list = world.get_resource::<systems::RenderList>()?;
+    let (drawable, hit) = render_list.drawables.iter()
+        .filter_map(|drawable| Some((drawable, intersect_drawable(drawable, shape(drawable.attachment.mesh)?, ray, alpha)?)))
+        .min_by(|(_, a), (_, b)| a.t.total_cmp(&b.t))?;
+    Some(make_hit(world, drawable, ray, hit))
+}
+
+// the same as pick, for a drawable that's already known to be under the cursor (like from the IdBuffer),
+// a ray that just grazes the edge can miss the triangles, the box is close enough then
+pub fn pick_drawable(world: &World, drawable: &systems::Drawable, shape: &mesh::MeshShape, ray: &Ray, alpha: f32) -> Option<PickHit>
+{
+    let hit = intersect_drawable(drawable, shape, ray, alpha).or_else(||
+    {
+        let inverse = drawable.interpolated(alpha).invert()?;
+        let local = shape.aabb.intersect_ray(&ray.transformed(&inverse))?;
+        Some(bounds::RayHit { t: local.t, normal: world_normal(&inverse, local.normal, ray) })
+    })?;
+    Some(make_hit(world, drawable, ray, hit))
+}
+
+// the hit in world space, t is the same in both spaces since the ray direction isn't normalized in between
+fn intersect_drawable(drawable: &systems::Drawable, shape: &mesh::MeshShape, ray: &Ray, alpha: f32) -> Option<bounds::RayHit>
+{
+    let inverse = drawable.interpolated(alpha).invert()?;
+    let hit = shape.intersect_ray(&ray.transformed(&inverse))?;
+    Some(bounds::RayHit { t: hit.t, normal: world_normal(&inverse, hit.normal, ray) })
+}
+
+// normals go through the inverse transpose, same as in the shader (see 'instance.rs')
+fn world_normal(inverse: &cgmath::Matrix4<f32>, normal: cgmath::Vector3<f32>, ray: &Ray) -> cgmath::Vector3<f32>
+{
+    let inverse3 = cgmath::Matrix3::from_cols(inverse.x.truncate(), inverse.y.truncate(), inverse.z.truncate());
+    let normal = (inverse3.transpose() * normal).normalize();
+    if normal.dot(ray.direction) > 0.0 { -normal } else { normal }
+}
+
+fn make_hit(world: &World, drawable: &systems::Drawable, ray: &Ray, hit: bounds::RayHit) -> PickHit
+{
+    PickHit
+    {
+        node: drawable.node,
+        entity: entity_for_node(world, drawable.node),
+        point: ray.at(hit.t),
+        normal: hit.normal,
+        distance: hit.t * ray.direction.magnitude(),
+    }
+}
+
+
+/*   <--------Id Buffer-------->   */
+// 0 is the background, the object at instance i is drawn as i + 1
+const ID_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
+const ID_ATTRIBUTES: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![12 => Uint32];
+
+pub struct IdBuffer
+{
+    pipeline: wgpu::RenderPipeline,
+    camera_bind_group: wgpu::BindGroup,
+    // 1, 2, 3... stepped per instance next to the instance buffer
+    ids: wgpu::Buffer,
+    id_capacity: usize,
+    target: wgpu::Texture,
+    target_view: wgpu::TextureView,
+    depth_texture: texture::Texture,
+    size: (u32, u32),
+    readback: wgpu::Buffer,
+}
+
+impl IdBuffer
+{
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, camera_buffer: &wgpu::Buffer) -> Self
+    {
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor
+        {
+            label: Some("Pick Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/pick.wgsl").into()),
+        });
+
+        // no layout, wgpu works it out from the shader (just the camera)
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor
+        {
+            label: Some("Pick Pipeline"),
+            layout: None,
+            vertex: wgpu::VertexState
+            {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[
+                    vertex::Vertex::desc(),
+                    instance::InstanceRaw::desc(),
+                    wgpu::VertexBufferLayout
+                    {
+                        array_stride: std::mem::size_of::<u32>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Instance,
+                        attributes: &ID_ATTRIBUTES,
+                    },
+                ],
+            },
+            fragment: Some(wgpu::FragmentState
+            {
+                module: &shader,
+                entry_point: "fs_main",
+                // integer targets can't be blended
+                targets: &[wgpu::ColorTargetState { format: ID_FORMAT, blend: None, write_mask: wgpu::ColorWrites::ALL }],
+            }),
+            // the same as the main pipeline, so the same pixels get covered
+            primitive: wgpu::PrimitiveState
+            {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState
+            {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor
+        {
+            label: Some("pick_camera_bind_group"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: camera_buffer.as_entire_binding() }],
+        });
+
+        let id_capacity = 16;
+        let (target, target_view) = Self::create_target(device, config);
+        Self
+        {
+            pipeline,
+            camera_bind_group,
+            ids: Self::create_ids(device, id_capacity),
+            id_capacity,
+            target,
+            target_view,
+            depth_texture: texture::Texture::create_depth_texture(device, config, 1, "pick_depth_texture"),
+            size: (config.width, config.height),
+            readback: device.create_buffer(&wgpu::BufferDescriptor
+            {
+                label: Some("Pick Readback Buffer"),
+                size: std::mem::size_of::<u32>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+        }
+    }
+
+    fn create_target(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> (wgpu::Texture, wgpu::TextureView)
+    {
+        let texture = device.create_texture(&wgpu::TextureDescriptor
+        {
+            label: Some("Pick Id Texture"),
+            size: wgpu::Extent3d { width: config.width, height: config.height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: ID_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        (texture, view)
+    }
+
+    fn create_ids(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer
+    {
+        use wgpu::util::DeviceExt;
+        let ids: Vec<u32> = (1..=capacity as u32).collect();
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor
+        {
+            label: Some("Pick Id Buffer"),
+            contents: bytemuck::cast_slice(&ids),
+            usage: wgpu::BufferUsages::VERTEX,
+        })
+    }
+
+    // makes room for the number of instances about to be drawn, and follows the window's size
+    pub fn prepare(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, instances: usize)
+    {
+        if instances > self.id_capacity
+        {
+            self.id_capacity = instances.next_power_of_two();
+            self.ids = Self::create_ids(device, self.id_capacity);
+        }
+        if self.size != (config.width, config.height)
+        {
+            let (target, target_view) = Self::create_target(device, config);
+            self.target = target;
+            self.target_view = target_view;
+            self.depth_texture = texture::Texture::create_depth_texture(device, config, 1, "pick_depth_texture");
+            self.size = (config.width, config.height);
+        }
+    }
+
+    // a pass with the pipeline, camera and ids set, the caller sets the mesh (slot 0)
+    // and instance (slot 1) buffers and draws the same way it does on screen
+    pub fn begin_pass<'a>(&'a self, encoder: &'a mut wgpu::CommandEncoder) -> wgpu::RenderPass<'a>
+    {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor
+        {
+            label: Some("Pick Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment
+            {
+                view: &self.target_view,
+                resolve_target: None,
+                ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT), store: true },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment
+            {
+                view: &self.depth_texture.view,
+                depth_ops: Some(wgpu::Operations { load: wgpu::LoadOp::Clear(1.0), store: true }),
+                stencil_ops: None,
+            }),
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        pass.set_vertex_buffer(2, self.ids.slice(..));
+        pass
+    }
+
+    // copies the id under a pixel out after the pass
+    pub fn copy_pixel(&self, encoder: &mut wgpu::CommandEncoder, x: u32, y: u32)
+    {
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture
+            {
+                texture: &self.target,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x, y, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer
+            {
+                buffer: &self.readback,
+                layout: wgpu::ImageDataLayout { offset: 0, bytes_per_row: None, rows_per_image: None },
+            },
+            wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 },
+        );
+    }
+
+    // waits for the gpu to finish the copy, Some(instance index) or None for the background
+    pub fn read(&self, device: &wgpu::Device) -> Option<usize>
+    {
+        let slice = self.readback.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        if let Err(e) = pollster::block_on(mapping)
+        {
+            log::error!("could not read the pick buffer: {}", e);
+            return None;
+        }
+        let id =
+        {
+            let bytes = slice.get_mapped_range();
+            u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+        };
+        self.readback.unmap();
+        (id as usize).checked_sub(1)
+    }
+
+    // whether a pixel is inside the id texture
+    pub fn contains(&self, x: u32, y: u32) -> bool
+    {
+        x < self.size.0 && y < self.size.1
+    }
+}
@@ -0,0 +1,571 @@
+use std::
+{
+    collections::VecDeque,
+    future::Future,
+    path::Path,
+    pin::Pin,
+    task::{ self, Poll, Waker },
+    time::Instant,
+};
+use anyhow::*;
+
+
+/*
+    Where the time in a frame goes. CPU scopes are timed around the code
+    they cover, and can nest:
+
+        profiler.begin_scope("draw world");
+        ...
+        profiler.end_scope();
+
+    GPU spans are timestamps written around passes into the command
+    encoder, when the adapter can do timestamp queries. They're read back
+    a couple of frames later (without waiting on the gpu) and filed under
+    the frame that drew them.
+
+    The last HISTORY frames are kept for averages and percentiles, the
+    graph in the profiler window (F6, see 'state.rs'), and exporting as a
+    Chrome trace (open it in chrome://tracing or https://ui.perfetto.dev).
+    A frame runs from the end of one State::end_frame to the end of the
+    next, so it covers the simulation ticks that led up to it too.
+*/
+
+// frames kept around
+const HISTORY: usize = 300;
+// gpu spans per frame, each takes two timestamps
+const MAX_GPU_SPANS: u32 = 32;
+// frames of gpu timings that can be in flight at once
+const READBACKS: usize = 3;
+
+
+// one timed scope, in milliseconds from the start of its frame
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ScopeRecord
+{
+    pub name: &'static str,
+    // 0 for scopes that aren't inside another one
+    pub depth: usize,
+    pub start: f64,
+    pub duration: f64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FrameProfile
+{
+    pub index: u64,
+    // milliseconds since the profiler started
+    pub start: f64,
+    pub duration: f64,
+    pub cpu: Vec<ScopeRecord>,
+    // empty until the gpu's timings come back, or when there aren't any
+    pub gpu: Vec<ScopeRecord>,
+}
+
+impl FrameProfile
+{
+    // the frame itself doesn't have any
+    pub fn records(&self, timeline: Timeline) -> &[ScopeRecord]
+    {
+        match timeline
+        {
+            Timeline::Frame => &[],
+            Timeline::Cpu => &self.cpu,
+            Timeline::Gpu => &self.gpu,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Timeline
+{
+    Frame,
+    Cpu,
+    Gpu,
+}
+
+// how long a scope took per frame over the history, in milliseconds
+// (a scope that runs more than once in a frame counts the total)
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScopeStats
+{
+    pub name: &'static str,
+    pub timeline: Timeline,
+    pub average: f64,
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+
+/*   <--------Profiler-------->   */
+pub struct Profiler
+{
+    start: Instant,
+    frame_start: Instant,
+    // scopes that have begun and not ended yet, innermost last
+    open: Vec<(&'static str, Instant)>,
+    current: FrameProfile,
+    history: VecDeque<FrameProfile>,
+    gpu: Option<GpuTimer>,
+}
+
+impl Profiler
+{
+    // gpu timings need the device to have been made with Features::TIMESTAMP_QUERY
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self
+    {
+        let gpu = if device.features().contains(wgpu::Features::TIMESTAMP_QUERY)
+        {
+            Some(GpuTimer::new(device, queue))
+        }
+        else
+        {
+            log::info!("no timestamp queries, the profiler only has cpu timings");
+            None
+        };
+        let start = Instant::now();
+        Self { start, frame_start: start, open: Vec::new(), current: FrameProfile::default(), history: VecDeque::new(), gpu }
+    }
+
+    pub fn has_gpu_timings(&self) -> bool
+    {
+        self.gpu.is_some()
+    }
+
+    pub fn begin_scope(&mut self, name: &'static str)
+    {
+        self.open.push((name, Instant::now()));
+    }
+
+    // ends the scope begun last
+    pub fn end_scope(&mut self)
+    {
+        if let Some((name, started)) = self.open.pop()
+        {
+            self.current.cpu.push(ScopeRecord
+            {
+                name,
+                depth: self.open.len(),
+                start: milliseconds(started - self.frame_start),
+                duration: milliseconds(started.elapsed()),
+            });
+        }
+    }
+
+    // a timestamp before a pass, or anything else recorded into the encoder
+    pub fn begin_gpu_span(&mut self, encoder: &mut wgpu::CommandEncoder, name: &'static str)
+    {
+        if let Some(gpu) = &mut self.gpu
+        {
+            gpu.begin(encoder, name);
+        }
+    }
+
+    pub fn end_gpu_span(&mut self, encoder: &mut wgpu::CommandEncoder)
+    {
+        if let Some(gpu) = &mut self.gpu
+        {
+            gpu.end(encoder);
+        }
+    }
+
+    // copies this frame's timestamps out, just before the encoder is finished
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder)
+    {
+        if let Some(gpu) = &mut self.gpu
+        {
+            gpu.resolve(encoder, self.current.index);
+        }
+    }
+
+    // after the frame is submitted, files it away and starts the next
+    pub fn end_frame(&mut self, device: &wgpu::Device)
+    {
+        // a scope left open is cut off at the end of the frame
+        while !self.open.is_empty()
+        {
+            self.end_scope();
+        }
+
+        let now = Instant::now();
+        let index = self.current.index;
+        self.current.start = milliseconds(self.frame_start - self.start);
+        self.current.duration = milliseconds(now - self.frame_start);
+        self.frame_start = now;
+        let next = FrameProfile { index: index + 1, ..FrameProfile::default() };
+        self.history.push_back(std::mem::replace(&mut self.current, next));
+        while self.history.len() > HISTORY
+        {
+            self.history.pop_front();
+        }
+
+        if let Some(gpu) = &mut self.gpu
+        {
+            gpu.submitted();
+            for (frame, spans) in gpu.collect(device)
+            {
+                if let Some(profile) = self.history.iter_mut().find(|profile| profile.index == frame)
+                {
+                    profile.gpu = spans;
+                }
+            }
+        }
+    }
+
+    // oldest first
+    pub fn history(&self) -> impl Iterator<Item = &FrameProfile> + '_
+    {
+        self.history.iter()
+    }
+
+    // the whole frame first, then every scope by name, in the order they first ran
+    pub fn stats(&self) -> Vec<ScopeStats>
+    {
+        let mut stats = vec![ScopeStats::from_samples("frame", Timeline::Frame, self.history.iter().map(|frame| frame.duration).collect())];
+        for timeline in [Timeline::Cpu, Timeline::Gpu]
+        {
+            let mut names: Vec<&'static str> = Vec::new();
+            for record in self.history.iter().flat_map(|frame| frame.records(timeline))
+            {
+                if !names.contains(&record.name)
+                {
+                    names.push(record.name);
+                }
+            }
+            for name in names
+            {
+                // frames without gpu timings (yet) don't count as zero
+                let samples = self.history.iter()
+                    .map(|frame| frame.records(timeline))
+                    .filter(|records| !records.is_empty())
+                    .map(|records| records.iter().filter(|record| record.name == name).map(|record| record.duration).sum())
+                    .collect();
+                stats.push(ScopeStats::from_samples(name, timeline, samples));
+            }
+        }
+        stats
+    }
+
+    // the history as Chrome's trace event format, cpu scopes on one track and gpu spans on another
+    pub fn chrome_trace(&self) -> serde_json::Value
+    {
+        let event = |name: &str, category: &str, thread: u32, start: f64, duration: f64| serde_json::json!(
+        {
+            "name": name,
+            "cat": category,
+            "ph": "X",
+            // microseconds
+            "ts": start * 1000.0,
+            "dur": duration * 1000.0,
+            "pid": 1,
+            "tid": thread,
+        });
+
+        let mut events = vec![
+            serde_json::json!({ "name": "thread_name", "ph": "M", "pid": 1, "tid": 1, "args": { "name": "cpu" } }),
+            serde_json::json!({ "name": "thread_name", "ph": "M", "pid": 1, "tid": 2, "args": { "name": "gpu" } }),
+        ];
+        for frame in &self.history
+        {
+            events.push(event(&format!("frame {}", frame.index), "frame", 1, frame.start, frame.duration));
+            for record in &frame.cpu
+            {
+                events.push(event(record.name, "cpu", 1, frame.start + record.start, record.duration));
+            }
+            // the gpu's clock isn't the cpu's, so its spans are lined up with the start of their frame
+            for record in &frame.gpu
+            {
+                events.push(event(record.name, "gpu", 2, frame.start + record.start, record.duration));
+            }
+        }
+        serde_json::json!({ "traceEvents": events, "displayTimeUnit": "ms" })
+    }
+
+    pub fn export_chrome_trace(&self, path: &Path) -> Result<()>
+    {
+        let json = serde_json::to_string(&self.chrome_trace())?;
+        std::fs::write(path, json).with_context(|| format!("couldn't write {}", path.display()))?;
+        log::info!("wrote a trace of {} frames to {}", self.history.len(), path.display());
+        Ok(())
+    }
+
+    // the profiler window: a graph of frame times and a table of the scopes
+    pub fn show(&self, ctx: &egui::Context)
+    {
+        egui::Window::new("Profiler").default_pos((10.0, 300.0)).show(ctx, |ui|
+        {
+            use egui::plot::{ HLine, Line, Plot, Value, Values };
+            let frames = Line::new(Values::from_values_iter(self.history.iter().map(|frame| Value::new(frame.index as f64, frame.duration)))).name("cpu");
+            let gpu = Line::new(Values::from_values_iter(self.history.iter()
+                .filter(|frame| !frame.gpu.is_empty())
+                .map(|frame| Value::new(frame.index as f64, frame.gpu.iter().map(|record| record.duration).sum::<f64>()))))
+                .name("gpu");
+            Plot::new("frame times")
+                .height(120.0)
+                .include_y(0.0)
+                .include_y(1000.0 / 60.0)
+                .allow_drag(false)
+                .allow_zoom(false)
+                .show_x(false)
+                .show(ui, |plot|
+                {
+                    plot.hline(HLine::new(1000.0 / 60.0).name("60 fps"));
+                    plot.line(frames);
+                    plot.line(gpu);
+                });
+
+            if !self.has_gpu_timings()
+            {
+                ui.label("no gpu timings on this adapter");
+            }
+            egui::Grid::new("profiler stats").striped(true).show(ui, |ui|
+            {
+                for heading in ["", "avg ms", "p50", "p95", "p99", "max"]
+                {
+                    ui.strong(heading);
+                }
+                ui.end_row();
+                for stat in self.stats()
+                {
+                    let name = match stat.timeline
+                    {
+                        Timeline::Frame => String::from(stat.name),
+                        Timeline::Cpu => format!("cpu: {}", stat.name),
+                        Timeline::Gpu => format!("gpu: {}", stat.name),
+                    };
+                    ui.label(name);
+                    for value in [stat.average, stat.p50, stat.p95, stat.p99, stat.max]
+                    {
+                        ui.monospace(format!("{:.2}", value));
+                    }
+                    ui.end_row();
+                }
+            });
+
+            if ui.button("export chrome trace").clicked()
+            {
+                let path = std::path::PathBuf::from(format!("trace-{}.json", self.current.index));
+                if let Err(e) = self.export_chrome_trace(&path)
+                {
+                    log::error!("{:#}", e);
+                }
+            }
+        });
+    }
+}
+
+impl ScopeStats
+{
+    fn from_samples(name: &'static str, timeline: Timeline, mut samples: Vec<f64>) -> Self
+    {
+        samples.sort_by(f64::total_cmp);
+        let average = if samples.is_empty() { 0.0 } else { samples.iter().sum::<f64>() / samples.len() as f64 };
+        Self
+        {
+            name,
+            timeline,
+            average,
+            p50: percentile(&samples, 50.0),
+            p95: percentile(&samples, 95.0),
+            p99: percentile(&samples, 99.0),
+            max: samples.last().copied().unwrap_or(0.0),
+        }
+    }
+}
+
+// nearest rank, samples have to be sorted
+fn percentile(samples: &[f64], percent: f64) -> f64
+{
+    if samples.is_empty()
+    {
+        return 0.0;
+    }
+    let rank = (percent / 100.0 * samples.len() as f64).ceil() as usize;
+    samples[rank.clamp(1, samples.len()) - 1]
+}
+
+fn milliseconds(duration: std::time::Duration) -> f64
+{
+    duration.as_secs_f64() * 1000.0
+}
+
+
+/*   <--------GPU Timestamps-------->   */
+type MapFuture = Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>> + Send>>;
+
+struct Readback
+{
+    buffer: wgpu::Buffer,
+    frame: u64,
+    names: Vec<&'static str>,
+    // Some while the buffer is on its way back from the gpu
+    mapping: Option<MapFuture>,
+    // resolved into this frame, waiting for the submit to map it
+    resolved: bool,
+}
+
+struct GpuTimer
+{
+    query_set: wgpu::QuerySet,
+    // nanoseconds per timestamp tick
+    period: f64,
+    // this frame's spans, and which of them are still open
+    names: Vec<&'static str>,
+    open: Vec<u32>,
+    readbacks: Vec<Readback>,
+}
+
+impl GpuTimer
+{
+    fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self
+    {
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor
+        {
+            label: Some("Profiler Timestamps"),
+            ty: wgpu::QueryType::Timestamp,
+            count: MAX_GPU_SPANS * 2,
+        });
+        let readbacks = (0..READBACKS)
+            .map(|_| Readback
+            {
+                buffer: device.create_buffer(&wgpu::BufferDescriptor
+                {
+                    label: Some("Profiler Readback Buffer"),
+                    size: (MAX_GPU_SPANS * 2 * wgpu::QUERY_SIZE) as wgpu::BufferAddress,
+                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                    mapped_at_creation: false,
+                }),
+                frame: 0,
+                names: Vec::new(),
+                mapping: None,
+                resolved: false,
+            })
+            .collect();
+        Self { query_set, period: queue.get_timestamp_period() as f64, names: Vec::new(), open: Vec::new(), readbacks }
+    }
+
+    fn begin(&mut self, encoder: &mut wgpu::CommandEncoder, name: &'static str)
+    {
+        // past the last query the span just isn't timed
+        let span = self.names.len() as u32;
+        if span < MAX_GPU_SPANS
+        {
+            encoder.write_timestamp(&self.query_set, span * 2);
+            self.names.push(name);
+            self.open.push(span);
+        }
+        else
+        {
+            self.open.push(u32::MAX);
+        }
+    }
+
+    fn end(&mut self, encoder: &mut wgpu::CommandEncoder)
+    {
+        match self.open.pop()
+        {
+            Some(span) if span != u32::MAX => encoder.write_timestamp(&self.query_set, span * 2 + 1),
+            _ => {}
+        }
+    }
+
+    fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder, frame: u64)
+    {
+        // close whatever was left open, so every span has both of its timestamps
+        while !self.open.is_empty()
+        {
+            self.end(encoder);
+        }
+        let names = std::mem::take(&mut self.names);
+        if names.is_empty()
+        {
+            return;
+        }
+        // when every readback is still in flight this frame goes untimed, rather than waiting on the gpu
+        if let Some(readback) = self.readbacks.iter_mut().find(|readback| readback.mapping.is_none() && !readback.resolved)
+        {
+            encoder.resolve_query_set(&self.query_set, 0..names.len() as u32 * 2, &readback.buffer, 0);
+            readback.frame = frame;
+            readback.names = names;
+            readback.resolved = true;
+        }
+    }
+
+    // the frame's commands are on their way, its timestamps can be mapped once they're done
+    fn submitted(&mut self)
+    {
+        for readback in self.readbacks.iter_mut().filter(|readback| readback.resolved)
+        {
+            readback.resolved = false;
+            let size = (readback.names.len() as u32 * 2 * wgpu::QUERY_SIZE) as wgpu::BufferAddress;
+            readback.mapping = Some(Box::pin(readback.buffer.slice(..size).map_async(wgpu::MapMode::Read)));
+        }
+    }
+
+    // the spans of every frame the gpu has finished since last time
+    fn collect(&mut self, device: &wgpu::Device) -> Vec<(u64, Vec<ScopeRecord>)>
+    {
+        device.poll(wgpu::Maintain::Poll);
+        let mut context = task::Context::from_waker(Waker::noop());
+        let mut frames = Vec::new();
+        for readback in &mut self.readbacks
+        {
+            let ready = match readback.mapping.as_mut().map(|mapping| mapping.as_mut().poll(&mut context))
+            {
+                Some(Poll::Ready(result)) => result,
+                _ => continue,
+            };
+            readback.mapping = None;
+            if let Err(e) = ready
+            {
+                log::warn!("lost a frame of gpu timings: {}", e);
+                continue;
+            }
+
+            let size = (readback.names.len() as u32 * 2 * wgpu::QUERY_SIZE) as wgpu::BufferAddress;
+            let timestamps: Vec<u64> =
+            {
+                let bytes = readback.buffer.slice(..size).get_mapped_range();
+                bytes.chunks_exact(8).map(|timestamp| u64::from_ne_bytes(timestamp.try_into().unwrap())).collect()
+            };
+            readback.buffer.unmap();
+
+            // in milliseconds from the frame's first timestamp
+            let first = timestamps[0];
+            let to_milliseconds = |ticks: u64| ticks as f64 * self.period / 1_000_000.0;
+            let spans = readback.names.iter().zip(timestamps.chunks_exact(2))
+                .map(|(name, pair)| ScopeRecord
+                {
+                    name,
+                    depth: 0,
+                    start: to_milliseconds(pair[0].saturating_sub(first)),
+                    duration: to_milliseconds(pair[1].saturating_sub(pair[0])),
+                })
+                .collect();
+            frames.push((readback.frame, spans));
+        }
+        frames
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn percentiles_are_the_nearest_rank()
+    {
+        assert_eq!(percentile(&[], 50.0), 0.0);
+        assert_eq!(percentile(&[4.0], 0.0), 4.0);
+        assert_eq!(percentile(&[4.0], 99.0), 4.0);
+
+        let samples: Vec<f64> = (1..=10).map(f64::from).collect();
+        assert_eq!(percentile(&samples, 0.0), 1.0);
+        assert_eq!(percentile(&samples, 50.0), 5.0);
+        assert_eq!(percentile(&samples, 91.0), 10.0);
+        assert_eq!(percentile(&samples, 100.0), 10.0);
+    }
+}
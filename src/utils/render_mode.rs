@@ -0,0 +1,132 @@
+/*
+    Ways to draw the world other than lit and textured, for tracking down
+    rendering problems. F4 goes through the modes, F5 turns back face
+    culling off and on (see 'input.rs').
+
+    Every combination is its own render pipeline, State makes them the first
+    time they're used. The modes are different fragment entry points in
+    'shader.wgsl'. Wireframe uses the gpu's line drawing when the device has
+    POLYGON_MODE_LINE, and draws the edges from barycentric coordinates
+    (vs_wireframe/fs_wireframe) when it doesn't.
+
+    Each pass over the world (opaque, cutout, then blended, see
+    scene::AlphaMode) has its own pipeline too, blended meshes don't write
+    depth so the ones behind them still show through.
+*/
+
+use super::scene::AlphaMode;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RenderMode
+{
+    // the normal textured and lit look
+    Lit,
+    Wireframe,
+    // world space normals as colors
+    Normals,
+    // a checkerboard over the texture coordinates
+    UvChecker,
+    // distance from the camera, white is close
+    Depth,
+}
+
+impl RenderMode
+{
+    pub const ALL: [RenderMode; 5] = [RenderMode::Lit, RenderMode::Wireframe, RenderMode::Normals, RenderMode::UvChecker, RenderMode::Depth];
+
+    // the one after this, back to Lit after the last
+    pub fn next(self) -> Self
+    {
+        let index = Self::ALL.iter().position(|mode| *mode == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    pub fn name(self) -> &'static str
+    {
+        match self
+        {
+            RenderMode::Lit => "lit",
+            RenderMode::Wireframe => "wireframe",
+            RenderMode::Normals => "normals",
+            RenderMode::UvChecker => "uv checker",
+            RenderMode::Depth => "depth",
+        }
+    }
+}
+
+// everything that decides which pipeline the world is drawn with
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RenderSettings
+{
+    pub mode: RenderMode,
+    // back face culling
+    pub culling: bool,
+    // only matters for wireframe: draw it with POLYGON_MODE_LINE, or with the barycentric shader
+    pub line_mode: bool,
+    // which of the world's passes this is for, State::render_settings always says Opaque
+    pub alpha: AlphaMode,
+}
+
+impl Default for RenderSettings
+{
+    fn default() -> Self
+    {
+        Self { mode: RenderMode::Lit, culling: true, line_mode: false, alpha: AlphaMode::Opaque }
+    }
+}
+
+impl RenderSettings
+{
+    // whether meshes have to be drawn from their WireframeMesh (see 'mesh.rs') instead of their index buffer
+    pub fn barycentric_wireframe(&self) -> bool
+    {
+        self.mode == RenderMode::Wireframe && !self.line_mode
+    }
+
+    pub fn polygon_mode(&self) -> wgpu::PolygonMode
+    {
+        if self.mode == RenderMode::Wireframe && self.line_mode { wgpu::PolygonMode::Line } else { wgpu::PolygonMode::Fill }
+    }
+
+    pub fn cull_mode(&self) -> Option<wgpu::Face>
+    {
+        if self.culling { Some(wgpu::Face::Back) } else { None }
+    }
+
+    // the same settings for a different pass
+    pub fn with_alpha(self, alpha: AlphaMode) -> Self
+    {
+        Self { alpha, ..self }
+    }
+
+    pub fn blend_state(&self) -> wgpu::BlendState
+    {
+        if self.alpha == AlphaMode::Blend { wgpu::BlendState::ALPHA_BLENDING } else { wgpu::BlendState::REPLACE }
+    }
+
+    // blended meshes still hide behind opaque ones, but not behind each other
+    pub fn depth_write(&self) -> bool
+    {
+        self.alpha != AlphaMode::Blend
+    }
+
+    // entry points in 'shader.wgsl'
+    pub fn vertex_entry(&self) -> &'static str
+    {
+        if self.barycentric_wireframe() { "vs_wireframe" } else { "vs_main" }
+    }
+
+    pub fn fragment_entry(&self) -> &'static str
+    {
+        match self.mode
+        {
+            RenderMode::Lit if self.alpha == AlphaMode::Cutout => "fs_cutout",
+            RenderMode::Lit => "fs_main",
+            RenderMode::Wireframe if self.line_mode => "fs_white",
+            RenderMode::Wireframe => "fs_wireframe",
+            RenderMode::Normals => "fs_normals",
+            RenderMode::UvChecker => "fs_uv_checker",
+            RenderMode::Depth => "fs_depth",
+        }
+    }
+}
@@ -0,0 +1,434 @@
+use std::
+{
+    fmt,
+    path::Path,
+    time::Duration,
+};
+use serde::{ Deserialize, Serialize };
+use winit::event::
+{
+    DeviceId, ElementState, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta, TouchPhase,
+    VirtualKeyCode, WindowEvent,
+};
+
+use super::
+{
+    assets,
+    camera,
+    data_file::{ self, DataError },
+    ecs::World,
+    gamepad::GamepadEvent,
+    input,
+    scene,
+    simulation::Simulation,
+    systems,
+};
+
+
+/*
+    Input recording and deterministic replay.
+
+    While recording, every input the simulation gets is saved with the tick
+    it arrived on, along with a checksum of the world every few ticks.
+    Replaying loads the same level into a Simulation without a window or a
+    gpu, feeds it the same inputs on the same ticks, and compares checksums
+    to find the first tick where things went differently:
+
+        cargo run -- --record replay.ron
+        cargo run -- --replay replay.ron
+*/
+
+/*   <--------Recorded Inputs-------->   */
+// the parts of a window event the simulation cares about
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum InputEvent
+{
+    Key { key: VirtualKeyCode, pressed: bool },
+    Mouse { button: MouseButton, pressed: bool },
+    // in lines, touchpad pixels are converted the same way 'input.rs' does
+    Scroll { lines: f32 },
+    Focus(bool),
+    // the window was resized
+    Aspect(f32),
+    Gamepad(GamepadEvent),
+}
+
+fn element_state(pressed: bool) -> ElementState
+{
+    if pressed { ElementState::Pressed } else { ElementState::Released }
+}
+
+impl InputEvent
+{
+    pub fn from_window_event(event: &WindowEvent) -> Option<Self>
+    {
+        match event
+        {
+            WindowEvent::KeyboardInput { input: KeyboardInput { state, virtual_keycode: Some(key), .. }, .. } =>
+                Some(InputEvent::Key { key: *key, pressed: *state == ElementState::Pressed }),
+            WindowEvent::MouseInput { state, button, .. } =>
+                Some(InputEvent::Mouse { button: *button, pressed: *state == ElementState::Pressed }),
+            WindowEvent::MouseWheel { .. } => Some(InputEvent::Scroll { lines: input::scroll_lines(event) }),
+            WindowEvent::Focused(focused) => Some(InputEvent::Focus(*focused)),
+            _ => None,
+        }
+    }
+
+    // the window event to play back, None for the ones that aren't window events
+    #[allow(deprecated)]
+    pub fn to_window_event(self) -> Option<WindowEvent<'static>>
+    {
+        // never handed to winit, the simulation doesn't look at device ids
+        let device_id = unsafe { DeviceId::dummy() };
+        match self
+        {
+            InputEvent::Key { key, pressed } => Some(WindowEvent::KeyboardInput
+            {
+                device_id,
+                input: KeyboardInput
+                {
+                    scancode: 0,
+                    state: element_state(pressed),
+                    virtual_keycode: Some(key),
+                    modifiers: ModifiersState::empty(),
+                },
+                is_synthetic: false,
+            }),
+            InputEvent::Mouse { button, pressed } => Some(WindowEvent::MouseInput
+            {
+                device_id,
+                state: element_state(pressed),
+                button,
+                modifiers: ModifiersState::empty(),
+            }),
+            InputEvent::Scroll { lines } => Some(WindowEvent::MouseWheel
+            {
+                device_id,
+                delta: MouseScrollDelta::LineDelta(0.0, lines),
+                phase: TouchPhase::Moved,
+                modifiers: ModifiersState::empty(),
+            }),
+            InputEvent::Focus(focused) => Some(WindowEvent::Focused(focused)),
+            InputEvent::Aspect(_) | InputEvent::Gamepad(_) => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TimedInput
+{
+    // ticks the level had run when the input came in
+    pub tick: u64,
+    pub event: InputEvent,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Checksum
+{
+    // taken right after this tick
+    pub tick: u64,
+    pub value: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Recording
+{
+    // scene file, relative to the asset root
+    pub level: String,
+    pub aspect: f32,
+    // the player's bindings at the time, so remapping later doesn't break old recordings
+    pub bindings: input::Bindings,
+    // buttons that were already down when the level started
+    #[serde(default)]
+    pub start: input::InputSnapshot,
+    // exact length of a tick, in nanoseconds
+    pub tick_nanos: u64,
+    pub ticks: u64,
+    pub inputs: Vec<TimedInput>,
+    pub checksums: Vec<Checksum>,
+}
+
+impl Recording
+{
+    // .ron or .json, see 'data_file.rs'
+    pub fn load(path: &Path) -> Result<Self, DataError>
+    {
+        data_file::load(path)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), DataError>
+    {
+        data_file::save(self, path)
+    }
+}
+
+
+/*   <--------Recorder-------->   */
+// lives in the Simulation while recording
+pub struct Recorder
+{
+    checksum_interval: u64,
+    recording: Option<Recording>,
+}
+
+impl Recorder
+{
+    pub fn new(checksum_interval: u64) -> Self
+    {
+        Self { checksum_interval: checksum_interval.max(1), recording: None }
+    }
+
+    // starts over on a freshly loaded level
+    pub fn begin(&mut self, level: &str, aspect: f32, input: &input::Input)
+    {
+        self.recording = Some(Recording
+        {
+            level: level.to_string(),
+            aspect,
+            bindings: input.bindings().clone(),
+            start: input.snapshot(),
+            tick_nanos: 0,
+            ticks: 0,
+            inputs: Vec::new(),
+            checksums: Vec::new(),
+        });
+    }
+
+    pub fn record(&mut self, tick: u64, event: InputEvent)
+    {
+        if let Some(recording) = &mut self.recording
+        {
+            recording.inputs.push(TimedInput { tick, event });
+        }
+    }
+
+    pub fn record_window_event(&mut self, tick: u64, event: &WindowEvent)
+    {
+        if let Some(event) = InputEvent::from_window_event(event)
+        {
+            self.record(tick, event);
+        }
+    }
+
+    pub fn end_tick(&mut self, world: &World, tick: Duration)
+    {
+        if let Some(recording) = &mut self.recording
+        {
+            recording.tick_nanos = tick.as_nanos() as u64;
+            recording.ticks = world.resource::<systems::Time>().tick;
+            if recording.ticks % self.checksum_interval == 0
+            {
+                recording.checksums.push(Checksum { tick: recording.ticks, value: checksum(world) });
+            }
+        }
+    }
+
+    pub fn finish(self) -> Option<Recording>
+    {
+        self.recording
+    }
+}
+
+
+/*   <--------Checksums-------->   */
+// FNV-1a, unlike std's hasher it's guaranteed to give the same result in every build
+struct Fnv(u64);
+
+impl Fnv
+{
+    fn new() -> Self
+    {
+        Fnv(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8])
+    {
+        for byte in bytes
+        {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn write_floats(&mut self, floats: &[f32])
+    {
+        for float in floats
+        {
+            self.write(&float.to_bits().to_le_bytes());
+        }
+    }
+}
+
+// everything the simulation moves: the tick, the transforms and the camera
+pub fn checksum(world: &World) -> u64
+{
+    let mut hash = Fnv::new();
+    hash.write(&world.resource::<systems::Time>().tick.to_le_bytes());
+    for (entity, transform) in world.query::<scene::Transform>()
+    {
+        hash.write(&(entity.index() as u64).to_le_bytes());
+        let rotation = transform.rotation;
+        hash.write_floats(&[
+            transform.translation.x, transform.translation.y, transform.translation.z,
+            rotation.v.x, rotation.v.y, rotation.v.z, rotation.s,
+            transform.scale.x, transform.scale.y, transform.scale.z,
+        ]);
+    }
+    let camera = world.resource::<camera::Camera>();
+    hash.write_floats(&[
+        camera.eye.x, camera.eye.y, camera.eye.z,
+        camera.target.x, camera.target.y, camera.target.z,
+        camera.up.x, camera.up.y, camera.up.z,
+    ]);
+    hash.0
+}
+
+
+/*   <--------Replay-------->   */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Divergence
+{
+    pub tick: u64,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ReplayReport
+{
+    pub ticks: u64,
+    // how many checksums matched
+    pub matched: usize,
+    // the first checksum that didn't, the replay stops there
+    pub divergence: Option<Divergence>,
+}
+
+impl fmt::Display for ReplayReport
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self.divergence
+        {
+            Some(divergence) => write!(f, "replay diverged at tick {} (expected checksum {:016x}, got {:016x}) after {} matching checksums",
+                divergence.tick, divergence.expected, divergence.actual, self.matched),
+            None => write!(f, "replay matched: {} ticks, {} checksums", self.ticks, self.matched),
+        }
+    }
+}
+
+// plays a recording back without a window, asset_root is where the level's scene file is
+pub fn replay(recording: &Recording, asset_root: &Path) -> Result<ReplayReport, DataError>
+{
+    let mut assets = assets::HeadlessAssets::new(asset_root);
+    let mut simulation = Simulation::new(recording.bindings.clone());
+    simulation.load_level(&mut assets, &recording.level, recording.aspect)?;
+    simulation.world_mut().resource_mut::<input::Input>().restore(&recording.start);
+
+    let tick = Duration::from_nanos(recording.tick_nanos);
+    let mut inputs = recording.inputs.iter().peekable();
+    let mut checksums = recording.checksums.iter().peekable();
+    let mut report = ReplayReport { ticks: 0, matched: 0, divergence: None };
+
+    for done in 0..recording.ticks
+    {
+        while let Some(input) = inputs.next_if(|input| input.tick <= done)
+        {
+            match input.event
+            {
+                InputEvent::Aspect(aspect) => simulation.set_aspect(aspect),
+                InputEvent::Gamepad(event) => simulation.gamepad_event(event),
+                event => if let Some(event) = event.to_window_event()
+                {
+                    simulation.input(&event);
+                },
+            }
+        }
+
+        simulation.update(tick);
+        report.ticks = done + 1;
+
+        if let Some(expected) = checksums.next_if(|checksum| checksum.tick == report.ticks)
+        {
+            let actual = simulation.checksum();
+            if actual != expected.value
+            {
+                report.divergence = Some(Divergence { tick: expected.tick, expected: expected.value, actual });
+                break;
+            }
+            report.matched += 1;
+        }
+    }
+    Ok(report)
+}
+
+pub fn replay_file(path: &Path, asset_root: &Path) -> Result<ReplayReport, DataError>
+{
+    replay(&Recording::load(path)?, asset_root)
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const TICK: Duration = Duration::from_nanos(1_000_000_000 / 60);
+    const ASSETS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/assets");
+    // when the recording below starts orbiting the camera
+    const ORBIT_TICK: u64 = 5;
+
+    // 60 ticks of orbiting and zooming the main level, with a checksum after every tick
+    fn record() -> Recording
+    {
+        let mut assets = assets::HeadlessAssets::new(ASSETS);
+        let mut simulation = Simulation::new(input::Bindings::default());
+        simulation.load_level(&mut assets, "scenes/main.ron", 1.0).unwrap();
+        simulation.start_recording(1);
+
+        let presses = [
+            (ORBIT_TICK, VirtualKeyCode::D, true),
+            (20, VirtualKeyCode::D, false),
+            (30, VirtualKeyCode::W, true),
+            (45, VirtualKeyCode::W, false),
+        ];
+        for tick in 0..60
+        {
+            for (_, key, pressed) in presses.iter().filter(|(at, _, _)| *at == tick)
+            {
+                let event = InputEvent::Key { key: *key, pressed: *pressed }.to_window_event().unwrap();
+                simulation.input(&event);
+            }
+            simulation.update(TICK);
+        }
+        simulation.stop_recording().unwrap()
+    }
+
+    #[test]
+    fn replaying_a_recording_gives_the_same_checksums()
+    {
+        let recording = record();
+        assert_eq!(recording.ticks, 60);
+        assert_eq!(recording.checksums.len(), 60);
+        assert_eq!(recording.inputs.len(), 4);
+
+        let report = replay(&recording, Path::new(ASSETS)).unwrap();
+        assert_eq!(report, ReplayReport { ticks: 60, matched: 60, divergence: None });
+    }
+
+    #[test]
+    fn a_changed_input_diverges_on_the_tick_after_it()
+    {
+        let mut recording = record();
+        // orbiting the other way from the same tick on
+        recording.inputs[0].event = InputEvent::Key { key: VirtualKeyCode::A, pressed: true };
+        recording.inputs[1].event = InputEvent::Key { key: VirtualKeyCode::A, pressed: false };
+
+        let report = replay(&recording, Path::new(ASSETS)).unwrap();
+        let divergence = report.divergence.unwrap();
+        assert_eq!(divergence.tick, ORBIT_TICK + 1);
+        assert_ne!(divergence.expected, divergence.actual);
+        assert_eq!(report.matched, ORBIT_TICK as usize);
+        assert_eq!(report.ticks, ORBIT_TICK + 1);
+    }
+}
@@ -0,0 +1,442 @@
+use cgmath::
+{
+    InnerSpace,
+    One,
+    SquareMatrix,
+    VectorSpace,
+    Zero,
+};
+use serde::{ Deserialize, Serialize };
+
+use super::
+{
+    assets,
+    camera,
+    light,
+};
+
+
+/*
+    The scene graph is a tree of nodes. Every node has a transform relative
+    to its parent (the local transform) and a cached world matrix, which is
+    the parent's world matrix times the local one. World matrices are only
+    recomputed for nodes whose local transform (or an ancestor's) changed.
+*/
+
+/*   <--------Transform-------->   */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform
+{
+    pub translation: cgmath::Vector3<f32>,
+    pub rotation: cgmath::Quaternion<f32>,
+    pub scale: cgmath::Vector3<f32>,
+}
+
+impl Default for Transform
+{
+    fn default() -> Self
+    {
+        Self
+        {
+            translation: cgmath::Vector3::zero(),
+            rotation: cgmath::Quaternion::one(),
+            scale: cgmath::Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl Transform
+{
+    pub fn from_translation(translation: cgmath::Vector3<f32>) -> Self
+    {
+        Self { translation, ..Default::default() }
+    }
+
+    // scale first, then rotate, then move
+    pub fn to_matrix(self) -> cgmath::Matrix4<f32>
+    {
+        cgmath::Matrix4::from_translation(self.translation)
+            * cgmath::Matrix4::from(self.rotation)
+            * cgmath::Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    // the opposite of to_matrix, for matrices without shear
+    // (a non-uniformly scaled parent with a rotated child has some, and comes back a bit off)
+    pub fn from_matrix(matrix: cgmath::Matrix4<f32>) -> Self
+    {
+        let scale = cgmath::Vector3::new(matrix.x.truncate().magnitude(), matrix.y.truncate().magnitude(), matrix.z.truncate().magnitude());
+        if scale.x == 0.0 || scale.y == 0.0 || scale.z == 0.0
+        {
+            return Self { translation: matrix.w.truncate(), scale, ..Default::default() };
+        }
+        let rotation = cgmath::Matrix3::from_cols(matrix.x.truncate() / scale.x, matrix.y.truncate() / scale.y, matrix.z.truncate() / scale.z);
+        Self
+        {
+            translation: matrix.w.truncate(),
+            rotation: cgmath::Quaternion::from(rotation).normalize(),
+            scale,
+        }
+    }
+
+    // t = 0 -> self, t = 1 -> other
+    pub fn lerp(self, other: Self, t: f32) -> Self
+    {
+        Self
+        {
+            translation: self.translation.lerp(other.translation, t),
+            rotation: self.rotation.slerp(other.rotation, t),
+            scale: self.scale.lerp(other.scale, t),
+        }
+    }
+}
+
+
+/*   <--------Attachments-------->   */
+// how a mesh uses its texture's alpha, the world is drawn in this order (see State::draw_world)
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum AlphaMode
+{
+    // alpha is ignored
+    #[default]
+    Opaque,
+    // pixels under half alpha aren't drawn at all, the rest are opaque (leaves, fences, ...)
+    Cutout,
+    // see-through, blended over whatever is behind it, drawn back to front
+    Blend,
+}
+
+// what to draw at a node
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MeshAttachment
+{
+    pub mesh: assets::MeshHandle,
+    pub texture: assets::TextureHandle,
+    pub alpha: AlphaMode,
+}
+
+// a camera attached to a node looks down the node's -z axis
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraAttachment
+{
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+}
+
+
+/*   <--------Nodes-------->   */
+// index into the scene graph, the generation makes ids of removed nodes stop working
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId
+{
+    index: usize,
+    generation: u32,
+}
+
+pub struct Node
+{
+    pub name: String,
+    local: Transform,
+    world: cgmath::Matrix4<f32>,
+    dirty: bool,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    pub mesh: Option<MeshAttachment>,
+    pub light: Option<light::Light>,
+    pub camera: Option<CameraAttachment>,
+}
+
+impl Node
+{
+    pub fn local(&self) -> &Transform
+    {
+        &self.local
+    }
+
+    // only up to date after SceneGraph::update_world_matrices()
+    pub fn world_matrix(&self) -> cgmath::Matrix4<f32>
+    {
+        self.world
+    }
+
+    pub fn world_position(&self) -> cgmath::Point3<f32>
+    {
+        cgmath::Point3::new(self.world.w.x, self.world.w.y, self.world.w.z)
+    }
+
+    // the direction the node's -z axis points in world space
+    pub fn world_forward(&self) -> cgmath::Vector3<f32>
+    {
+        (-self.world.z.truncate()).normalize()
+    }
+
+    pub fn parent(&self) -> Option<NodeId>
+    {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId]
+    {
+        &self.children
+    }
+}
+
+struct NodeSlot
+{
+    generation: u32,
+    node: Option<Node>,
+}
+
+
+/*   <--------Scene Graph-------->   */
+#[derive(Default)]
+pub struct SceneGraph
+{
+    slots: Vec<NodeSlot>,
+    free: Vec<usize>,
+    roots: Vec<NodeId>,
+    // which camera node to render from, None -> the free camera in State
+    pub active_camera: Option<NodeId>,
+}
+
+impl SceneGraph
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    // adds a node under parent (or as a root when parent is None)
+    pub fn add_node(&mut self, name: &str, local: Transform, parent: Option<NodeId>) -> NodeId
+    {
+        let node = Node
+        {
+            name: name.to_string(),
+            local,
+            world: cgmath::Matrix4::identity(),
+            dirty: true,
+            parent: None,
+            children: Vec::new(),
+            mesh: None,
+            light: None,
+            camera: None,
+        };
+
+        // reuse a removed node's slot if there is one
+        let id = match self.free.pop()
+        {
+            Some(index) =>
+            {
+                let slot = &mut self.slots[index];
+                slot.generation += 1;
+                slot.node = Some(node);
+                NodeId { index, generation: slot.generation }
+            }
+            None =>
+            {
+                self.slots.push(NodeSlot { generation: 0, node: Some(node) });
+                NodeId { index: self.slots.len() - 1, generation: 0 }
+            }
+        };
+
+        self.roots.push(id);
+        if parent.is_some()
+        {
+            self.set_parent(id, parent);
+        }
+        id
+    }
+
+    // removes a node and everything under it
+    pub fn remove_node(&mut self, id: NodeId)
+    {
+        if self.get(id).is_none()
+        {
+            return;
+        }
+        self.detach(id);
+
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop()
+        {
+            if let Some(node) = self.slots[id.index].node.take()
+            {
+                stack.extend(node.children);
+                self.free.push(id.index);
+            }
+        }
+        if self.active_camera.is_some_and(|camera| self.get(camera).is_none())
+        {
+            self.active_camera = None;
+        }
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&Node>
+    {
+        self.slots.get(id.index)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_ref())
+    }
+
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut Node>
+    {
+        self.slots.get_mut(id.index)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_mut())
+    }
+
+    pub fn find(&self, name: &str) -> Option<NodeId>
+    {
+        self.ids().find(|id| self.get(*id).is_some_and(|node| node.name == name))
+    }
+
+    // every live node, in no particular order
+    pub fn ids(&self) -> impl Iterator<Item = NodeId> + '_
+    {
+        self.slots.iter().enumerate()
+            .filter(|(_, slot)| slot.node.is_some())
+            .map(|(index, slot)| NodeId { index, generation: slot.generation })
+    }
+
+    pub fn roots(&self) -> &[NodeId]
+    {
+        &self.roots
+    }
+
+    pub fn set_local(&mut self, id: NodeId, local: Transform)
+    {
+        if let Some(node) = self.get_mut(id)
+        {
+            node.local = local;
+            node.dirty = true;
+        }
+    }
+
+    // edit the local transform in place, e.g. scene.update_local(id, |t| t.translation.x += 1.0)
+    pub fn update_local(&mut self, id: NodeId, f: impl FnOnce(&mut Transform))
+    {
+        if let Some(node) = self.get_mut(id)
+        {
+            f(&mut node.local);
+            node.dirty = true;
+        }
+    }
+
+    // takes the node out of its parent's children (or out of the roots)
+    fn detach(&mut self, id: NodeId)
+    {
+        let parent = self.get(id).and_then(|node| node.parent);
+        match parent
+        {
+            Some(parent) =>
+            {
+                if let Some(parent) = self.get_mut(parent)
+                {
+                    parent.children.retain(|child| *child != id);
+                }
+            }
+            None => self.roots.retain(|root| *root != id),
+        }
+        if let Some(node) = self.get_mut(id)
+        {
+            node.parent = None;
+        }
+    }
+
+    // moves a node under a new parent, the local transform is kept as it is
+    // (so the node moves along with its new parent)
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>)
+    {
+        if self.get(id).is_none()
+        {
+            return;
+        }
+        if let Some(parent) = parent
+        {
+            // a node can't be parented to itself or to something below it
+            if self.get(parent).is_none() || self.is_ancestor(id, parent)
+            {
+                log::warn!("can't parent node {:?} to {:?}", id, parent);
+                return;
+            }
+        }
+
+        self.detach(id);
+        match parent
+        {
+            Some(parent) =>
+            {
+                if let Some(parent_node) = self.get_mut(parent)
+                {
+                    parent_node.children.push(id);
+                }
+            }
+            None => self.roots.push(id),
+        }
+        if let Some(node) = self.get_mut(id)
+        {
+            node.parent = parent;
+            node.dirty = true;
+        }
+    }
+
+    // true if ancestor is node or one of node's parents
+    fn is_ancestor(&self, ancestor: NodeId, node: NodeId) -> bool
+    {
+        let mut current = Some(node);
+        while let Some(id) = current
+        {
+            if id == ancestor
+            {
+                return true;
+            }
+            current = self.get(id).and_then(|node| node.parent);
+        }
+        false
+    }
+
+    // recomputes the world matrices of dirty nodes and everything below them
+    pub fn update_world_matrices(&mut self)
+    {
+        // (node, parent's world matrix, did an ancestor change)
+        let mut stack: Vec<(NodeId, cgmath::Matrix4<f32>, bool)> = self.roots.iter()
+            .map(|root| (*root, cgmath::Matrix4::identity(), false))
+            .collect();
+
+        while let Some((id, parent_world, parent_changed)) = stack.pop()
+        {
+            let node = match self.get_mut(id)
+            {
+                Some(node) => node,
+                None => continue,
+            };
+            let changed = node.dirty || parent_changed;
+            if changed
+            {
+                node.world = parent_world * node.local.to_matrix();
+                node.dirty = false;
+            }
+            let world = node.world;
+            stack.extend(node.children.iter().map(|child| (*child, world, changed)));
+        }
+    }
+
+    // builds a camera looking through a node with a camera attached
+    pub fn camera_from_node(&self, id: NodeId, aspect: f32) -> Option<camera::Camera>
+    {
+        let node = self.get(id)?;
+        let attachment = node.camera?;
+        let eye = node.world_position();
+        Some(camera::Camera
+        {
+            eye,
+            target: eye + node.world_forward(),
+            up: node.world.y.truncate().normalize(),
+            aspect,
+            fovy: attachment.fovy,
+            znear: attachment.znear,
+            zfar: attachment.zfar,
+        })
+    }
+}
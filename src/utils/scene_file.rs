@@ -0,0 +1,408 @@
+use std::
+{
+    collections::HashMap,
+    path::Path,
+};
+use serde::{ Deserialize, Serialize };
+
+use super::
+{
+    assets,
+    camera,
+    data_file::{ self, DataError },
+    ecs::World,
+    light,
+    scene,
+    systems,
+};
+
+
+/*
+    Levels as data. A scene file lists the camera and every entity in the
+    level, with asset paths (relative to the asset root) instead of handles:
+
+        (
+            camera: (eye: (0.0, 1.0, 2.0), target: (0.0, 0.0, 0.0), fovy: 45.0, znear: 0.1, zfar: 100.0),
+            entities: [
+                (
+                    name: "pentagon",
+                    transform: (rotation: (0.0, 0.0, 90.0)),
+                    mesh: Some((mesh: "models/pentagon.obj", texture: "textures/happy_tree.png", alpha: Blend)),
+                ),
+            ],
+        )
+
+    A mesh's alpha is Opaque, Cutout or Blend (see scene::AlphaMode),
+    Opaque when it's left out.
+
+    Loading checks every reference in the file (parents, asset paths,
+    the active camera) and reports the field that's wrong.
+*/
+
+/*   <--------File Layout-------->   */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneFile
+{
+    pub camera: CameraDesc,
+    // name of an entity with a camera to look through instead of the free camera
+    #[serde(default)]
+    pub active_camera: Option<String>,
+    #[serde(default)]
+    pub entities: Vec<EntityDesc>,
+}
+
+// the Camera fields, apart from aspect which always comes from the window size
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraDesc
+{
+    pub eye: [f32; 3],
+    pub target: [f32; 3],
+    #[serde(default = "default_up")]
+    pub up: [f32; 3],
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TransformDesc
+{
+    #[serde(default)]
+    pub translation: [f32; 3],
+    // euler angles in degrees, applied x then y then z
+    #[serde(default)]
+    pub rotation: [f32; 3],
+    #[serde(default = "default_scale")]
+    pub scale: [f32; 3],
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MeshDesc
+{
+    pub mesh: String,
+    pub texture: String,
+    #[serde(default)]
+    pub alpha: scene::AlphaMode,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpinDesc
+{
+    pub axis: [f32; 3],
+    pub degrees_per_second: f32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EntityDesc
+{
+    pub name: String,
+    #[serde(default)]
+    pub parent: Option<String>,
+    #[serde(default)]
+    pub transform: TransformDesc,
+    #[serde(default)]
+    pub mesh: Option<MeshDesc>,
+    #[serde(default)]
+    pub light: Option<light::Light>,
+    #[serde(default)]
+    pub camera: Option<scene::CameraAttachment>,
+    #[serde(default)]
+    pub spin: Option<SpinDesc>,
+}
+
+fn default_up() -> [f32; 3]
+{
+    [0.0, 1.0, 0.0]
+}
+
+fn default_scale() -> [f32; 3]
+{
+    [1.0, 1.0, 1.0]
+}
+
+impl Default for TransformDesc
+{
+    fn default() -> Self
+    {
+        Self
+        {
+            translation: [0.0; 3],
+            rotation: [0.0; 3],
+            scale: default_scale(),
+        }
+    }
+}
+
+impl From<&TransformDesc> for scene::Transform
+{
+    fn from(desc: &TransformDesc) -> Self
+    {
+        let [x, y, z] = desc.rotation;
+        scene::Transform
+        {
+            translation: desc.translation.into(),
+            rotation: cgmath::Euler::new(cgmath::Deg(x), cgmath::Deg(y), cgmath::Deg(z)).into(),
+            scale: desc.scale.into(),
+        }
+    }
+}
+
+impl From<&scene::Transform> for TransformDesc
+{
+    fn from(transform: &scene::Transform) -> Self
+    {
+        let euler = cgmath::Euler::from(transform.rotation);
+        Self
+        {
+            translation: transform.translation.into(),
+            rotation: [cgmath::Deg::from(euler.x).0, cgmath::Deg::from(euler.y).0, cgmath::Deg::from(euler.z).0],
+            scale: transform.scale.into(),
+        }
+    }
+}
+
+
+/*   <--------Validation-------->   */
+fn check(condition: bool, path: &Path, field: String, message: &str) -> Result<(), DataError>
+{
+    if condition { Ok(()) } else { Err(DataError::new(path, field, message)) }
+}
+
+fn is_nonzero(v: [f32; 3]) -> bool
+{
+    v.iter().any(|c| *c != 0.0)
+}
+
+fn check_projection(path: &Path, field: &str, fovy: f32, znear: f32, zfar: f32) -> Result<(), DataError>
+{
+    check(fovy > 0.0 && fovy < 180.0, path, format!("{}.fovy", field), "must be between 0 and 180 degrees")?;
+    check(znear > 0.0, path, format!("{}.znear", field), "must be greater than 0")?;
+    check(zfar > znear, path, format!("{}.zfar", field), "must be greater than znear")
+}
+
+
+impl SceneFile
+{
+    // reads a .ron or .json scene and checks it against the asset root
+    pub fn load(path: &Path, asset_root: &Path) -> Result<Self, DataError>
+    {
+        let scene: Self = data_file::load(path)?;
+        scene.validate(path, asset_root)?;
+        Ok(scene)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), DataError>
+    {
+        data_file::save(self, path)
+    }
+
+    // catches what serde can't: missing files, bad references and values that make no sense
+    pub fn validate(&self, path: &Path, asset_root: &Path) -> Result<(), DataError>
+    {
+        let camera = &self.camera;
+        check_projection(path, "camera", camera.fovy, camera.znear, camera.zfar)?;
+        check(camera.eye != camera.target, path, "camera.target".into(), "must not be the same as camera.eye")?;
+        check(is_nonzero(camera.up), path, "camera.up".into(), "must not be (0, 0, 0)")?;
+
+        // name -> index in entities
+        let mut names = HashMap::new();
+        for (i, entity) in self.entities.iter().enumerate()
+        {
+            let field = format!("entities[{}]", i);
+            check(!entity.name.is_empty(), path, format!("{}.name", field), "must not be empty")?;
+            if let Some(other) = names.insert(entity.name.as_str(), i)
+            {
+                return Err(DataError::new(path, format!("{}.name", field), format!("\"{}\" is already used by entities[{}]", entity.name, other)));
+            }
+        }
+
+        for (i, entity) in self.entities.iter().enumerate()
+        {
+            let field = format!("entities[{}]", i);
+
+            if let Some(parent) = &entity.parent
+            {
+                if !names.contains_key(parent.as_str())
+                {
+                    return Err(DataError::new(path, format!("{}.parent", field), format!("there is no entity named \"{}\"", parent)));
+                }
+                // walk up the parents, getting back to this entity means a loop
+                let mut current = Some(parent.as_str());
+                while let Some(name) = current
+                {
+                    if name == entity.name
+                    {
+                        return Err(DataError::new(path, format!("{}.parent", field), format!("\"{}\" ends up being its own parent", entity.name)));
+                    }
+                    current = self.entities[names[name]].parent.as_deref();
+                }
+            }
+
+            check(entity.transform.scale.iter().all(|c| *c != 0.0), path, format!("{}.transform.scale", field), "must not have a 0 in it")?;
+
+            if let Some(mesh) = &entity.mesh
+            {
+                check(asset_root.join(&mesh.mesh).is_file(), path, format!("{}.mesh.mesh", field), &format!("no file {} in {}", mesh.mesh, asset_root.display()))?;
+                check(asset_root.join(&mesh.texture).is_file(), path, format!("{}.mesh.texture", field), &format!("no file {} in {}", mesh.texture, asset_root.display()))?;
+            }
+            if let Some(light) = &entity.light
+            {
+                check(light.intensity >= 0.0, path, format!("{}.light.intensity", field), "must not be negative")?;
+            }
+            if let Some(camera) = &entity.camera
+            {
+                check_projection(path, &format!("{}.camera", field), camera.fovy, camera.znear, camera.zfar)?;
+            }
+            if let Some(spin) = &entity.spin
+            {
+                check(is_nonzero(spin.axis), path, format!("{}.spin.axis", field), "must not be (0, 0, 0)")?;
+            }
+        }
+
+        if let Some(active) = &self.active_camera
+        {
+            match names.get(active.as_str())
+            {
+                None => return Err(DataError::new(path, "active_camera", format!("there is no entity named \"{}\"", active))),
+                Some(i) => check(self.entities[*i].camera.is_some(), path, "active_camera".into(), &format!("entities[{}] (\"{}\") has no camera", i, active))?,
+            }
+        }
+
+        Ok(())
+    }
+
+
+    /*   <--------Loading Into The World-------->   */
+    // replaces whatever scene the world had with this one,
+    // meshes and textures are queued on the asset manager
+    pub fn instantiate(&self, world: &mut World, assets: &mut dyn assets::AssetLoader, aspect: f32)
+    {
+        // entities from a previous scene go away with their scene graph
+        let old: Vec<_> = world.query::<systems::SceneNode>().map(|(entity, _)| entity).collect();
+        for entity in old
+        {
+            world.despawn(entity);
+        }
+
+        let mut scene = scene::SceneGraph::new();
+        let mut nodes: HashMap<&str, scene::NodeId> = HashMap::new();
+        let mut spawned = Vec::new();
+
+        // parents have to exist before their children, so keep going over the list
+        // until everything is placed (validate() made sure there are no loops)
+        while nodes.len() < self.entities.len()
+        {
+            for entity in &self.entities
+            {
+                if nodes.contains_key(entity.name.as_str())
+                {
+                    continue;
+                }
+                let parent = match &entity.parent
+                {
+                    Some(parent) => match nodes.get(parent.as_str())
+                    {
+                        Some(parent) => Some(*parent),
+                        None => continue,
+                    },
+                    None => None,
+                };
+
+                let id = scene.add_node(&entity.name, scene::Transform::from(&entity.transform), parent);
+                let node = scene.get_mut(id).expect("node was just added");
+                node.mesh = entity.mesh.as_ref().map(|mesh| scene::MeshAttachment
+                {
+                    mesh: assets.load_mesh(Path::new(&mesh.mesh)),
+                    texture: assets.load_texture(Path::new(&mesh.texture)),
+                    alpha: mesh.alpha,
+                });
+                node.light = entity.light;
+                node.camera = entity.camera;
+
+                nodes.insert(&entity.name, id);
+                spawned.push((id, entity));
+            }
+        }
+        scene.active_camera = self.active_camera.as_ref().map(|name| nodes[name.as_str()]);
+
+        let camera = &self.camera;
+        world.insert_resource(camera::Camera
+        {
+            eye: camera.eye.into(),
+            target: camera.target.into(),
+            up: camera.up.into(),
+            aspect,
+            fovy: camera.fovy,
+            znear: camera.znear,
+            zfar: camera.zfar,
+        });
+        world.insert_resource(scene);
+        // node ids start over in the new graph, don't let the renderer blend them with the old level's
+        world.remove_resource::<systems::RenderList>();
+
+        for (id, desc) in spawned
+        {
+            let entity = systems::spawn_for_node(world, id);
+            if let Some(spin) = &desc.spin
+            {
+                world.insert(entity, systems::Spin { axis: spin.axis.into(), degrees_per_second: spin.degrees_per_second });
+            }
+        }
+    }
+
+    // the opposite of instantiate, for saving the level as it is right now
+    pub fn from_world(world: &World, assets: &dyn assets::AssetLoader) -> Self
+    {
+        let scene = world.resource::<scene::SceneGraph>();
+        let camera = world.resource::<camera::Camera>();
+        let name_of = |id: scene::NodeId| scene.get(id).map(|node| node.name.clone());
+
+        let entities = world.query::<systems::SceneNode>()
+            .filter_map(|(entity, systems::SceneNode(id))|
+            {
+                let node = scene.get(*id)?;
+                let transform = world.get::<scene::Transform>(entity).copied().unwrap_or(*node.local());
+                Some(EntityDesc
+                {
+                    name: node.name.clone(),
+                    parent: node.parent().and_then(name_of),
+                    transform: TransformDesc::from(&transform),
+                    mesh: node.mesh.map(|mesh| MeshDesc
+                    {
+                        mesh: assets.mesh_path(mesh.mesh).display().to_string(),
+                        texture: assets.texture_path(mesh.texture).display().to_string(),
+                        alpha: mesh.alpha,
+                    }),
+                    light: node.light,
+                    camera: node.camera,
+                    spin: world.get::<systems::Spin>(entity).map(|spin| SpinDesc
+                    {
+                        axis: spin.axis.into(),
+                        degrees_per_second: spin.degrees_per_second,
+                    }),
+                })
+            })
+            .collect();
+
+        Self
+        {
+            camera: CameraDesc
+            {
+                eye: camera.eye.into(),
+                target: camera.target.into(),
+                up: camera.up.into(),
+                fovy: camera.fovy,
+                znear: camera.znear,
+                zfar: camera.zfar,
+            },
+            active_camera: scene.active_camera.and_then(name_of),
+            entities,
+        }
+    }
+}
@@ -0,0 +1,492 @@
+use std::time::Duration;
+
+use super::
+{
+    game_state::{ GameState, Transition },
+    state::{ self, Frame, State },
+    systems,
+    input,
+    picking,
+    scene,
+    debug_draw,
+    render_mode::RenderMode,
+    text::{ Align, Text },
+    settings::{ self, Backend, PowerPreference, PresentMode, Settings, WindowMode },
+};
+
+
+/*
+    The game's states (see 'game_state.rs'):
+
+        main menu --Enter--> loading --assets done--> playing
+        playing --click--> playing (with whatever is under the cursor selected)
+        playing --Esc--> paused --Esc--> playing
+                                --Q--> main menu
+        playing --task done--> task complete --Enter--> loading (same level again)
+                                             --Esc--> main menu
+
+    The keys are the default bindings of the confirm, back, pause and
+    quit to menu actions (see 'input.rs'), which can be remapped or
+    pressed on a gamepad instead.
+
+    The menus tell the player what to do in the middle of the screen,
+    and in the window title too. With debug drawing on, playing also
+    shows a panel to change the render settings with (see 'ui.rs'), and
+    the pause screen has one for the window and graphics settings (see
+    'settings.rs').
+*/
+
+// a message in the middle of the window, wrapped to fit
+fn draw_message(engine: &mut State, frame: &mut Frame, message: &str)
+{
+    let (width, height) = (engine.size.width as f32, engine.size.height as f32);
+    let message = Text::new(message, 28.0).wrap((width - 40.0).max(1.0)).align(Align::Center);
+    let layout = engine.layout_text(&message);
+    engine.draw_text(frame, &message, cgmath::Vector2::new(20.0, ((height - layout.height) / 2.0).round()));
+}
+
+// the render settings, along with the rest of the debug view
+fn debug_panel(engine: &mut State)
+{
+    let ctx = engine.ui();
+    egui::Window::new("Debug").default_pos((10.0, 40.0)).resizable(false).show(&ctx, |ui|
+    {
+        let settings = engine.render_settings();
+        let mut mode = settings.mode;
+        egui::ComboBox::from_label("render mode")
+            .selected_text(mode.name())
+            .show_ui(ui, |ui|
+            {
+                for option in RenderMode::ALL
+                {
+                    ui.selectable_value(&mut mode, option, option.name());
+                }
+            });
+        if mode != settings.mode
+        {
+            engine.set_render_mode(mode);
+        }
+
+        let mut culling = settings.culling;
+        if ui.checkbox(&mut culling, "back face culling").changed()
+        {
+            engine.set_culling(culling);
+        }
+        let mut frustum_culling = engine.frustum_culling();
+        if ui.checkbox(&mut frustum_culling, "frustum culling").changed()
+        {
+            engine.set_frustum_culling(frustum_culling);
+        }
+
+        let stats = engine.render_stats();
+        ui.separator();
+        ui.label(format!("drawn {}, culled {}", stats.drawn, stats.culled));
+        ui.label(format!("draw calls {}", stats.draw_calls));
+    });
+}
+
+// a row of the settings grid to pick one of options with
+fn settings_combo<T: Copy + PartialEq>(ui: &mut egui::Ui, label: &str, value: &mut T, options: &[T], name: fn(T) -> &'static str)
+{
+    ui.label(label);
+    egui::ComboBox::from_id_source(label)
+        .selected_text(name(*value))
+        .show_ui(ui, |ui|
+        {
+            for &option in options
+            {
+                ui.selectable_value(value, option, name(option));
+            }
+        });
+    ui.end_row();
+}
+
+fn msaa_name(samples: u32) -> &'static str
+{
+    if samples > 1 { "4x" } else { "off" }
+}
+
+// the window and graphics settings, edited here and changed when they're applied
+fn settings_panel(engine: &mut State, edited: &mut Settings, status: &mut Option<String>)
+{
+    let ctx = engine.ui();
+    egui::Window::new("Settings").anchor(egui::Align2::RIGHT_TOP, (-10.0, 40.0)).resizable(false).show(&ctx, |ui|
+    {
+        egui::Grid::new("settings grid").num_columns(2).show(ui, |ui|
+        {
+            ui.label("resolution");
+            ui.horizontal(|ui|
+            {
+                ui.add(egui::DragValue::new(&mut edited.resolution.0).clamp_range(1..=8192));
+                ui.label("x");
+                ui.add(egui::DragValue::new(&mut edited.resolution.1).clamp_range(1..=8192));
+            });
+            ui.end_row();
+            settings_combo(ui, "window", &mut edited.window_mode, &WindowMode::ALL, WindowMode::name);
+            settings_combo(ui, "present mode", &mut edited.present_mode, &PresentMode::ALL, PresentMode::name);
+            settings_combo(ui, "msaa", &mut edited.msaa, &settings::MSAA_SAMPLES, msaa_name);
+            ui.label("field of view");
+            ui.add(egui::Slider::new(&mut edited.fov, 30.0..=120.0).suffix("°"));
+            ui.end_row();
+            settings_combo(ui, "backend", &mut edited.backend, &Backend::ALL, Backend::name);
+            settings_combo(ui, "power preference", &mut edited.power_preference, &PowerPreference::ALL, PowerPreference::name);
+        });
+        if engine.settings().needs_restart(edited)
+        {
+            ui.label("the backend and power preference change after a restart");
+        }
+
+        ui.separator();
+        ui.horizontal(|ui|
+        {
+            let apply = ui.button("Apply").clicked();
+            let save = ui.button("Save").clicked();
+            if ui.button("Undo").clicked()
+            {
+                *edited = engine.settings().clone();
+                *status = None;
+            }
+            if apply || save
+            {
+                *status = Some(match engine.set_settings(edited.clone())
+                {
+                    Ok(()) if save => match engine.save_settings()
+                    {
+                        Ok(()) => String::from("saved"),
+                        Err(e) => e.to_string(),
+                    },
+                    Ok(()) => String::from("applied"),
+                    Err(e) => e.to_string(),
+                });
+            }
+        });
+        if let Some(status) = status
+        {
+            ui.label(status.as_str());
+        }
+    });
+}
+
+/*   <--------Main Menu-------->   */
+#[derive(Default)]
+pub struct MainMenu
+{
+    // why we ended up back here, shown in the title
+    error: Option<String>,
+}
+
+impl MainMenu
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    pub fn with_error(error: impl Into<String>) -> Self
+    {
+        Self { error: Some(error.into()) }
+    }
+}
+
+impl GameState for MainMenu
+{
+    fn name(&self) -> &'static str
+    {
+        "main menu"
+    }
+
+    fn title(&self, _engine: &State) -> String
+    {
+        match &self.error
+        {
+            Some(error) => format!("With Me - {} (Enter to try again, Esc to quit)", error),
+            None => String::from("With Me - press Enter to start, Esc to quit"),
+        }
+    }
+
+    fn actions(&mut self, engine: &mut State) -> Transition
+    {
+        let input = engine.world().resource::<input::Input>();
+        if input.triggered(input::CONFIRM)
+        {
+            Transition::Switch(Box::new(Loading::level(state::MAIN_SCENE)))
+        }
+        else if input.triggered(input::BACK)
+        {
+            Transition::Quit
+        }
+        else
+        {
+            Transition::None
+        }
+    }
+
+    fn render(&mut self, engine: &mut State, frame: &mut Frame, _alpha: f32)
+    {
+        frame.clear_color = wgpu::Color { r: 0.1, g: 0.2, b: 0.3, a: 1.0 };
+        let message = match &self.error
+        {
+            Some(error) => format!("{}\n\nEnter to try again, Esc to quit", error),
+            None => String::from("With Me\n\nEnter to start, Esc to quit"),
+        };
+        draw_message(engine, frame, &message);
+    }
+}
+
+
+/*   <--------Loading-------->   */
+// waits for the level's assets to finish loading
+pub struct Loading
+{
+    // scene file, relative to the asset root
+    level: String,
+}
+
+impl Loading
+{
+    pub fn level(level: &str) -> Self
+    {
+        Self { level: level.to_string() }
+    }
+}
+
+impl GameState for Loading
+{
+    fn name(&self) -> &'static str
+    {
+        "loading"
+    }
+
+    fn title(&self, engine: &State) -> String
+    {
+        format!("With Me - loading {:.0}%", engine.load_progress().fraction() * 100.0)
+    }
+
+    fn on_enter(&mut self, engine: &mut State) -> Transition
+    {
+        match engine.load_level(&self.level)
+        {
+            Ok(()) => Transition::None,
+            Err(e) =>
+            {
+                log::error!("{}", e);
+                Transition::Switch(Box::new(MainMenu::with_error("the level could not be loaded")))
+            }
+        }
+    }
+
+    fn update(&mut self, engine: &mut State, _tick: Duration) -> Transition
+    {
+        if engine.load_progress().is_done()
+        {
+            Transition::Switch(Box::new(Playing::new()))
+        }
+        else
+        {
+            Transition::None
+        }
+    }
+
+    fn render(&mut self, engine: &mut State, frame: &mut Frame, _alpha: f32)
+    {
+        frame.clear_color = wgpu::Color { r: 0.05, g: 0.05, b: 0.05, a: 1.0 };
+        let message = format!("loading {:.0}%", engine.load_progress().fraction() * 100.0);
+        draw_message(engine, frame, &message);
+    }
+}
+
+
+/*   <--------Playing-------->   */
+#[derive(Default)]
+pub struct Playing
+{
+    // what the player clicked on last (see 'picking.rs')
+    selected: Option<picking::PickHit>,
+}
+
+impl Playing
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+}
+
+impl GameState for Playing
+{
+    fn name(&self) -> &'static str
+    {
+        "playing"
+    }
+
+    fn title(&self, engine: &State) -> String
+    {
+        let scene = engine.world().resource::<scene::SceneGraph>();
+        let title = match self.selected.and_then(|hit| scene.get(hit.node))
+        {
+            Some(node) => format!("With Me - selected {}", node.name),
+            None => String::from("With Me"),
+        };
+        // how much frustum culling saved, along with the rest of the debug view
+        if engine.world().resource::<debug_draw::DebugDraw>().is_enabled()
+        {
+            let stats = engine.render_stats();
+            return format!("{} - drawn {}, culled {}, draw calls {}", title, stats.drawn, stats.culled, stats.draw_calls);
+        }
+        title
+    }
+
+    fn actions(&mut self, engine: &mut State) -> Transition
+    {
+        let input = engine.world().resource::<input::Input>();
+        if input.triggered(input::PAUSE)
+        {
+            return Transition::Push(Box::new(Paused::new()));
+        }
+        if input.triggered(input::SELECT)
+        {
+            self.selected = engine.pick_at_cursor();
+            if let Some(hit) = self.selected
+            {
+                log::info!("picked {:?} at {:?}, {:.2} away", hit.node, hit.point, hit.distance);
+            }
+        }
+        Transition::None
+    }
+
+    fn update(&mut self, engine: &mut State, tick: Duration) -> Transition
+    {
+        engine.update(tick);
+        if engine.world().get_resource::<systems::TaskStatus>().is_some_and(|task| task.complete)
+        {
+            Transition::Push(Box::new(TaskComplete))
+        }
+        else
+        {
+            Transition::None
+        }
+    }
+
+    fn render(&mut self, engine: &mut State, frame: &mut Frame, alpha: f32)
+    {
+        // where the selection was clicked, with debug drawing on
+        if let Some(hit) = self.selected
+        {
+            engine.debug_draw().arrow(hit.point, hit.point + hit.normal * 0.3, debug_draw::YELLOW);
+        }
+        engine.draw_world(frame, alpha);
+
+        // the fps counter goes with the rest of the debug view
+        if engine.world().resource::<debug_draw::DebugDraw>().is_enabled()
+        {
+            let fps = Text::new(format!("{:.0} fps", engine.fps()), 18.0).color([1.0, 0.9, 0.2, 1.0]);
+            engine.draw_text(frame, &fps, cgmath::Vector2::new(10.0, 10.0));
+            debug_panel(engine);
+        }
+    }
+}
+
+
+/*   <--------Paused-------->   */
+#[derive(Default)]
+pub struct Paused
+{
+    // the settings panel's copy of the settings, made when it's first shown
+    settings: Option<Settings>,
+    status: Option<String>,
+}
+
+impl Paused
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+}
+
+impl GameState for Paused
+{
+    fn name(&self) -> &'static str
+    {
+        "paused"
+    }
+
+    fn title(&self, _engine: &State) -> String
+    {
+        String::from("With Me - paused (Esc to resume, Q to quit to the menu)")
+    }
+
+    fn is_overlay(&self) -> bool
+    {
+        true
+    }
+
+    fn actions(&mut self, engine: &mut State) -> Transition
+    {
+        let input = engine.world().resource::<input::Input>();
+        if input.triggered(input::PAUSE) || input.triggered(input::BACK)
+        {
+            Transition::Pop
+        }
+        else if input.triggered(input::QUIT_TO_MENU)
+        {
+            Transition::Reset(Box::new(MainMenu::new()))
+        }
+        else
+        {
+            Transition::None
+        }
+    }
+
+    // over the frozen game underneath
+    fn render(&mut self, engine: &mut State, frame: &mut Frame, _alpha: f32)
+    {
+        draw_message(engine, frame, "Paused\n\nEsc to resume, Q to quit to the menu");
+        let edited = self.settings.get_or_insert_with(|| engine.settings().clone());
+        settings_panel(engine, edited, &mut self.status);
+    }
+}
+
+
+/*   <--------Task Complete-------->   */
+pub struct TaskComplete;
+
+impl GameState for TaskComplete
+{
+    fn name(&self) -> &'static str
+    {
+        "task complete"
+    }
+
+    fn title(&self, _engine: &State) -> String
+    {
+        String::from("With Me - task complete! (Enter to play again, Esc for the menu)")
+    }
+
+    fn is_overlay(&self) -> bool
+    {
+        true
+    }
+
+    fn actions(&mut self, engine: &mut State) -> Transition
+    {
+        let input = engine.world().resource::<input::Input>();
+        if input.triggered(input::CONFIRM)
+        {
+            Transition::Reset(Box::new(Loading::level(state::MAIN_SCENE)))
+        }
+        else if input.triggered(input::BACK)
+        {
+            Transition::Reset(Box::new(MainMenu::new()))
+        }
+        else
+        {
+            Transition::None
+        }
+    }
+
+    fn render(&mut self, engine: &mut State, frame: &mut Frame, _alpha: f32)
+    {
+        draw_message(engine, frame, "Task complete!\n\nEnter to play again, Esc for the menu");
+    }
+}
@@ -0,0 +1,319 @@
+use std::
+{
+    fmt,
+    path::Path,
+};
+use serde::{ Deserialize, Serialize };
+use winit::
+{
+    dpi::PhysicalSize,
+    monitor::VideoMode,
+    window::{ Fullscreen, Window },
+};
+
+use super::data_file;
+
+
+/*
+    Window and graphics settings, from a config file (settings.ron in the
+    player's config folder, or the defaults in assets/config, see 'paths.rs')
+    the player can edit, or from the settings panel on the pause screen:
+
+        resolution        the window's size in pixels, or the video mode's in fullscreen
+        window_mode       Windowed, Borderless or Fullscreen
+        present_mode      Vsync, Mailbox or Immediate (Fifo, Mailbox and Immediate in wgpu)
+        backend           Auto, Vulkan, Metal, Dx12, Dx11 or Gl
+        power_preference  Default, LowPower or HighPerformance
+        msaa              samples per pixel for the world, 1 (off) or 4
+        fov               the free camera's vertical field of view, in degrees
+
+    Anything left out of the file gets its default. Values that are out
+    of range are errors that say which one it was, like
+
+        assets/config/settings.ron: msaa: must be 1 (off) or 4
+
+    The backend and power preference pick the gpu, so changing them
+    only takes effect the next time the game starts.
+*/
+
+// the sample counts every gpu can do, others depend on the format and the adapter
+pub const MSAA_SAMPLES: [u32; 2] = [1, 4];
+// the biggest texture every gpu can make, so the biggest surface too
+const MAX_RESOLUTION: u32 = 8192;
+
+
+/*   <--------Options-------->   */
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WindowMode
+{
+    Windowed,
+    // a window without decorations that covers the monitor, quick to switch in and out of
+    Borderless,
+    // takes the monitor over, at the video mode closest to the resolution
+    Fullscreen,
+}
+
+impl WindowMode
+{
+    pub const ALL: [WindowMode; 3] = [WindowMode::Windowed, WindowMode::Borderless, WindowMode::Fullscreen];
+
+    pub fn name(self) -> &'static str
+    {
+        match self
+        {
+            WindowMode::Windowed => "windowed",
+            WindowMode::Borderless => "borderless",
+            WindowMode::Fullscreen => "fullscreen",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PresentMode
+{
+    // waits for the display, no tearing
+    Vsync,
+    // no tearing and no waiting, older frames get replaced, where the gpu has it
+    Mailbox,
+    // as fast as it goes, frames can tear
+    Immediate,
+}
+
+impl PresentMode
+{
+    pub const ALL: [PresentMode; 3] = [PresentMode::Vsync, PresentMode::Mailbox, PresentMode::Immediate];
+
+    pub fn name(self) -> &'static str
+    {
+        match self
+        {
+            PresentMode::Vsync => "vsync",
+            PresentMode::Mailbox => "mailbox",
+            PresentMode::Immediate => "immediate",
+        }
+    }
+
+    // wgpu goes back to Fifo when the surface can't do the others
+    pub fn to_wgpu(self) -> wgpu::PresentMode
+    {
+        match self
+        {
+            PresentMode::Vsync => wgpu::PresentMode::Fifo,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Backend
+{
+    // whatever the platform has
+    Auto,
+    Vulkan,
+    Metal,
+    Dx12,
+    Dx11,
+    Gl,
+}
+
+impl Backend
+{
+    pub const ALL: [Backend; 6] = [Backend::Auto, Backend::Vulkan, Backend::Metal, Backend::Dx12, Backend::Dx11, Backend::Gl];
+
+    pub fn name(self) -> &'static str
+    {
+        match self
+        {
+            Backend::Auto => "auto",
+            Backend::Vulkan => "vulkan",
+            Backend::Metal => "metal",
+            Backend::Dx12 => "dx12",
+            Backend::Dx11 => "dx11",
+            Backend::Gl => "gl",
+        }
+    }
+
+    pub fn to_wgpu(self) -> wgpu::Backends
+    {
+        match self
+        {
+            Backend::Auto => wgpu::Backends::all(),
+            Backend::Vulkan => wgpu::Backends::VULKAN,
+            Backend::Metal => wgpu::Backends::METAL,
+            Backend::Dx12 => wgpu::Backends::DX12,
+            Backend::Dx11 => wgpu::Backends::DX11,
+            Backend::Gl => wgpu::Backends::GL,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PowerPreference
+{
+    Default,
+    // the integrated gpu on laptops, for battery life
+    LowPower,
+    // the discrete gpu on laptops
+    HighPerformance,
+}
+
+impl PowerPreference
+{
+    pub const ALL: [PowerPreference; 3] = [PowerPreference::Default, PowerPreference::LowPower, PowerPreference::HighPerformance];
+
+    pub fn name(self) -> &'static str
+    {
+        match self
+        {
+            PowerPreference::Default => "default",
+            PowerPreference::LowPower => "low power",
+            PowerPreference::HighPerformance => "high performance",
+        }
+    }
+
+    pub fn to_wgpu(self) -> wgpu::PowerPreference
+    {
+        match self
+        {
+            PowerPreference::Default => wgpu::PowerPreference::default(),
+            PowerPreference::LowPower => wgpu::PowerPreference::LowPower,
+            PowerPreference::HighPerformance => wgpu::PowerPreference::HighPerformance,
+        }
+    }
+}
+
+
+/*   <--------Settings-------->   */
+// a setting that's out of range, field is its name in the file
+#[derive(Clone, Debug, PartialEq)]
+pub struct SettingError
+{
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for SettingError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+impl std::error::Error for SettingError {}
+
+fn check(ok: bool, field: &'static str, message: &str) -> Result<(), SettingError>
+{
+    if ok { Ok(()) } else { Err(SettingError { field, message: message.to_string() }) }
+}
+
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings
+{
+    pub resolution: (u32, u32),
+    pub window_mode: WindowMode,
+    pub present_mode: PresentMode,
+    pub backend: Backend,
+    pub power_preference: PowerPreference,
+    pub msaa: u32,
+    pub fov: f32,
+}
+
+impl Default for Settings
+{
+    fn default() -> Self
+    {
+        Self
+        {
+            resolution: (1280, 720),
+            window_mode: WindowMode::Windowed,
+            present_mode: PresentMode::Vsync,
+            backend: Backend::Auto,
+            power_preference: PowerPreference::Default,
+            msaa: 1,
+            fov: 45.0,
+        }
+    }
+}
+
+impl Settings
+{
+    // .ron or .json (see 'data_file.rs'), out of range values are errors too
+    pub fn load(path: &Path) -> Result<Self, data_file::DataError>
+    {
+        let settings: Self = data_file::load(path)?;
+        settings.validate().map_err(|e| data_file::DataError::new(path, e.field, e.message))?;
+        Ok(settings)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), data_file::DataError>
+    {
+        data_file::save(self, path)
+    }
+
+    pub fn validate(&self) -> Result<(), SettingError>
+    {
+        let (width, height) = self.resolution;
+        check((1..=MAX_RESOLUTION).contains(&width) && (1..=MAX_RESOLUTION).contains(&height),
+            "resolution", &format!("must be between 1 and {} pixels on each side", MAX_RESOLUTION))?;
+        check(MSAA_SAMPLES.contains(&self.msaa), "msaa", "must be 1 (off) or 4")?;
+        check(self.fov > 0.0 && self.fov < 180.0, "fov", "must be between 0 and 180 degrees")?;
+        Ok(())
+    }
+
+    // whether going from self to other picks a different gpu, which needs a restart
+    pub fn needs_restart(&self, other: &Settings) -> bool
+    {
+        self.backend != other.backend || self.power_preference != other.power_preference
+    }
+
+    // whether going from self to other changes the window
+    pub fn changes_window(&self, other: &Settings) -> bool
+    {
+        self.resolution != other.resolution || self.window_mode != other.window_mode
+    }
+
+    pub fn window_size(&self) -> PhysicalSize<u32>
+    {
+        PhysicalSize::new(self.resolution.0, self.resolution.1)
+    }
+
+    // sizes the window and takes it in or out of fullscreen, it gets a Resized event after
+    pub fn apply_to_window(&self, window: &Window)
+    {
+        match self.window_mode
+        {
+            WindowMode::Windowed =>
+            {
+                window.set_fullscreen(None);
+                window.set_inner_size(self.window_size());
+            }
+            WindowMode::Borderless => window.set_fullscreen(Some(Fullscreen::Borderless(window.current_monitor()))),
+            WindowMode::Fullscreen => match self.video_mode(window)
+            {
+                Some(mode) => window.set_fullscreen(Some(Fullscreen::Exclusive(mode))),
+                None =>
+                {
+                    log::warn!("the monitor has no video modes to go fullscreen with, using borderless");
+                    window.set_fullscreen(Some(Fullscreen::Borderless(window.current_monitor())));
+                }
+            },
+        }
+    }
+
+    // the monitor's video mode closest in size to the resolution, the fastest and deepest of those
+    fn video_mode(&self, window: &Window) -> Option<VideoMode>
+    {
+        let monitor = window.current_monitor().or_else(|| window.primary_monitor())?;
+        let (width, height) = (self.resolution.0 as i64, self.resolution.1 as i64);
+        monitor.video_modes().min_by_key(|mode|
+        {
+            let size = mode.size();
+            let distance = (size.width as i64 - width).abs() + (size.height as i64 - height).abs();
+            (distance, std::cmp::Reverse(mode.refresh_rate()), std::cmp::Reverse(mode.bit_depth()))
+        })
+    }
+}
@@ -0,0 +1,276 @@
+use std::time::Duration;
+use winit::event::WindowEvent;
+
+use super::
+{
+    assets,
+    camera,
+    camera_controller,
+    console,
+    data_file,
+    debug_draw,
+    ecs,
+    gamepad,
+    input,
+    replay,
+    scene_file,
+    systems,
+};
+
+
+/*
+    The game world and the systems that run it, with nothing to do with the
+    window or the gpu. State owns one and draws it, replays (see 'replay.rs')
+    run one on their own.
+
+    Everything that changes the world from outside goes through here
+    (window events, gamepads, the window's aspect ratio, ticks, loading a level),
+    which is what lets a recording play back the same way. Console commands
+    come through here too, but they aren't recorded.
+*/
+pub struct Simulation
+{
+    world: ecs::World,
+    schedule: ecs::Schedule,
+    // the level loaded last, relative to the asset root
+    level: Option<String>,
+    recorder: Option<replay::Recorder>,
+    gamepads: Option<Box<dyn gamepad::GamepadBackend>>,
+    gamepad_events: Vec<gamepad::GamepadEvent>,
+}
+
+impl Simulation
+{
+    pub fn new(bindings: input::Bindings) -> Self
+    {
+        let mut world = ecs::World::new();
+        world.insert_resource(camera_controller::CameraController::new(0.05));
+        world.insert_resource(input::Input::new(bindings));
+        world.insert_resource(systems::Time::default());
+        world.insert_resource(debug_draw::DebugDraw::new());
+        let mut console = console::Console::new();
+        systems::add_commands(&mut console);
+        world.insert_resource(console);
+
+        let mut schedule = ecs::Schedule::new();
+        systems::add_systems(&mut schedule);
+
+        Self { world, schedule, level: None, recorder: None, gamepads: None, gamepad_events: Vec::new() }
+    }
+
+    pub fn world(&self) -> &ecs::World
+    {
+        &self.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut ecs::World
+    {
+        &mut self.world
+    }
+
+    pub fn level(&self) -> Option<&str>
+    {
+        self.level.as_deref()
+    }
+
+    // loads a scene file (relative to the asset root) into the world, replacing whatever level was there,
+    // the old level stays if the new one can't be loaded
+    pub fn load_level(&mut self, assets: &mut dyn assets::AssetLoader, level: &str, aspect: f32) -> Result<(), data_file::DataError>
+    {
+        let path = assets.root().join(level);
+        let scene = scene_file::SceneFile::load(&path, assets.root())?;
+        scene.instantiate(&mut self.world, assets, aspect);
+        self.world.insert_resource(systems::TaskStatus::default());
+        // ticks count from the start of the level
+        self.world.resource_mut::<systems::Time>().tick = 0;
+
+        // there has to be a RenderList before the first frame
+        self.schedule.run_stage(ecs::Stage::TransformPropagation, &mut self.world);
+        self.schedule.run_stage(ecs::Stage::RenderExtraction, &mut self.world);
+
+        self.level = Some(level.to_string());
+        if let Some(recorder) = &mut self.recorder
+        {
+            recorder.begin(level, aspect, self.world.resource::<input::Input>());
+        }
+        Ok(())
+    }
+
+    // returns a bool to indicate whether an event has been fully processed
+    pub fn input(&mut self, event: &WindowEvent) -> bool
+    {
+        if let Some(recorder) = &mut self.recorder
+        {
+            recorder.record_window_event(self.world.resource::<systems::Time>().tick, event);
+        }
+        // the event handlers in 'systems.rs' get first go at it
+        self.schedule.handle_event(&mut self.world, event)
+    }
+
+    // one line of the developer console (see 'console.rs')
+    pub fn run_command(&mut self, line: &str) -> console::CommandResult
+    {
+        self.world.resource_scope(|world, console: &mut console::Console| console.execute(world, line))
+    }
+
+    pub fn console(&self) -> &console::Console
+    {
+        self.world.resource::<console::Console>()
+    }
+
+    // where gamepad events come from, polled at the start of every tick and by the game states in between (see 'gamepad.rs')
+    pub fn set_gamepad_backend(&mut self, backend: Box<dyn gamepad::GamepadBackend>)
+    {
+        log::info!("gamepad backend: {}", backend.name());
+        self.gamepads = Some(backend);
+    }
+
+    // what the backend has heard since it was last polled, without handing it to the Input resource yet,
+    // the game states look at each one as it goes in (see GameStateStack::update in 'game_state.rs')
+    pub fn poll_gamepads(&mut self) -> Vec<gamepad::GamepadEvent>
+    {
+        if let Some(backend) = &mut self.gamepads
+        {
+            backend.poll(&mut self.gamepad_events);
+        }
+        std::mem::take(&mut self.gamepad_events)
+    }
+
+    pub fn gamepad_event(&mut self, event: gamepad::GamepadEvent)
+    {
+        if let Some(recorder) = &mut self.recorder
+        {
+            recorder.record(self.world.resource::<systems::Time>().tick, replay::InputEvent::Gamepad(event));
+        }
+        self.world.resource_mut::<input::Input>().handle_gamepad_event(&event);
+    }
+
+    pub fn set_aspect(&mut self, aspect: f32)
+    {
+        if let Some(recorder) = &mut self.recorder
+        {
+            recorder.record(self.world.resource::<systems::Time>().tick, replay::InputEvent::Aspect(aspect));
+        }
+        // before the first level there's no camera, load_level() is given the aspect ratio then
+        if let Some(camera) = self.world.get_resource_mut::<camera::Camera>()
+        {
+            camera.aspect = aspect;
+        }
+    }
+
+    // one fixed simulation tick (see 'timestep.rs')
+    pub fn update(&mut self, tick: Duration)
+    {
+        for event in self.poll_gamepads()
+        {
+            self.gamepad_event(event);
+        }
+
+        let time = self.world.resource_mut::<systems::Time>();
+        time.tick += 1;
+        time.delta_seconds = tick.as_secs_f32();
+
+        // run every stage of the game once (see 'systems.rs'),
+        // which leaves what there is to draw in the RenderList
+        self.world.resource_mut::<debug_draw::DebugDraw>().begin_tick();
+        self.schedule.run_update(&mut self.world);
+        self.world.resource_mut::<debug_draw::DebugDraw>().end_tick();
+        // pressed/released only last for one tick
+        self.world.resource_mut::<input::Input>().end_tick();
+
+        if let Some(recorder) = &mut self.recorder
+        {
+            recorder.end_tick(&self.world, tick);
+        }
+    }
+
+    // a hash of the simulated state, the same inputs on the same level always give the same checksums
+    pub fn checksum(&self) -> u64
+    {
+        replay::checksum(&self.world)
+    }
+
+    // records from the next level load on, or right away if nothing has happened on this level yet
+    pub fn start_recording(&mut self, checksum_interval: u64)
+    {
+        let mut recorder = replay::Recorder::new(checksum_interval);
+        if let Some(level) = &self.level
+        {
+            if self.world.resource::<systems::Time>().tick == 0
+            {
+                recorder.begin(level, self.world.resource::<camera::Camera>().aspect, self.world.resource::<input::Input>());
+            }
+        }
+        self.recorder = Some(recorder);
+    }
+
+    // the recording of the current level, if there is one
+    pub fn stop_recording(&mut self) -> Option<replay::Recording>
+    {
+        self.recorder.take().and_then(replay::Recorder::finish)
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use cgmath::MetricSpace;
+    use super::*;
+    use crate::utils::gamepad::{ GamepadAxis, GamepadButton, GamepadId, VirtualGamepad };
+
+    const TICK: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+    // the main level, with a virtual pad that the test keeps a clone of
+    fn simulation_with_pad() -> (Simulation, VirtualGamepad)
+    {
+        let mut assets = assets::HeadlessAssets::new(concat!(env!("CARGO_MANIFEST_DIR"), "/assets"));
+        let mut simulation = Simulation::new(input::Bindings::default());
+        simulation.load_level(&mut assets, "scenes/main.ron", 1.0).unwrap();
+        let pad = VirtualGamepad::new(GamepadId(0));
+        simulation.set_gamepad_backend(Box::new(pad.clone()));
+        (simulation, pad)
+    }
+
+    #[test]
+    fn virtual_pad_pauses_and_orbits_the_camera()
+    {
+        let (mut simulation, pad) = simulation_with_pad();
+        let start = *simulation.world().resource::<camera::Camera>();
+
+        // polled one event at a time like the game states do, Start triggers pause as it goes in
+        pad.press(GamepadButton::Start);
+        pad.set_axis(GamepadAxis::LeftStickX, 1.0);
+        let mut paused = false;
+        for event in simulation.poll_gamepads()
+        {
+            simulation.gamepad_event(event);
+            paused |= simulation.world().resource::<input::Input>().triggered(input::PAUSE);
+        }
+        assert!(paused);
+        let input = simulation.world().resource::<input::Input>();
+        assert!(input.pressed(input::PAUSE));
+        assert!(input.held(input::PAUSE));
+        assert_eq!(input.axis(input::ORBIT), 1.0);
+
+        // the stick orbits the camera around its target, keeping its distance
+        simulation.update(TICK);
+        let input = simulation.world().resource::<input::Input>();
+        assert!(!input.pressed(input::PAUSE));
+        assert!(input.held(input::PAUSE));
+        let camera = *simulation.world().resource::<camera::Camera>();
+        assert!(camera.eye.distance(start.eye) > 0.01);
+        assert!((camera.eye.distance(camera.target) - start.eye.distance(start.target)).abs() < 1e-4);
+
+        // letting go stops it, and update() polls the pad itself
+        pad.release(GamepadButton::Start);
+        pad.set_axis(GamepadAxis::LeftStickX, 0.0);
+        simulation.update(TICK);
+        let moved = simulation.world().resource::<camera::Camera>().eye;
+        simulation.update(TICK);
+        let input = simulation.world().resource::<input::Input>();
+        assert!(!input.held(input::PAUSE));
+        assert_eq!(input.axis(input::ORBIT), 0.0);
+        assert_eq!(simulation.world().resource::<camera::Camera>().eye, moved);
+    }
+}
@@ -0,0 +1,426 @@
+use wgpu::util::DeviceExt;
+
+use super::
+{
+    assets,
+    camera,
+};
+
+
+/*
+    2D sprites: textured quads for the HUD and for the 2D puzzle levels.
+
+    Sprites are handed to State::draw_sprites() along with the orthographic
+    camera to see them through, any number of times a frame. They're all
+    drawn when the frame ends, on top of the 3D world and the debug lines,
+    in the order they were handed over. Within one call they're sorted by
+    layer (lowest first) and then by texture, every run of sprites with the
+    same texture is one draw call out of one shared vertex buffer.
+
+    A camera made with OrthographicCamera::screen() works in pixels with
+    (0, 0) at the top left like the window, one made with centered() works
+    in world units with y going up. Rotations are counter clockwise on
+    screen either way.
+*/
+
+/*   <--------Sprites-------->   */
+// the part of a texture a sprite shows, 0..1 across the texture with (0, 0) at the top left
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UvRect
+{
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+impl UvRect
+{
+    // the whole texture
+    pub const FULL: UvRect = UvRect { min: [0.0, 0.0], max: [1.0, 1.0] };
+
+    // a rectangle of pixels in a texture of texture_size pixels
+    pub fn from_pixels(x: u32, y: u32, width: u32, height: u32, texture_size: (u32, u32)) -> Self
+    {
+        let (texture_width, texture_height) = (texture_size.0 as f32, texture_size.1 as f32);
+        Self
+        {
+            min: [x as f32 / texture_width, y as f32 / texture_height],
+            max: [(x + width) as f32 / texture_width, (y + height) as f32 / texture_height],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Sprite
+{
+    pub texture: assets::TextureHandle,
+    // where the middle of the sprite goes, in the camera's units
+    pub position: cgmath::Vector2<f32>,
+    // width and height, in the camera's units
+    pub size: cgmath::Vector2<f32>,
+    // counter clockwise around the middle
+    pub rotation: cgmath::Rad<f32>,
+    // multiplied with the texture, alpha included
+    pub tint: [f32; 4],
+    pub uv: UvRect,
+    // higher layers are drawn over lower ones
+    pub layer: i32,
+}
+
+impl Sprite
+{
+    // the whole texture, untinted and unrotated
+    pub fn new(texture: assets::TextureHandle, position: cgmath::Vector2<f32>, size: cgmath::Vector2<f32>) -> Self
+    {
+        Self
+        {
+            texture,
+            position,
+            size,
+            rotation: cgmath::Rad(0.0),
+            tint: [1.0, 1.0, 1.0, 1.0],
+            uv: UvRect::FULL,
+            layer: 0,
+        }
+    }
+
+    // the four corners, top left then clockwise on screen
+    fn vertices(&self, y_up: bool) -> [SpriteVertex; 4]
+    {
+        let (sin, cos) = (self.rotation.0.sin(), self.rotation.0.cos());
+        // rotated as if y goes up, then flipped for cameras where it goes down
+        let flip = if y_up { 1.0 } else { -1.0 };
+        let corner = |x: f32, y: f32, tex_coords: [f32; 2]|
+        {
+            let (x, y) = (x * self.size.x, y * self.size.y);
+            SpriteVertex
+            {
+                position: [self.position.x + x * cos - y * sin, self.position.y + (x * sin + y * cos) * flip],
+                tex_coords,
+                color: self.tint,
+            }
+        };
+        let UvRect { min, max } = self.uv;
+        [
+            corner(-0.5, 0.5, min),
+            corner(0.5, 0.5, [max[0], min[1]]),
+            corner(0.5, -0.5, max),
+            corner(-0.5, -0.5, [min[0], max[1]]),
+        ]
+    }
+}
+
+
+/*   <--------Orthographic Camera-------->   */
+// what part of the 2D plane is on screen, left..right across and bottom..top up the screen
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OrthographicCamera
+{
+    pub left: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub top: f32,
+}
+
+impl OrthographicCamera
+{
+    // pixels, (0, 0) at the top left corner and y going down, for the HUD
+    pub fn screen(width: u32, height: u32) -> Self
+    {
+        Self { left: 0.0, right: width as f32, bottom: height as f32, top: 0.0 }
+    }
+
+    // world units, height of them on screen around center and y going up, for 2D levels
+    pub fn centered(center: cgmath::Point2<f32>, height: f32, aspect: f32) -> Self
+    {
+        let (half_width, half_height) = (height * aspect / 2.0, height / 2.0);
+        Self
+        {
+            left: center.x - half_width,
+            right: center.x + half_width,
+            bottom: center.y - half_height,
+            top: center.y + half_height,
+        }
+    }
+
+    pub fn y_up(&self) -> bool
+    {
+        self.top > self.bottom
+    }
+
+    pub fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32>
+    {
+        camera::OPENGL_TO_WGPU_MATRIX * cgmath::ortho(self.left, self.right, self.bottom, self.top, -1.0, 1.0)
+    }
+}
+
+
+/*   <--------Sprite Renderer-------->   */
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SpriteVertex
+{
+    position: [f32; 2],
+    tex_coords: [f32; 2],
+    color: [f32; 4],
+}
+
+impl SpriteVertex
+{
+    const ATTRIBUTES: [wgpu::VertexAttribute; 3] = wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32x4];
+
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a>
+    {
+        wgpu::VertexBufferLayout
+        {
+            array_stride: std::mem::size_of::<SpriteVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+// sprites handed to State::draw_sprites(), waiting for the frame to end
+pub struct SpriteBatch
+{
+    // usually an OrthographicCamera's, sprites placed in the world (see State::draw_world_sprites) use the 3D camera's
+    pub view_proj: cgmath::Matrix4<f32>,
+    // whether y goes up on screen, for which way to flip the sprites (see OrthographicCamera::y_up)
+    pub y_up: bool,
+    pub sprites: Vec<Sprite>,
+}
+
+impl SpriteBatch
+{
+    pub fn new(camera: &OrthographicCamera, sprites: Vec<Sprite>) -> Self
+    {
+        Self { view_proj: camera.build_view_projection_matrix(), y_up: camera.y_up(), sprites }
+    }
+}
+
+// one camera's uniform buffer, there are as many as the most batches there have been in a frame
+struct CameraSlot
+{
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+// consecutive quads in the vertex buffer with the same texture
+struct DrawRun
+{
+    texture: assets::TextureHandle,
+    quads: std::ops::Range<u32>,
+}
+
+pub struct SpriteRenderer
+{
+    pipeline: wgpu::RenderPipeline,
+    camera_bind_group_layout: wgpu::BindGroupLayout,
+    cameras: Vec<CameraSlot>,
+    // what each batch draws, from the last prepare()
+    runs: Vec<Vec<DrawRun>>,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    // in quads
+    capacity: usize,
+}
+
+impl SpriteRenderer
+{
+    // texture_bind_group_layout is the asset manager's, sprites use its textures
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, texture_bind_group_layout: &wgpu::BindGroupLayout) -> Self
+    {
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor
+        {
+            label: Some("Sprite Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/sprite.wgsl").into()),
+        });
+
+        let camera_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor
+        {
+            label: Some("sprite_camera_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry
+            {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer
+                {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor
+        {
+            label: Some("Sprite Pipeline Layout"),
+            bind_group_layouts: &[&camera_bind_group_layout, texture_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor
+        {
+            label: Some("Sprite Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState
+            {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[SpriteVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState
+            {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState
+                {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState
+            {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                // the screen camera flips y, which flips the winding too
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            // drawn in order on top of everything, layers decide what covers what
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let capacity = 256;
+        let (vertex_buffer, index_buffer) = Self::create_buffers(device, capacity);
+        Self
+        {
+            pipeline,
+            camera_bind_group_layout,
+            cameras: Vec::new(),
+            runs: Vec::new(),
+            vertex_buffer,
+            index_buffer,
+            capacity,
+        }
+    }
+
+    // the indices never change, two triangles for every quad
+    fn create_buffers(device: &wgpu::Device, capacity: usize) -> (wgpu::Buffer, wgpu::Buffer)
+    {
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor
+        {
+            label: Some("Sprite Vertex Buffer"),
+            size: (capacity * 4 * std::mem::size_of::<SpriteVertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let indices: Vec<u32> = (0..capacity as u32)
+            .flat_map(|quad| [0, 1, 2, 2, 3, 0].map(|corner| quad * 4 + corner))
+            .collect();
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor
+        {
+            label: Some("Sprite Index Buffer"),
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        (vertex_buffer, index_buffer)
+    }
+
+    fn camera_slot(&mut self, device: &wgpu::Device, index: usize) -> &CameraSlot
+    {
+        while self.cameras.len() <= index
+        {
+            // written before every draw
+            let buffer = device.create_buffer(&wgpu::BufferDescriptor
+            {
+                label: Some("Sprite Camera Buffer"),
+                size: std::mem::size_of::<[[f32; 4]; 4]>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor
+            {
+                label: Some("sprite_camera_bind_group"),
+                layout: &self.camera_bind_group_layout,
+                entries: &[wgpu::BindGroupEntry { binding: 0, resource: buffer.as_entire_binding() }],
+            });
+            self.cameras.push(CameraSlot { buffer, bind_group });
+        }
+        &self.cameras[index]
+    }
+
+    // uploads the batches' quads and cameras, ready for draw()
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, batches: &[SpriteBatch])
+    {
+        // all the batches' quads go in one buffer, each batch keeps the runs it draws
+        let mut vertices = Vec::new();
+        let mut runs: Vec<Vec<DrawRun>> = Vec::with_capacity(batches.len());
+        for (i, batch) in batches.iter().enumerate()
+        {
+            let camera = self.camera_slot(device, i);
+            let view_proj: [[f32; 4]; 4] = batch.view_proj.into();
+            queue.write_buffer(&camera.buffer, 0, bytemuck::cast_slice(&[view_proj]));
+
+            let mut sprites: Vec<&Sprite> = batch.sprites.iter().collect();
+            sprites.sort_by_key(|sprite| (sprite.layer, sprite.texture));
+
+            let mut batch_runs: Vec<DrawRun> = Vec::new();
+            for sprite in sprites
+            {
+                let quad = (vertices.len() / 4) as u32;
+                vertices.extend(sprite.vertices(batch.y_up));
+                match batch_runs.last_mut()
+                {
+                    Some(run) if run.texture == sprite.texture => run.quads.end += 1,
+                    _ => batch_runs.push(DrawRun { texture: sprite.texture, quads: quad..quad + 1 }),
+                }
+            }
+            runs.push(batch_runs);
+        }
+
+        // the buffers grow with the number of sprites, same as the instance buffer
+        let quads = vertices.len() / 4;
+        if quads > self.capacity
+        {
+            self.capacity = quads.next_power_of_two();
+            (self.vertex_buffer, self.index_buffer) = Self::create_buffers(device, self.capacity);
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+        self.runs = runs;
+    }
+
+    // draws every batch from the last prepare() over what's on screen, in order
+    pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, load: wgpu::LoadOp<wgpu::Color>, assets: &assets::AssetManager)
+    {
+        let mut sprite_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor
+        {
+            label: Some("Sprite Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment
+            {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations { load, store: true },
+            }],
+            depth_stencil_attachment: None,
+        });
+        sprite_pass.set_pipeline(&self.pipeline);
+        sprite_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        sprite_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        for (camera, batch_runs) in self.cameras.iter().zip(&self.runs)
+        {
+            sprite_pass.set_bind_group(0, &camera.bind_group, &[]);
+            for run in batch_runs
+            {
+                sprite_pass.set_bind_group(1, assets.texture_bind_group(run.texture), &[]);
+                sprite_pass.draw_indexed(run.quads.start * 6..run.quads.end * 6, 0, 0..1);
+            }
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use serde::{ Deserialize, Serialize };
+
+use super::atlas::PixelRect;
+
+
+/*
+    Sprite sheet animations: a list of frames, each a rectangle of the
+    sheet and how long it stays up. They're plain data so they can be
+    written into data files (see 'data_file.rs'):
+
+        (
+            frames: [
+                (rect: (x: 0, y: 0, width: 32, height: 32), seconds: 0.1),
+                (rect: (x: 32, y: 0, width: 32, height: 32), seconds: 0.1),
+            ],
+            looping: true,
+        )
+
+    The rects are in the sheet's own pixels, whether the sheet is a texture
+    of its own (PixelRect::uv) or packed into an atlas
+    (TextureAtlas::uv_within). An AnimationPlayer keeps the time for one
+    sprite, so many sprites can share an animation at different points.
+*/
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnimationFrame
+{
+    pub rect: PixelRect,
+    pub seconds: f32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpriteAnimation
+{
+    pub frames: Vec<AnimationFrame>,
+    // starts over after the last frame, otherwise it stays on it
+    #[serde(default)]
+    pub looping: bool,
+}
+
+impl SpriteAnimation
+{
+    // count frames of the same size, left to right and then down a row after columns of them,
+    // starting from the top left of the sheet, 0 columns is taken as 1
+    pub fn grid(frame_width: u32, frame_height: u32, columns: u32, count: u32, seconds: f32, looping: bool) -> Self
+    {
+        let columns = columns.max(1);
+        let frames = (0..count)
+            .map(|i| AnimationFrame
+            {
+                rect: PixelRect { x: (i % columns) * frame_width, y: (i / columns) * frame_height, width: frame_width, height: frame_height },
+                seconds,
+            })
+            .collect();
+        Self { frames, looping }
+    }
+
+    // one time through
+    pub fn duration(&self) -> f32
+    {
+        self.frames.iter().map(|frame| frame.seconds).sum()
+    }
+
+    // the frame that's up this long after starting, None without any frames
+    pub fn frame_at(&self, seconds: f32) -> Option<&AnimationFrame>
+    {
+        let duration = self.duration();
+        let mut time = if self.looping && duration > 0.0 { seconds.rem_euclid(duration) } else { seconds };
+        for frame in &self.frames
+        {
+            if time < frame.seconds
+            {
+                return Some(frame);
+            }
+            time -= frame.seconds;
+        }
+        self.frames.last()
+    }
+
+    pub fn is_finished(&self, seconds: f32) -> bool
+    {
+        !self.looping && seconds >= self.duration()
+    }
+}
+
+// where one sprite is in an animation
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AnimationPlayer
+{
+    pub seconds: f32,
+    // 1 -> as the frames say, 2 -> twice as fast, 0 -> paused
+    pub speed: f32,
+}
+
+impl Default for AnimationPlayer
+{
+    fn default() -> Self
+    {
+        Self { seconds: 0.0, speed: 1.0 }
+    }
+}
+
+impl AnimationPlayer
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    // every tick (see 'timestep.rs'), or every frame for animations that don't affect the game
+    pub fn update(&mut self, delta_seconds: f32)
+    {
+        self.seconds += delta_seconds * self.speed;
+    }
+
+    pub fn restart(&mut self)
+    {
+        self.seconds = 0.0;
+    }
+
+    pub fn frame<'a>(&self, animation: &'a SpriteAnimation) -> Option<&'a AnimationFrame>
+    {
+        animation.frame_at(self.seconds)
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn rect(x: u32, y: u32) -> PixelRect
+    {
+        PixelRect { x, y, width: 16, height: 8 }
+    }
+
+    #[test]
+    fn grid_frames_go_across_then_down()
+    {
+        let animation = SpriteAnimation::grid(16, 8, 3, 5, 0.1, true);
+        let rects: Vec<_> = animation.frames.iter().map(|frame| frame.rect).collect();
+        assert_eq!(rects, vec![rect(0, 0), rect(16, 0), rect(32, 0), rect(0, 8), rect(16, 8)]);
+        assert!((animation.duration() - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn grid_without_columns_is_one_column()
+    {
+        let animation = SpriteAnimation::grid(16, 8, 0, 3, 0.1, false);
+        let rects: Vec<_> = animation.frames.iter().map(|frame| frame.rect).collect();
+        assert_eq!(rects, vec![rect(0, 0), rect(0, 8), rect(0, 16)]);
+    }
+}
@@ -9,31 +9,164 @@ use super::
 {
     vertex,
     texture,
+    assets,
+    instance,
+    scene,
+    light,
+    systems,
+    data_file,
+    simulation,
+    ecs,
     camera,
-    camera_controller,
+    input,
+    picking,
+    debug_draw,
+    render_mode,
+    mesh,
+    bounds,
+    sprite,
+    atlas,
+    text,
+    ui,
+    console,
+    profiler,
+    capture,
+    settings::{ self, Settings },
+    gpu,
+    gamepad,
+    paths,
 };
 
 
-/*
-    we store all the unique vertices in VERTICES and 
-    we create another buffer that stores indices to 
-    elements in VERTICES to create the triangles
-*/
-// Changed
-const VERTICES: &[vertex::Vertex] = 
-&[
-    vertex::Vertex { position: [-0.0868241, 0.49240386, 0.0], tex_coords: [0.4131759, 0.00759614], },
-    vertex::Vertex { position: [-0.49513406, 0.06958647, 0.0], tex_coords: [0.0048659444, 0.43041354], },
-    vertex::Vertex { position: [-0.21918549, -0.44939706, 0.0], tex_coords: [0.28081453, 0.949397], },
-    vertex::Vertex { position: [0.35966998, -0.3473291, 0.0], tex_coords: [0.85967, 0.84732914], },
-    vertex::Vertex { position: [0.44147372, 0.2347359, 0.0], tex_coords: [0.9414737, 0.2652641], },
-];
-const INDICES: &[u16] = 
-&[
-    0, 1, 4,
-    1, 2, 4,
-    2, 3, 4,
-];
+// the level loaded at startup, relative to the asset root (see 'paths.rs')
+pub const MAIN_SCENE: &str = "scenes/main.ron";
+// the font text is drawn in unless it says otherwise, relative to the asset root (see 'text.rs')
+const DEFAULT_FONT: &str = "fonts/DejaVuSans.ttf";
+// the window and graphics settings, in the player's config folder or the defaults in the assets (see 'settings.rs' and 'paths.rs')
+const SETTINGS: &str = "settings.ron";
+// asked for when the gpu has them: wireframes drawn as lines (see 'render_mode.rs') and the profiler's gpu timings (see 'profiler.rs')
+const OPTIONAL_FEATURES: wgpu::Features = wgpu::Features::POLYGON_MODE_LINE.union(wgpu::Features::TIMESTAMP_QUERY);
+// frames in a row that can time out waiting for the surface before it's configured again
+const MAX_SURFACE_TIMEOUTS: u32 = 3;
+
+
+// consecutive instances in the instance buffer that share a mesh and texture,
+// drawn with a single draw call
+struct DrawBatch
+{
+    attachment: scene::MeshAttachment,
+    instances: std::ops::Range<u32>,
+}
+
+
+// counts from the last frame the world was drawn in
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RenderStats
+{
+    // objects on screen
+    pub drawn: usize,
+    // objects skipped because they were out of view
+    pub culled: usize,
+    pub draw_calls: usize,
+}
+
+
+// why State::new() couldn't start the game
+#[derive(Debug)]
+pub enum InitError
+{
+    // every gpu tried (see 'gpu.rs') and why each one didn't work
+    NoGpu(Vec<(String, gpu::GpuError)>),
+    // the asset workers or the placeholder textures (see 'assets.rs')
+    Assets(anyhow::Error),
+    // the glyph cache (see 'text.rs')
+    Text(anyhow::Error),
+}
+
+impl std::fmt::Display for InitError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        match self
+        {
+            InitError::NoGpu(failures) =>
+            {
+                write!(f, "no gpu to draw with, tried:")?;
+                for (attempt, e) in failures
+                {
+                    write!(f, "\n    {}: {}", attempt, e)?;
+                }
+                Ok(())
+            }
+            InitError::Assets(e) => write!(f, "couldn't start loading assets: {:#}", e),
+            InitError::Text(e) => write!(f, "couldn't make the glyph cache: {:#}", e),
+        }
+    }
+}
+
+impl std::error::Error for InitError {}
+
+
+// one frame being drawn, from State::begin_frame() to State::end_frame(),
+// the game states each draw their part of it in between (see 'game_state.rs')
+pub struct Frame
+{
+    output: wgpu::SurfaceTexture,
+    // the surface's, or the capture target's when this frame is being saved (see 'capture.rs')
+    view: wgpu::TextureView,
+    capture: Option<capture::FrameCapture>,
+    encoder: wgpu::CommandEncoder,
+    // the first pass clears the screen, the ones after draw on top
+    cleared: bool,
+    // debug lines go on top of the world, so only frames with the world in them get them
+    world_drawn: bool,
+    // drawn over everything else when the frame ends (see 'sprite.rs')
+    sprite_batches: Vec<sprite::SpriteBatch>,
+    pub clear_color: wgpu::Color,
+}
+
+impl Frame
+{
+    // what the next pass should do with what's already on screen
+    fn color_load_op(&mut self) -> wgpu::LoadOp<wgpu::Color>
+    {
+        if std::mem::replace(&mut self.cleared, true)
+        {
+            wgpu::LoadOp::Load
+        }
+        else
+        {
+            wgpu::LoadOp::Clear(self.clear_color)
+        }
+    }
+}
+
+
+// what State::create_gpu_resources() makes, to go into State's fields
+struct GpuResources
+{
+    surface: wgpu::Surface,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    config: wgpu::SurfaceConfiguration,
+    device_lost: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    line_mode_supported: bool,
+    shader: wgpu::ShaderModule,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    render_pipelines: std::collections::HashMap<render_mode::RenderSettings, wgpu::RenderPipeline>,
+    depth_texture: texture::Texture,
+    msaa_view: Option<wgpu::TextureView>,
+    instance_buffer: wgpu::Buffer,
+    instance_capacity: usize,
+    debug_renderer: debug_draw::DebugRenderer,
+    sprite_renderer: sprite::SpriteRenderer,
+    ui: ui::Ui,
+    profiler: profiler::Profiler,
+    light_buffer: wgpu::Buffer,
+    light_bind_group: wgpu::BindGroup,
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+}
 
 
 /*   <--------Global State-------->   */
@@ -44,15 +177,60 @@ pub struct State
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
+    // set when the device is lost, main.rs calls recover_device() (see 'gpu.rs')
+    device_lost: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    // nothing is drawn while the window is minimized or the app is suspended
+    minimized: bool,
+    suspended: bool,
+    // frames in a row that timed out waiting for the surface
+    surface_timeouts: u32,
     clear_color: wgpu::Color,
-    render_pipeline: wgpu::RenderPipeline,
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
-    num_indices: u32,
-    diffuse_bind_group: wgpu::BindGroup,
-    diffuse_texture: texture::Texture,
-    camera: camera::Camera,
-    camera_controller: camera_controller::CameraController,
+    // the world's pipelines are made as they're needed, when the render mode changes
+    shader: wgpu::ShaderModule,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    render_pipelines: std::collections::HashMap<render_mode::RenderSettings, wgpu::RenderPipeline>,
+    render_settings: render_mode::RenderSettings,
+    depth_texture: texture::Texture,
+    // the window and graphics settings (see 'settings.rs'), and whether main.rs still has to apply them to the window
+    settings: Settings,
+    window_changed: bool,
+    // the world is drawn in here and resolved to the surface when msaa is on
+    msaa_view: Option<wgpu::TextureView>,
+    assets: assets::AssetManager,
+    simulation: simulation::Simulation,
+    // where to save the recording when the game closes (see 'replay.rs')
+    record_to: Option<std::path::PathBuf>,
+    instance_buffer: wgpu::Buffer,
+    instance_capacity: usize,
+    draw_batches: Vec<DrawBatch>,
+    // which node each instance in the instance buffer is, and the alpha it was drawn at
+    drawn_nodes: Vec<scene::NodeId>,
+    drawn_alpha: f32,
+    // window side only, the simulation never sees the cursor
+    cursor: Option<winit::dpi::PhysicalPosition<f64>>,
+    // made the first time it's used (see 'picking.rs')
+    id_buffer: Option<picking::IdBuffer>,
+    gpu_picking: bool,
+    // objects outside the camera's view are skipped (see Frustum in 'bounds.rs')
+    frustum_culling: bool,
+    render_stats: RenderStats,
+    debug_renderer: debug_draw::DebugRenderer,
+    sprite_renderer: sprite::SpriteRenderer,
+    text: text::TextRenderer,
+    // menus and panels drawn over everything (see 'ui.rs')
+    ui: ui::Ui,
+    // the developer console, over the top of the screen while it's open (see 'console.rs')
+    console: console::ConsoleOverlay,
+    // where the time in a frame goes, and whether its window is up (see 'profiler.rs')
+    profiler: profiler::Profiler,
+    show_profiler: bool,
+    // screenshots and frame sequences (see 'capture.rs')
+    capture: capture::Capture,
+    // when the last frame started, and how long frames have been taking lately in seconds
+    last_frame: Option<std::time::Instant>,
+    frame_seconds: f32,
+    light_buffer: wgpu::Buffer,
+    light_bind_group: wgpu::BindGroup,
     camera_uniform: camera::CameraUniform,
     camera_buffer: wgpu::Buffer,
     camera_bind_group: wgpu::BindGroup,
@@ -62,124 +240,194 @@ impl State
 {
     // the new function creates a new global state
     // This is like the constructor
-    pub async fn new(window: &Window) -> Self 
+    // settings pick the gpu and how it draws, main.rs has already applied them to the window
+    pub async fn new(window: &Window, settings: Settings) -> Result<Self, InitError>
     {
         let size = window.inner_size();
 
-        // The instance is a handle to our GPU
-        // Its main purpose is to create Adapters and Surfaces.
-        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        // the first gpu that works, falling back to others when the one in the settings doesn't (see 'gpu.rs')
+        let gpu = gpu::request(window, &settings, OPTIONAL_FEATURES).await.map_err(InitError::NoGpu)?;
 
-        // The surface is the part of the window that we draw to
-        let surface = unsafe { instance.create_surface(window) };
+        // Asset loading (see 'assets.rs')
+        // decoding happens on worker threads, until it's done the texture is a placeholder
+        let mut assets = assets::AssetManager::new(&gpu.device, &gpu.queue, paths::asset_root()).map_err(InitError::Assets)?;
+        // re-exported textures and models show up without restarting
+        if let Err(e) = assets.watch()
+        {
+            log::warn!("asset hot-reload is off: {:#}", e);
+        }
 
-        // The adapter is a handle to the actual graphics card
-        // You can use it to get info about the card
-        let adapter = instance.request_adapter(
-            &wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            },
-        ).await.unwrap();
-
-        // Get device and queue from adapter
-        let (device, queue) = adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                features: wgpu::Features::empty(),
-                limits: wgpu::Limits::default(),
-                label: None,
-            },
-            None, // Trace path
-        ).await.unwrap();
+        // text is drawn as sprites out of a glyph cache (see 'text.rs'), the game still runs without it
+        let mut text = text::TextRenderer::new(&gpu.device, &gpu.queue, &mut assets).map_err(InitError::Text)?;
+        if let Err(e) = text.load_font(&assets.root().join(DEFAULT_FONT))
+        {
+            log::error!("no text: {:#}", e);
+        }
+
+        let GpuResources
+        {
+            surface, device, queue, config, device_lost, line_mode_supported,
+            shader, render_pipeline_layout, render_pipelines, depth_texture, msaa_view,
+            instance_buffer, instance_capacity, debug_renderer, sprite_renderer, ui, profiler,
+            light_buffer, light_bind_group, camera_buffer, camera_bind_group,
+        } = Self::create_gpu_resources(window, gpu, &settings, &assets);
+
+        // <----- World ----->
+        // the game objects and the systems that run them (see 'simulation.rs' and 'systems.rs'),
+        // the level itself comes from a scene file, loaded by the loading screen (see 'screens.rs')
+        // only the gamepad backend below changes it
+        #[cfg_attr(not(feature = "gilrs"), allow(unused_mut))]
+        let mut simulation = simulation::Simulation::new(Self::load_bindings(&paths::config_file(assets.root(), input::BINDINGS_FILE)));
+
+        // controllers, when the game is built with them (see 'gamepad.rs')
+        #[cfg(feature = "gilrs")]
+        match gamepad::GilrsBackend::new()
+        {
+            Ok(backend) => simulation.set_gamepad_backend(Box::new(backend)),
+            Err(e) => log::warn!("no gamepads: {:#}", e),
+        }
+
+        // the camera itself is a resource in the world once a level is loaded, this is the cpu side of the camera buffer
+        let camera_uniform = camera::CameraUniform::new();
+
+        let clear_color = wgpu::Color::BLACK;
+        let render_settings = render_mode::RenderSettings { line_mode: line_mode_supported, ..Default::default() };
+
+        Ok(Self {
+            surface,
+            device,
+            queue,
+            config,
+            size,
+            device_lost,
+            minimized: false,
+            suspended: false,
+            surface_timeouts: 0,
+            clear_color,
+            shader,
+            render_pipeline_layout,
+            render_pipelines,
+            render_settings,
+            depth_texture,
+            settings,
+            window_changed: false,
+            msaa_view,
+            assets,
+            simulation,
+            record_to: None,
+            instance_buffer,
+            instance_capacity,
+            draw_batches: Vec::new(),
+            drawn_nodes: Vec::new(),
+            drawn_alpha: 1.0,
+            cursor: None,
+            id_buffer: None,
+            gpu_picking: false,
+            frustum_culling: true,
+            render_stats: RenderStats::default(),
+            debug_renderer,
+            sprite_renderer,
+            text,
+            ui,
+            console: console::ConsoleOverlay::new(),
+            profiler,
+            show_profiler: false,
+            capture: capture::Capture::new(),
+            last_frame: None,
+            frame_seconds: 0.0,
+            light_buffer,
+            light_bind_group,
+            camera_uniform,
+            camera_buffer,
+            camera_bind_group,
+        })
+    }
+
+    // everything on the gpu besides the assets, made by new() and made again by recover_device()
+    fn create_gpu_resources(window: &Window, gpu: gpu::Gpu, settings: &Settings, assets: &assets::AssetManager) -> GpuResources
+    {
+        let gpu::Gpu { surface, adapter: _, device, queue, format } = gpu;
+        let size = window.inner_size();
+        // errors that say the device was lost are caught instead of panicking, to make all of this again
+        let device_lost = gpu::watch_for_loss(&device);
+        // wireframes are drawn as lines when the gpu can, and with a shader when it can't (see 'render_mode.rs')
+        let line_mode_supported = device.features().contains(wgpu::Features::POLYGON_MODE_LINE);
 
         // This is the config for the surface
         // it will define how the surface creates its underlying SurfaceTextures
         let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,              // how SurfaceTextures are used
-            format: surface.get_preferred_format(&adapter).unwrap(),    // how SurfaceTextures are stored on gpu
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,              // how SurfaceTextures are used, screenshots are drawn offscreen (see 'capture.rs')
+            format,                                                     // how SurfaceTextures are stored on gpu
             width: size.width,
             height: size.height,
-            present_mode: wgpu::PresentMode::Fifo,                      // how to sync surface with display
+            present_mode: settings.present_mode.to_wgpu(),              // how to sync surface with display
         };
         surface.configure(&device, &config);
 
+        let depth_texture = texture::Texture::create_depth_texture(&device, &config, settings.msaa, "depth_texture");
+        let msaa_view = Self::create_msaa_view(&device, &config, settings.msaa);
 
+        // instance data is rewritten every frame, the buffer grows when the scene does
+        let instance_capacity = 16;
+        let instance_buffer = Self::create_instance_buffer(&device, instance_capacity);
 
 
-        // Texture creation (see 'texture.rs')
-        let diffuse_bytes = include_bytes!("../../images/happy_tree.png");
-        let diffuse_texture = texture::Texture::from_bytes(&device, &queue, diffuse_bytes, "../../images/happy_tree.png").unwrap();
+        // <----- Light Buffer ----->
+        // filled in from the scene's lights every update
+        let light_uniform = light::LightUniform::new();
 
+        let light_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor
+            {
+                label: Some("Light Buffer"),
+                contents: bytemuck::cast_slice(&[light_uniform]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
 
-        // A BindGroup describes a set of resources and how they can be accessed by a shader
-        let texture_bind_group_layout = 
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
+        let light_bind_group_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor
+            {
+                entries:
+                &[
                     wgpu::BindGroupLayoutEntry
                     {
                         binding: 0,
                         visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture
+                        ty: wgpu::BindingType::Buffer
                         {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
                         },
                         count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry
-                    {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
+                    }
                 ],
-                label: Some("texture_bind_group_layout"),
-            });
+                label: Some("light_bind_group_layout"),
+            }
+        );
 
-        // a BindGroup is a more specific declaration of the BindGroupLayout
-        let diffuse_bind_group = device.create_bind_group(
+        let light_bind_group = device.create_bind_group(
             &wgpu::BindGroupDescriptor
             {
-                layout: &texture_bind_group_layout,
-                entries: &[
+                layout: &light_bind_group_layout,
+                entries:
+                &[
                     wgpu::BindGroupEntry
                     {
                         binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                    },
+                        resource: light_buffer.as_entire_binding(),
+                    }
                 ],
-                label: Some("diffuse_bind_group"),
+                label: Some("light_bind_group")
             }
         );
 
         // Camera Stuff
+        // the camera itself is a resource in the world, this is the gpu side of it
 
-        let camera_controller = camera_controller::CameraController::new(0.05);
-
-        let camera = camera::Camera
-        {
-            // position the camera one unit up and 2 units back
-            // +z is out of the screen
-            eye: (0.0, 1.0, 2.0).into(),
-            // have it look at the origin
-            target: (0.0, 0.0, 0.0).into(),
-            // which way is "up"
-            up: cgmath::Vector3::unit_y(),
-            aspect: config.width as f32 / config.height as f32,
-            fovy: 45.0,
-            znear: 0.1,
-            zfar: 100.0,
-        };
-
-        let mut camera_uniform = camera::CameraUniform::new();
-        camera_uniform.update_view_proj(&camera);
+        // filled in from the camera every time the world is drawn (see prepare_world)
+        let camera_uniform = camera::CameraUniform::new();
 
         let camera_buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor
@@ -198,7 +446,7 @@ impl State
                     wgpu::BindGroupLayoutEntry
                     {
                         binding: 0,
-                        visibility: wgpu::ShaderStages::VERTEX,
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,     // the depth render mode needs the near and far planes
                         ty: wgpu::BindingType::Buffer
                         {
                             ty: wgpu::BufferBindingType::Uniform,
@@ -227,9 +475,7 @@ impl State
                 label: Some("camera_bind_group")
             }
         );
-        
-        
-        let clear_color = wgpu::Color::BLACK;
+
 
         // <--------------Making Render Pipeline-------------->
 
@@ -245,34 +491,70 @@ impl State
             label: Some("Render Pipeline Layout"),
             bind_group_layouts: 
             &[
-                &texture_bind_group_layout,
+                assets.texture_bind_group_layout(),
                 &camera_bind_group_layout,
+                &light_bind_group_layout,
             ],
             push_constant_ranges: &[],
         });
+        // the world's pipelines for each render mode are made the first time they're drawn with (see prepare_render_mode)
+        let render_pipelines = std::collections::HashMap::new();
+
+        // <--------------END-------------->
+
+
+        // lines over the world, for debugging (see 'debug_draw.rs')
+        let debug_renderer = debug_draw::DebugRenderer::new(&device, config.format, settings.msaa, &camera_buffer);
+        // 2D sprites over everything (see 'sprite.rs')
+        let sprite_renderer = sprite::SpriteRenderer::new(&device, config.format, assets.texture_bind_group_layout());
+        // laid out in points, so it needs the scale factor from the start
+        let ui = ui::Ui::new(&device, config.format, size, window.scale_factor());
+        let profiler = profiler::Profiler::new(&device, &queue);
+
+        GpuResources
+        {
+            surface, device, queue, config, device_lost, line_mode_supported,
+            shader, render_pipeline_layout, render_pipelines, depth_texture, msaa_view,
+            instance_buffer, instance_capacity, debug_renderer, sprite_renderer, ui, profiler,
+            light_buffer, light_bind_group, camera_buffer, camera_bind_group,
+        }
+    }
+
+    // the pipeline the world is drawn with, one for every combination of render settings (see 'render_mode.rs'),
+    // target is the format and sample count of what it draws into
+    fn create_render_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        (format, sample_count): (wgpu::TextureFormat, u32),
+        settings: render_mode::RenderSettings,
+    ) -> wgpu::RenderPipeline
+    {
+        // the mesh's own vertices, or every triangle on its own for the barycentric wireframe
+        let vertices = if settings.barycentric_wireframe() { mesh::WireframeMesh::desc() } else { vertex::Vertex::desc() };
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor
         {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
+            label: Some(&format!("Render Pipeline ({}, {:?})", settings.mode.name(), settings.alpha)),
+            layout: Some(layout),
 
             // Vertex shader
             vertex: wgpu::VertexState
             {
-                module: &shader,
-                entry_point: "vs_main",             // entry point of shader (name of fn)
-                buffers: &[vertex::Vertex::desc(),],        // what type of vertices we want to pass to the vertex shader
+                module: shader,
+                entry_point: settings.vertex_entry(),   // entry point of shader (name of fn)
+                buffers: &[vertices, instance::InstanceRaw::desc()],        // what type of vertices we want to pass to the vertex shader
             },
 
             // Fragment shader
             fragment: Some(wgpu::FragmentState 
             {
-                module: &shader,
-                entry_point: "fs_main",
+                module: shader,
+                entry_point: settings.fragment_entry(),     // a different one for each render mode
                 targets: &[wgpu::ColorTargetState   // what color outputs it should set up
                 {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
+                    format,
+                    blend: Some(settings.blend_state()),     // alpha blending for the transparent pass
                     write_mask: wgpu::ColorWrites::ALL,
                 }],
             }),
@@ -282,82 +564,354 @@ impl State
                 topology: wgpu::PrimitiveTopology::TriangleList,    // each three vertices will correspond to one triangle
                 strip_index_format: None,
                 front_face: wgpu::FrontFace::Ccw,                   // tell wgpu how to determine whether a given triangle is facing forward or not
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
+                cull_mode: settings.cull_mode(),                    // back faces are skipped unless culling is turned off
+                polygon_mode: settings.polygon_mode(),              // Line for wireframes, when the device can
                 unclipped_depth: false,
                 conservative: false,
             },
-            depth_stencil: None,
+            // draw the closest fragment, whatever order objects are drawn in
+            depth_stencil: Some(wgpu::DepthStencilState
+            {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: settings.depth_write(),
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
             multisample: wgpu::MultisampleState {
-                count: 1,                                           // how many samples the pipeline will use
+                count: sample_count,                                // how many samples the pipeline will use
                 mask: !0,                                           // which samples should be active (all in this case)
                 alpha_to_coverage_enabled: false,
             },
             multiview: None,                                       // how many array layers the render attachments can have
-        });
+        })
+    }
 
-        // <----- Vertex Buffer ----->
-        let vertex_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor
-            {
-                label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(VERTICES),          // using bytemuck to cast VERTICES as a &[u8]
-                usage: wgpu::BufferUsages::VERTEX,
-            }
-        );
+    // None when msaa is off, the world is drawn straight to the surface then
+    fn create_msaa_view(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, sample_count: u32) -> Option<wgpu::TextureView>
+    {
+        (sample_count > 1).then(|| texture::Texture::create_multisampled_view(device, config, sample_count, "msaa_texture"))
+    }
 
-        // <----- Indices Buffer ----->
-        let index_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor
+    // where the world and the debug lines over it are drawn, the multisampled texture resolves to the frame
+    // at the end of each pass (it keeps its own contents in between, so the world has to be the first thing drawn)
+    fn world_color_attachment<'a>(msaa_view: &'a Option<wgpu::TextureView>, frame_view: &'a wgpu::TextureView, load: wgpu::LoadOp<wgpu::Color>) -> wgpu::RenderPassColorAttachment<'a>
+    {
+        let (view, resolve_target) = match msaa_view
+        {
+            Some(msaa_view) => (msaa_view, Some(frame_view)),
+            None => (frame_view, None),
+        };
+        wgpu::RenderPassColorAttachment { view, resolve_target, ops: wgpu::Operations { load, store: true } }
+    }
+
+    // loads a scene file (relative to the asset root), the old level stays if the new one can't be loaded
+    pub fn load_level(&mut self, level: &str) -> Result<(), data_file::DataError>
+    {
+        let aspect = self.config.width as f32 / self.config.height as f32;
+        self.simulation.load_level(&mut self.assets, level, aspect)?;
+        // the player's field of view over the one in the scene file
+        self.world_mut().resource_mut::<camera::Camera>().fovy = self.settings.fov;
+        Ok(())
+    }
+
+    pub fn world(&self) -> &ecs::World
+    {
+        self.simulation.world()
+    }
+
+    pub fn world_mut(&mut self) -> &mut ecs::World
+    {
+        self.simulation.world_mut()
+    }
+
+    // records every level played from now on, the last one is saved to path by finish_recording(),
+    // with a checksum every checksum_interval ticks
+    pub fn start_recording(&mut self, path: impl Into<std::path::PathBuf>, checksum_interval: u64)
+    {
+        self.simulation.start_recording(checksum_interval);
+        self.record_to = Some(path.into());
+    }
+
+    pub fn finish_recording(&mut self) -> Result<(), data_file::DataError>
+    {
+        let recording = self.simulation.stop_recording();
+        match (recording, self.record_to.take())
+        {
+            (Some(recording), Some(path)) =>
             {
-                label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(INDICES),          // using bytemuck to cast VERTICES as a &[u8]
-                usage: wgpu::BufferUsages::INDEX,
+                recording.save(&path)?;
+                log::info!("saved a recording of {} ticks to {}", recording.ticks, path.display());
+                Ok(())
             }
-        );
+            _ => Ok(()),
+        }
+    }
 
-        let num_indices = INDICES.len() as u32;
+    // a broken bindings file shouldn't stop the game from starting, it just gets the default controls
+    fn load_bindings(path: &std::path::Path) -> input::Bindings
+    {
+        if !path.exists()
+        {
+            return input::Bindings::default();
+        }
+        input::Bindings::load(path).unwrap_or_else(|e|
+        {
+            log::error!("{}, using the default controls", e);
+            input::Bindings::default()
+        })
+    }
 
-        // <--------------END-------------->
 
-        Self {
-            surface,
-            device,
-            queue,
-            config,
-            size,
-            clear_color,
-            render_pipeline,
-            vertex_buffer,
-            index_buffer,
-            num_indices,
-            diffuse_bind_group,
-            diffuse_texture,
-            camera,
-            camera_controller,
-            camera_uniform,
-            camera_buffer,
-            camera_bind_group,
+    // the settings the window and State::new() start with, a broken file is reported and the defaults are used
+    pub fn load_settings() -> Settings
+    {
+        let path = paths::config_file(&paths::asset_root(), SETTINGS);
+        if !path.exists()
+        {
+            return Settings::default();
         }
+        Settings::load(&path).unwrap_or_else(|e|
+        {
+            log::error!("{}, using the default settings", e);
+            Settings::default()
+        })
+    }
+
+    pub fn settings(&self) -> &Settings
+    {
+        &self.settings
+    }
+
+    // changes what it can straight away, the window is changed by main.rs (see take_window_changes())
+    // and a different backend or power preference waits for the game to be started again
+    pub fn set_settings(&mut self, settings: Settings) -> Result<(), settings::SettingError>
+    {
+        settings.validate()?;
+        if self.settings.needs_restart(&settings)
+        {
+            log::info!("the backend and power preference change the next time the game starts");
+        }
+        self.window_changed |= self.settings.changes_window(&settings);
+        if settings.present_mode != self.settings.present_mode
+        {
+            self.config.present_mode = settings.present_mode.to_wgpu();
+            self.surface.configure(&self.device, &self.config);
+        }
+        if settings.msaa != self.settings.msaa
+        {
+            // everything drawn into the world's targets needs the new sample count
+            self.depth_texture = texture::Texture::create_depth_texture(&self.device, &self.config, settings.msaa, "depth_texture");
+            self.msaa_view = Self::create_msaa_view(&self.device, &self.config, settings.msaa);
+            self.render_pipelines.clear();
+            self.debug_renderer = debug_draw::DebugRenderer::new(&self.device, self.config.format, settings.msaa, &self.camera_buffer);
+        }
+        // there's no camera until a level is loaded, load_level() gives it the field of view then
+        if let Some(camera) = self.world_mut().get_resource_mut::<camera::Camera>()
+        {
+            camera.fovy = settings.fov;
+        }
+        self.settings = settings;
+        Ok(())
+    }
+
+    // the settings to apply to the window, once after they've changed its size or mode
+    pub fn take_window_changes(&mut self) -> Option<Settings>
+    {
+        std::mem::take(&mut self.window_changed).then(|| self.settings.clone())
+    }
+
+    // writes the current settings to the player's config folder
+    pub fn save_settings(&self) -> Result<(), data_file::DataError>
+    {
+        self.settings.save(&paths::user_config(SETTINGS)?)
     }
 
     // Changes the size of the window, through the global state
+    // a size of zero is the window being minimized, the surface can't be that small so it stays as it is
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>)
     {
-        if new_size.width > 0 && new_size.height > 0
+        self.minimized = new_size.width == 0 || new_size.height == 0;
+        if !self.minimized
         {
             // just change the surface config w/ new dimensions
             self.size = new_size;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
             self.surface.configure(&self.device, &self.config);
+            self.depth_texture = texture::Texture::create_depth_texture(&self.device, &self.config, self.settings.msaa, "depth_texture");
+            self.msaa_view = Self::create_msaa_view(&self.device, &self.config, self.settings.msaa);
+            self.simulation.set_aspect(self.config.width as f32 / self.config.height as f32);
+            self.ui.resize(new_size);
         }
     }
 
+    // the app was suspended (true) or resumed (false) by the system
+    pub fn set_suspended(&mut self, suspended: bool)
+    {
+        self.suspended = suspended;
+    }
+
+    // whether there's anywhere to draw to, main.rs skips rendering (and waits for events) when there isn't
+    pub fn is_drawable(&self) -> bool
+    {
+        !self.minimized && !self.suspended
+    }
+
+    // gets drawing going again after begin_frame() failed, Err -> it can't (the gpu is out of memory)
+    pub fn recover_surface(&mut self, error: wgpu::SurfaceError) -> Result<(), wgpu::SurfaceError>
+    {
+        match error
+        {
+            // the surface went away or doesn't match the window anymore, configuring it again fixes both
+            wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated =>
+            {
+                log::info!("the surface is {:?}, configuring it again", error);
+                self.configure_surface();
+                Ok(())
+            }
+            // one now and then is the compositor being busy, a few in a row and the surface is probably stuck
+            wgpu::SurfaceError::Timeout =>
+            {
+                self.surface_timeouts += 1;
+                log::warn!("timed out waiting for a frame to draw to ({} in a row)", self.surface_timeouts);
+                if self.surface_timeouts >= MAX_SURFACE_TIMEOUTS
+                {
+                    self.configure_surface();
+                    self.surface_timeouts = 0;
+                }
+                Ok(())
+            }
+            wgpu::SurfaceError::OutOfMemory => Err(error),
+        }
+    }
+
+    fn configure_surface(&mut self)
+    {
+        if self.is_drawable()
+        {
+            self.surface.configure(&self.device, &self.config);
+        }
+    }
+
+    // the device was lost, recover_device() has to be called before anything else is drawn
+    pub fn is_device_lost(&self) -> bool
+    {
+        self.device_lost.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    // finds a gpu again after the device was lost (see 'gpu.rs') and makes everything on it again,
+    // the world, the assets behind their handles (see AssetManager::recreate), fonts, the console and the settings
+    // carry over, Err -> there's no gpu to go on with
+    pub async fn recover_device(&mut self, window: &Window) -> Result<(), InitError>
+    {
+        log::warn!("the gpu device was lost, making everything again");
+        let gpu = gpu::request(window, &self.settings, OPTIONAL_FEATURES).await.map_err(InitError::NoGpu)?;
+        self.assets.recreate(&gpu.device, &gpu.queue).map_err(InitError::Assets)?;
+        let GpuResources
+        {
+            surface, device, queue, config, device_lost, line_mode_supported,
+            shader, render_pipeline_layout, render_pipelines, depth_texture, msaa_view,
+            instance_buffer, instance_capacity, debug_renderer, sprite_renderer, ui, profiler,
+            light_buffer, light_bind_group, camera_buffer, camera_bind_group,
+        } = Self::create_gpu_resources(window, gpu, &self.settings, &self.assets);
+
+        self.surface = surface;
+        self.device = device;
+        self.queue = queue;
+        self.size = window.inner_size();
+        self.config = config;
+        self.device_lost = device_lost;
+        self.surface_timeouts = 0;
+        // the render mode stays, drawing wireframes as lines depends on the new gpu
+        self.render_settings.line_mode = line_mode_supported;
+        self.shader = shader;
+        self.render_pipeline_layout = render_pipeline_layout;
+        self.render_pipelines = render_pipelines;
+        self.depth_texture = depth_texture;
+        self.msaa_view = msaa_view;
+        self.instance_buffer = instance_buffer;
+        self.instance_capacity = instance_capacity;
+        self.id_buffer = None;
+        self.debug_renderer = debug_renderer;
+        self.sprite_renderer = sprite_renderer;
+        self.ui = ui;
+        self.profiler = profiler;
+        self.capture.device_lost();
+        self.light_buffer = light_buffer;
+        self.light_bind_group = light_bind_group;
+        self.camera_buffer = camera_buffer;
+        self.camera_bind_group = camera_bind_group;
+        Ok(())
+    }
+
+    // window events go here before input(), true -> the ui used it and nothing else should (see 'ui.rs')
+    pub fn ui_input(&mut self, event: &WindowEvent) -> bool
+    {
+        // the console key works even while typing into the console, and never types itself
+        match event
+        {
+            WindowEvent::KeyboardInput { input: KeyboardInput { state: ElementState::Pressed, virtual_keycode: Some(VirtualKeyCode::Grave), .. }, .. } =>
+            {
+                self.console.toggle();
+                true
+            }
+            WindowEvent::ReceivedCharacter('`' | '~') => true,
+            _ => self.ui.input(event),
+        }
+    }
+
+    // gamepad events since the last poll, for the game states to hand to gamepad_event() one at a time
+    pub fn poll_gamepads(&mut self) -> Vec<gamepad::GamepadEvent>
+    {
+        self.simulation.poll_gamepads()
+    }
+
+    pub fn gamepad_event(&mut self, event: gamepad::GamepadEvent)
+    {
+        self.simulation.gamepad_event(event);
+    }
+
+    // one line of the developer console, as if it was typed in (see 'console.rs')
+    pub fn run_command(&mut self, line: &str) -> console::CommandResult
+    {
+        self.simulation.run_command(line)
+    }
+
     // returns a bool to indicate whether an event has been fully processed
     pub fn input(&mut self, event: &WindowEvent) -> bool
     {
-        self.camera_controller.process_events(event)
+        match event
+        {
+            WindowEvent::CursorMoved { position, .. } => self.cursor = Some(*position),
+            WindowEvent::CursorLeft { .. } => self.cursor = None,
+            _ => {}
+        }
+        let used = self.simulation.input(event);
+
+        // the debug keys for render modes work whatever game state is on top
+        let input = self.simulation.world().resource::<input::Input>();
+        if input.triggered(input::RENDER_MODE)
+        {
+            self.set_render_mode(self.render_settings.mode.next());
+        }
+        else if input.triggered(input::CULLING)
+        {
+            self.set_culling(!self.render_settings.culling);
+        }
+        else if input.triggered(input::PROFILER)
+        {
+            self.show_profiler = !self.show_profiler;
+        }
+        else if input.triggered(input::SCREENSHOT)
+        {
+            self.capture.screenshot(None);
+        }
+        else if input.triggered(input::CAPTURE_FRAMES)
+        {
+            self.capture.toggle_sequence();
+        }
+        used
 
         // when cursor moved --->
         // uses cursor position to set self.clear_color
@@ -376,59 +930,582 @@ impl State
         // }
     }
 
-    pub fn update(&mut self)
+    // one fixed simulation tick, main.rs decides how many of these to run per frame (see 'timestep.rs')
+    pub fn update(&mut self, tick: std::time::Duration)
+    {
+        self.profiler.begin_scope("simulation tick");
+        self.simulation.update(tick);
+        self.profiler.end_scope();
+    }
+
+    // gets the gpu side of the world ready to draw, alpha is how far we are between the last two ticks
+    fn prepare_world(&mut self, alpha: f32)
     {
+        self.drawn_alpha = alpha;
+        let render_list = self.simulation.world().resource::<systems::RenderList>();
+
         // update values in uniform buffer
-        self.camera_controller.update_camera(&mut self.camera);
-        self.camera_uniform.update_view_proj(&self.camera);
+        let camera = render_list.interpolated_camera(alpha);
+        self.camera_uniform.update_view_proj(&camera);
         self.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[self.camera_uniform]));
+        self.queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&[render_list.light]));
+
+        self.prepare_instances(alpha, &camera);
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer
+    {
+        device.create_buffer(&wgpu::BufferDescriptor
+        {
+            label: Some("Instance Buffer"),
+            size: (capacity * std::mem::size_of::<instance::InstanceRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    // whether a mesh drawn with this matrix could be anywhere on screen,
+    // meshes that haven't loaded yet don't have bounds, but they aren't drawn either
+    fn is_visible(&self, frustum: &bounds::Frustum, drawable: &systems::Drawable, matrix: &cgmath::Matrix4<f32>) -> bool
+    {
+        match self.assets.mesh_shape(drawable.attachment.mesh)
+        {
+            // the sphere is quicker to test, the box is closer to the mesh's shape
+            Some(shape) => frustum.intersects_sphere(&shape.sphere.transformed(matrix))
+                && frustum.intersects_aabb(&shape.aabb.transformed(matrix)),
+            None => true,
+        }
     }
 
-    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError>
+    // how far a blended mesh is from the camera, squared, to sort by (the middle of its bounds if it's loaded)
+    fn distance_to(&self, eye: cgmath::Point3<f32>, drawable: &systems::Drawable, matrix: &cgmath::Matrix4<f32>) -> f32
     {
-        // get a frame to render to
-        let output = self.surface.get_current_texture()?;
+        use cgmath::{ EuclideanSpace, MetricSpace };
+
+        let center = match self.assets.mesh_shape(drawable.attachment.mesh)
+        {
+            Some(shape) => shape.sphere.transformed(matrix).center,
+            None => cgmath::Point3::from_vec(matrix.w.truncate()),
+        };
+        eye.distance2(center)
+    }
+
+    // puts everything in the RenderList that's in view into the instance buffer, in drawing order:
+    // opaque, then cutout, then blended ones from the back to the front. opaque and cutout
+    // objects sharing a mesh and texture are drawn together, blended ones only when they're next to each other
+    fn prepare_instances(&mut self, alpha: f32, camera: &camera::Camera)
+    {
+        let frustum = bounds::Frustum::from_view_projection(&camera.build_view_projection_matrix());
+        let all = &self.simulation.world().resource::<systems::RenderList>().drawables;
+        let mut drawables: Vec<(systems::Drawable, cgmath::Matrix4<f32>, f32)> = all.iter()
+            .map(|drawable| (*drawable, drawable.interpolated(alpha)))
+            .filter(|(drawable, matrix)| !self.frustum_culling || self.is_visible(&frustum, drawable, matrix))
+            .map(|(drawable, matrix)| match drawable.attachment.alpha
+            {
+                scene::AlphaMode::Blend => (drawable, matrix, self.distance_to(camera.eye, &drawable, &matrix)),
+                _ => (drawable, matrix, 0.0),
+            })
+            .collect();
+        self.render_stats = RenderStats { drawn: drawables.len(), culled: all.len() - drawables.len(), draw_calls: 0 };
+        drawables.sort_by(|(a, _, a_distance), (b, _, b_distance)|
+        {
+            a.attachment.alpha.cmp(&b.attachment.alpha)
+                .then(b_distance.total_cmp(a_distance))
+                .then((a.attachment.mesh, a.attachment.texture).cmp(&(b.attachment.mesh, b.attachment.texture)))
+        });
+
+        self.drawn_nodes.clear();
+        self.drawn_nodes.extend(drawables.iter().map(|(drawable, _, _)| drawable.node));
+
+        self.draw_batches.clear();
+        for (i, (drawable, _, _)) in drawables.iter().enumerate()
+        {
+            match self.draw_batches.last_mut()
+            {
+                Some(batch) if batch.attachment == drawable.attachment => batch.instances.end += 1,
+                _ => self.draw_batches.push(DrawBatch { attachment: drawable.attachment, instances: i as u32..i as u32 + 1 }),
+            }
+        }
+        self.render_stats.draw_calls = self.draw_batches.len();
+
+        if drawables.len() > self.instance_capacity
+        {
+            self.instance_capacity = drawables.len().next_power_of_two();
+            self.instance_buffer = Self::create_instance_buffer(&self.device, self.instance_capacity);
+        }
+        let instances: Vec<instance::InstanceRaw> = drawables.iter()
+            .map(|(_, matrix, _)| instance::InstanceRaw::from_matrix(*matrix))
+            .collect();
+        self.queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instances));
+    }
+
+    // what the last frame drew
+    pub fn render_stats(&self) -> RenderStats
+    {
+        self.render_stats
+    }
+
+    pub fn frustum_culling(&self) -> bool
+    {
+        self.frustum_culling
+    }
 
-        // create TextureView with default settings
+    // on by default, turning it off draws everything to compare (see 'bounds.rs')
+    pub fn set_frustum_culling(&mut self, on: bool)
+    {
+        self.frustum_culling = on;
+    }
+
+    // picks with the gpu (see 'picking.rs') instead of testing every mesh's triangles,
+    // faster for scenes with lots of objects but it has to wait for the gpu
+    pub fn set_gpu_picking(&mut self, on: bool)
+    {
+        self.gpu_picking = on;
+    }
+
+    pub fn pick_at_cursor(&mut self) -> Option<picking::PickHit>
+    {
+        let cursor = self.cursor?;
+        self.pick(cursor.x as f32, cursor.y as f32)
+    }
+
+    // what's under a pixel (from the top left of the window), where it was drawn last frame
+    pub fn pick(&mut self, x: f32, y: f32) -> Option<picking::PickHit>
+    {
+        // the gpu only says which node it is, where the ray hits it comes from the cpu
+        let node = if self.gpu_picking { Some(self.pick_node(x, y)?) } else { None };
+
+        let world = self.simulation.world();
+        let render_list = world.get_resource::<systems::RenderList>()?;
+        let ray = picking::screen_ray(&render_list.interpolated_camera(self.drawn_alpha), x, y, self.size)?;
+        match node
+        {
+            Some(node) =>
+            {
+                let drawable = render_list.drawables.iter().find(|drawable| drawable.node == node)?;
+                picking::pick_drawable(world, drawable, self.assets.mesh_shape(drawable.attachment.mesh)?, &ray, self.drawn_alpha)
+            }
+            None => picking::pick(world, &ray, self.drawn_alpha, |mesh| self.assets.mesh_shape(mesh)),
+        }
+    }
+
+    // draws last frame's instances again into the id buffer and reads back the one under the pixel
+    fn pick_node(&mut self, x: f32, y: f32) -> Option<scene::NodeId>
+    {
+        if x < 0.0 || y < 0.0
+        {
+            return None;
+        }
+        // nothing can be submitted to a lost device, main.rs recovers before the next frame
+        if self.is_device_lost()
+        {
+            return None;
+        }
+        let (x, y) = (x as u32, y as u32);
+        let id_buffer = self.id_buffer.get_or_insert_with(|| picking::IdBuffer::new(&self.device, &self.config, &self.camera_buffer));
+        id_buffer.prepare(&self.device, &self.config, self.drawn_nodes.len());
+        if !id_buffer.contains(x, y)
+        {
+            return None;
+        }
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor
+        {
+            label: Some("Pick Encoder"),
+        });
+        {
+            let mut pick_pass = id_buffer.begin_pass(&mut encoder);
+            pick_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+            for batch in &self.draw_batches
+            {
+                if let Some(mesh) = self.assets.mesh(batch.attachment.mesh)
+                {
+                    pick_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                    pick_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                    pick_pass.draw_indexed(0..mesh.num_indices, 0, batch.instances.clone());
+                }
+            }
+        }
+        id_buffer.copy_pixel(&mut encoder, x, y);
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let index = id_buffer.read(&self.device)?;
+        self.drawn_nodes.get(index).copied()
+    }
+
+    pub fn load_progress(&self) -> assets::LoadProgress
+    {
+        self.assets.progress()
+    }
+
+    // gets a frame to draw to
+    pub fn begin_frame(&mut self) -> Result<Frame, wgpu::SurfaceError>
+    {
+        // averaged over the last ten frames or so, so the fps counter is readable
+        let now = std::time::Instant::now();
+        if let Some(last) = self.last_frame.replace(now)
+        {
+            let seconds = (now - last).as_secs_f32();
+            self.frame_seconds = if self.frame_seconds == 0.0 { seconds } else { self.frame_seconds * 0.9 + seconds * 0.1 };
+        }
+
+        // upload whatever the asset workers finished decoding, even when the world isn't drawn (like on the loading screen)
+        self.assets.poll(&self.device, &self.queue);
+
+        // waits for the display when vsync is holding things up
+        self.profiler.begin_scope("acquire surface");
+        let output = self.surface.get_current_texture();
+        self.profiler.end_scope();
+        let output = output?;
+        self.surface_timeouts = 0;
+        self.ui.begin_frame();
+
+        // create TextureView with default settings,
+        // a frame that's being captured is drawn somewhere it can be copied from and put on the surface after
         let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let (view, capture) = self.capture.begin_frame(&self.device, view, (self.config.width, self.config.height), self.config.format);
 
         // CommandEncoder to create the actual commands to send to the gpu
         // the encoder builds a command buffer that we can then send to the gpu
         // commands -> command buffer -> gpu
-        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor{
+        let encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor{
             label: Some("Render Encoder"),
         });
 
+        Ok(Frame { output, view, capture, encoder, cleared: false, world_drawn: false, sprite_batches: Vec::new(), clear_color: self.clear_color })
+    }
+
+    // to build this frame's ui with, any time between begin_frame() and end_frame()
+    pub fn ui(&self) -> egui::Context
+    {
+        self.ui.context().clone()
+    }
+
+    // lines to draw over the world this frame (or this tick, from a system), see 'debug_draw.rs'
+    pub fn debug_draw(&mut self) -> &mut debug_draw::DebugDraw
+    {
+        self.simulation.world_mut().resource_mut::<debug_draw::DebugDraw>()
+    }
+
+    // 2D sprites seen through camera, they go over the world and anything drawn before them
+    pub fn draw_sprites(&mut self, frame: &mut Frame, camera: sprite::OrthographicCamera, sprites: &[sprite::Sprite])
+    {
+        frame.sprite_batches.push(sprite::SpriteBatch::new(&camera, sprites.to_vec()));
+    }
+
+    // sprites on the xy plane of model (y up), seen through the 3D camera, they're still drawn over the world
+    pub fn draw_world_sprites(&mut self, frame: &mut Frame, model: cgmath::Matrix4<f32>, sprites: &[sprite::Sprite])
+    {
+        let view_proj = self.world_view_projection() * model;
+        frame.sprite_batches.push(sprite::SpriteBatch { view_proj, y_up: true, sprites: sprites.to_vec() });
+    }
+
+    // text on screen, in pixels from the top left corner of the window to the text's
+    pub fn draw_text(&mut self, frame: &mut Frame, text: &text::Text, position: cgmath::Vector2<f32>)
+    {
+        let sprites = self.text.sprites(text, position);
+        frame.sprite_batches.push(sprite::SpriteBatch::new(&self.screen_camera(), sprites));
+    }
+
+    // text on the xy plane of model, reading along x with its top at the origin,
+    // a pixel of text is a unit of model so it usually needs scaling down
+    pub fn draw_world_text(&mut self, frame: &mut Frame, text: &text::Text, model: cgmath::Matrix4<f32>)
+    {
+        let sprites = self.text.sprites(text, cgmath::Vector2::new(0.0, 0.0));
+        // text is laid out with y going down
+        let view_proj = self.world_view_projection() * model * cgmath::Matrix4::from_nonuniform_scale(1.0, -1.0, 1.0);
+        frame.sprite_batches.push(sprite::SpriteBatch { view_proj, y_up: false, sprites });
+    }
+
+    // how big text will be and where its glyphs go, without drawing it
+    pub fn layout_text(&self, text: &text::Text) -> text::TextLayout
+    {
+        self.text.layout(text)
+    }
+
+    // another font for text to use (path is relative to the asset root)
+    pub fn load_font(&mut self, path: impl AsRef<std::path::Path>) -> anyhow::Result<text::FontId>
+    {
+        self.text.load_font(&self.assets.root().join(path))
+    }
+
+    pub fn fps(&self) -> f32
+    {
+        if self.frame_seconds > 0.0 { 1.0 / self.frame_seconds } else { 0.0 }
+    }
+
+    // the 3D camera the world was last drawn with
+    fn world_view_projection(&self) -> cgmath::Matrix4<f32>
+    {
+        let world = self.simulation.world();
+        match world.get_resource::<systems::RenderList>()
+        {
+            Some(render_list) => render_list.interpolated_camera(self.drawn_alpha).build_view_projection_matrix(),
+            None => world.resource::<camera::Camera>().build_view_projection_matrix(),
+        }
+    }
+
+    // for the HUD, one unit is one pixel (see OrthographicCamera::screen)
+    pub fn screen_camera(&self) -> sprite::OrthographicCamera
+    {
+        sprite::OrthographicCamera::screen(self.config.width, self.config.height)
+    }
+
+    // starts loading a texture for sprites (path is relative to the asset root), see 'assets.rs'
+    pub fn load_texture(&mut self, path: impl AsRef<std::path::Path>) -> assets::TextureHandle
+    {
+        self.assets.load_texture(path)
+    }
+
+    // packs the builder's images into a texture as big as the gpu allows (see 'atlas.rs')
+    pub fn build_atlas(&mut self, builder: &atlas::AtlasBuilder, name: &str) -> anyhow::Result<atlas::TextureAtlas>
+    {
+        builder.pack(self.device.limits().max_texture_dimension_2d)?.upload(&self.device, &self.queue, &mut self.assets, name)
+    }
+
+    // draws the 3D world into the frame
+    pub fn draw_world(&mut self, frame: &mut Frame, alpha: f32)
+    {
+        // writing the world's buffers would panic, end_frame() drops the frame
+        if self.is_device_lost()
+        {
+            return;
+        }
+        self.profiler.begin_scope("draw world");
+        self.prepare_world(alpha);
+        self.prepare_render_mode();
+        self.debug_draw_bounds(alpha);
+        frame.world_drawn = true;
+        let load = frame.color_load_op();
+        self.profiler.begin_gpu_span(&mut frame.encoder, "world");
 
         // render pass
+        let mut render_pass = frame.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            // where to draw colors to
+            color_attachments: &[Self::world_color_attachment(&self.msaa_view, &frame.view, load)],     // the screen, or the msaa texture that's resolved to it
+            // where to draw depth to
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.light_bind_group, &[]);
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        let barycentric_wireframe = self.render_settings.barycentric_wireframe();
+        let mut pass = None;
+        for batch in &self.draw_batches
+        {
+            // the batches are in pass order (see prepare_instances), switch pipelines when the next pass starts
+            if pass != Some(batch.attachment.alpha)
+            {
+                pass = Some(batch.attachment.alpha);
+                render_pass.set_pipeline(&self.render_pipelines[&self.render_settings.with_alpha(batch.attachment.alpha)]);
+            }
+            if barycentric_wireframe
+            {
+                // no index buffer, every triangle has its own vertices
+                if let Some(wireframe) = self.assets.wireframe_mesh(batch.attachment.mesh)
+                {
+                    render_pass.set_bind_group(0, self.assets.texture_bind_group(batch.attachment.texture), &[]);
+                    render_pass.set_vertex_buffer(0, wireframe.vertex_buffer.slice(..));
+                    render_pass.draw(0..wireframe.num_vertices, batch.instances.clone());
+                }
+            }
+            // the mesh is still loading, nothing to draw yet
+            else if let Some(mesh) = self.assets.mesh(batch.attachment.mesh)
+            {
+                render_pass.set_bind_group(0, self.assets.texture_bind_group(batch.attachment.texture), &[]);
+                render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                render_pass.draw_indexed(0..mesh.num_indices, 0, batch.instances.clone());        // draw every instance in the batch with num_indices vertices
+            }
+        }
+        drop(render_pass);
+        self.profiler.end_gpu_span(&mut frame.encoder);
+        self.profiler.end_scope();
+    }
+
+    pub fn render_settings(&self) -> render_mode::RenderSettings
+    {
+        self.render_settings
+    }
+
+    pub fn set_render_mode(&mut self, mode: render_mode::RenderMode)
+    {
+        self.render_settings.mode = mode;
+        log::info!("render mode: {}", mode.name());
+    }
+
+    pub fn set_culling(&mut self, culling: bool)
+    {
+        self.render_settings.culling = culling;
+        log::info!("back face culling: {}", if culling { "on" } else { "off" });
+    }
+
+    // makes the pipelines for the current render settings (and wireframe meshes) if they don't exist yet,
+    // one for each pass there's something to draw in
+    fn prepare_render_mode(&mut self)
+    {
+        let settings = self.render_settings;
+        for batch in &self.draw_batches
         {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                // where to draw colors to
+            let pass = settings.with_alpha(batch.attachment.alpha);
+            if !self.render_pipelines.contains_key(&pass)
+            {
+                let pipeline = Self::create_render_pipeline(&self.device, &self.render_pipeline_layout, &self.shader, (self.config.format, self.settings.msaa), pass);
+                self.render_pipelines.insert(pass, pipeline);
+            }
+        }
+        if settings.barycentric_wireframe()
+        {
+            for batch in &self.draw_batches
+            {
+                self.assets.prepare_wireframe(&self.device, batch.attachment.mesh);
+            }
+        }
+    }
+
+    // the box around every mesh, where it's drawn
+    fn debug_draw_bounds(&mut self, alpha: f32)
+    {
+        let world = self.simulation.world_mut();
+        world.resource_scope(|world, debug: &mut debug_draw::DebugDraw|
+        {
+            if !debug.is_enabled()
+            {
+                return;
+            }
+            for drawable in &world.resource::<systems::RenderList>().drawables
+            {
+                if let Some(shape) = self.assets.mesh_shape(drawable.attachment.mesh)
+                {
+                    debug.oriented_box(&shape.aabb, &drawable.interpolated(alpha), debug_draw::GREEN);
+                }
+            }
+        });
+    }
+
+    pub fn end_frame(&mut self, mut frame: Frame)
+    {
+        // the device was lost while the frame was being drawn, nothing else can be written or submitted to it,
+        // the frame is dropped without being presented and main.rs recovers before the next one
+        if self.is_device_lost()
+        {
+            self.debug_draw().end_frame();
+            return;
+        }
+        if frame.world_drawn
+        {
+            self.profiler.begin_gpu_span(&mut frame.encoder, "debug lines");
+            self.debug_renderer.draw(
+                &self.device,
+                &self.queue,
+                &mut frame.encoder,
+                Self::world_color_attachment(&self.msaa_view, &frame.view, wgpu::LoadOp::Load),
+                &self.depth_texture.view,
+                self.simulation.world().resource::<debug_draw::DebugDraw>(),
+            );
+            self.profiler.end_gpu_span(&mut frame.encoder);
+        }
+        self.debug_draw().end_frame();
+
+        self.profiler.begin_scope("sprites");
+        self.text.upload(&self.queue, &mut self.assets);
+        if !frame.sprite_batches.is_empty()
+        {
+            let load = frame.color_load_op();
+            self.sprite_renderer.prepare(&self.device, &self.queue, &frame.sprite_batches);
+            self.profiler.begin_gpu_span(&mut frame.encoder, "sprites");
+            self.sprite_renderer.draw(&mut frame.encoder, &frame.view, load, &self.assets);
+            self.profiler.end_gpu_span(&mut frame.encoder);
+        }
+        self.profiler.end_scope();
+
+        // the ui goes over everything, sprites and text included
+        self.profiler.begin_scope("ui");
+        if self.show_profiler
+        {
+            self.profiler.show(self.ui.context());
+        }
+        self.console.show(self.ui.context(), &mut self.simulation);
+        if self.ui.end_frame(&self.device, &self.queue)
+        {
+            let load = frame.color_load_op();
+            self.profiler.begin_gpu_span(&mut frame.encoder, "ui");
+            self.ui.draw(&mut frame.encoder, &frame.view, load);
+            self.profiler.end_gpu_span(&mut frame.encoder);
+        }
+        self.profiler.end_scope();
+
+        // nothing drew anything, the screen still has to be cleared
+        if !frame.cleared
+        {
+            let load = frame.color_load_op();
+            frame.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Clear Pass"),
                 color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &view,                // what texture to save the colors to (in this case the screen)
+                    view: &frame.view,
                     resolve_target: None,
-                    ops: wgpu::Operations {     // tells wgpu what to do with the colors on the screen
-                        load: wgpu::LoadOp::Clear(self.clear_color),
-                        store: true,
-                    },
+                    ops: wgpu::Operations { load, store: true },
                 }],
                 depth_stencil_attachment: None,
             });
-
-            render_pass.set_pipeline(&self.render_pipeline);    // set the pipeline on the render_pass using the one we made in 'new()'
-            render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
-            render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..1);        // draw something with num_indices vertices, and 1 instance
         }
-    
+
         // finish the command buffer, and to submit it to the gpu's render queue.
         // submit will accept anything that implements IntoIter
-        self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
-    
-        Ok(())
+        if let Some(capture) = frame.capture.take()
+        {
+            self.capture.end_frame(&self.device, &mut frame.encoder, capture);
+        }
+        // lost while drawing the sprites or the ui
+        if self.is_device_lost()
+        {
+            return;
+        }
+        self.profiler.resolve(&mut frame.encoder);
+        self.profiler.begin_scope("submit");
+        self.queue.submit(std::iter::once(frame.encoder.finish()));
+        frame.output.present();
+        self.profiler.end_scope();
+        self.capture.submitted();
+        self.capture.poll(&self.device, false);
+        self.profiler.end_frame(&self.device);
+    }
+
+    // saves the next frame, to path or to a new file in capture::SCREENSHOT_DIR
+    pub fn screenshot(&mut self, path: Option<std::path::PathBuf>)
+    {
+        self.capture.screenshot(path);
+    }
+
+    // saves one frame in every `every` to numbered images in directory, for trailers and bug reports
+    pub fn start_frame_capture(&mut self, directory: impl Into<std::path::PathBuf>, every: u32)
+    {
+        self.capture.start_sequence(directory.into(), every);
+    }
+
+    pub fn stop_frame_capture(&mut self)
+    {
+        self.capture.stop_sequence();
+    }
+
+    // waits for the captures still on their way to be saved, before the game exits
+    pub fn finish_captures(&mut self)
+    {
+        self.capture.finish(&self.device);
+    }
+
+    // where the time in the last few seconds of frames went (see 'profiler.rs')
+    pub fn profiler(&self) -> &profiler::Profiler
+    {
+        &self.profiler
     }
 }
@@ -0,0 +1,433 @@
+use std::collections::HashMap;
+use cgmath::{ Rotation3, SquareMatrix };
+use winit::event::WindowEvent;
+
+use super::
+{
+    ecs::{ Entity, Schedule, Stage, World },
+    scene,
+    light,
+    camera,
+    camera_controller,
+    console::{ self, CommandResult, Console },
+    debug_draw::{ self, DebugDraw },
+    input,
+    paths,
+};
+
+
+/*
+    The game's components, resources and systems.
+
+    Resources every system can count on being in the world:
+        scene::SceneGraph, camera::Camera, camera_controller::CameraController, input::Input,
+        debug_draw::DebugDraw, Time, TaskStatus, RenderList
+
+    Every update is one fixed simulation tick (see 'timestep.rs'), so systems
+    move things by Time::delta_seconds rather than by "one frame".
+
+    The console commands and cvars for all of this are at the bottom
+    (see 'console.rs').
+*/
+
+/*   <--------Components-------->   */
+// links an entity to its node in the scene graph,
+// the entity's scene::Transform component is copied into the node every update
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SceneNode(pub scene::NodeId);
+
+// keeps rotating the entity around an axis
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Spin
+{
+    pub axis: cgmath::Vector3<f32>,
+    pub degrees_per_second: f32,
+}
+
+
+/*   <--------Resources-------->   */
+// the simulation clock, State::update sets it before every tick
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Time
+{
+    // ticks run so far
+    pub tick: u64,
+    // simulated seconds per tick
+    pub delta_seconds: f32,
+}
+
+// whether the level's task is done, the game shows the task complete screen once it is
+// (levels don't have tasks of their own yet, the task.complete cvar finishes one by hand)
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TaskStatus
+{
+    pub complete: bool,
+}
+
+// one thing to draw, with where it was on the tick before so the renderer
+// can blend between the two (see 'timestep.rs')
+#[derive(Copy, Clone, Debug)]
+pub struct Drawable
+{
+    pub node: scene::NodeId,
+    pub attachment: scene::MeshAttachment,
+    pub previous: cgmath::Matrix4<f32>,
+    pub world: cgmath::Matrix4<f32>,
+}
+
+impl Drawable
+{
+    // alpha = 0 -> the previous tick, alpha = 1 -> this one
+    pub fn interpolated(&self, alpha: f32) -> cgmath::Matrix4<f32>
+    {
+        if alpha >= 1.0 || self.previous == self.world
+        {
+            return self.world;
+        }
+        scene::Transform::from_matrix(self.previous)
+            .lerp(scene::Transform::from_matrix(self.world), alpha)
+            .to_matrix()
+    }
+}
+
+// everything State::render needs from the world, filled in by the render extraction stage
+// so the renderer never has to look at entities or the scene graph itself
+pub struct RenderList
+{
+    pub previous_camera: camera::Camera,
+    pub camera: camera::Camera,
+    pub light: light::LightUniform,
+    pub drawables: Vec<Drawable>,
+}
+
+impl RenderList
+{
+    pub fn interpolated_camera(&self, alpha: f32) -> camera::Camera
+    {
+        self.previous_camera.lerp(&self.camera, alpha)
+    }
+}
+
+
+// makes an entity for a node that's already in the scene graph,
+// starting from the node's current local transform
+pub fn spawn_for_node(world: &mut World, node: scene::NodeId) -> Entity
+{
+    let local = world.resource::<scene::SceneGraph>().get(node).map(|node| *node.local()).unwrap_or_default();
+    let entity = world.spawn();
+    world.insert(entity, SceneNode(node));
+    world.insert(entity, local);
+    entity
+}
+
+// every system the game runs, in order
+pub fn add_systems(schedule: &mut Schedule)
+{
+    schedule
+        .add_event_handler("input", track_input)
+        .add_system(Stage::Input, "move camera", move_camera)
+        .add_system(Stage::Physics, "spin", spin)
+        .add_system(Stage::TransformPropagation, "propagate transforms", propagate_transforms)
+        .add_system(Stage::RenderExtraction, "extract render list", extract_render_list)
+        .add_system(Stage::RenderExtraction, "debug draw scene", debug_draw_scene);
+}
+
+
+/*   <--------Systems-------->   */
+fn track_input(world: &mut World, event: &WindowEvent) -> bool
+{
+    let used = world.resource_mut::<input::Input>().handle_event(event);
+    // here rather than in a game state so it works everywhere, even paused
+    if world.resource::<input::Input>().triggered(input::DEBUG_DRAW)
+    {
+        world.resource_mut::<DebugDraw>().toggle();
+    }
+    used
+}
+
+fn move_camera(world: &mut World)
+{
+    world.resource_scope(|world, camera: &mut camera::Camera|
+    {
+        world.resource::<camera_controller::CameraController>().update_camera(camera, world.resource::<input::Input>());
+    });
+}
+
+fn spin(world: &mut World)
+{
+    let delta_seconds = world.resource::<Time>().delta_seconds;
+    world.for_each2_mut(|_, transform: &mut scene::Transform, spin: &mut Spin|
+    {
+        let degrees = spin.degrees_per_second * delta_seconds;
+        transform.rotation = cgmath::Quaternion::from_axis_angle(spin.axis, cgmath::Deg(degrees)) * transform.rotation;
+    });
+}
+
+// copies changed transforms into the scene graph, then updates the world matrices
+// of (only) the nodes that changed and their children
+fn propagate_transforms(world: &mut World)
+{
+    world.resource_scope(|world, scene: &mut scene::SceneGraph|
+    {
+        for (_, transform, SceneNode(node)) in world.query2::<scene::Transform, SceneNode>()
+        {
+            if scene.get(*node).is_some_and(|node| node.local() != transform)
+            {
+                scene.set_local(*node, *transform);
+            }
+        }
+        scene.update_world_matrices();
+    });
+}
+
+fn extract_render_list(world: &mut World)
+{
+    let scene = world.resource::<scene::SceneGraph>();
+    let free_camera = *world.resource::<camera::Camera>();
+
+    // a camera node in the scene takes over from the free camera
+    let camera = scene.active_camera
+        .and_then(|id| scene.camera_from_node(id, free_camera.aspect))
+        .unwrap_or(free_camera);
+
+    let mut light = light::LightUniform::new();
+    light.update_from_scene(scene);
+
+    // last tick's list becomes the "previous" half of this one
+    let last = world.get_resource::<RenderList>();
+    let previous_camera = last.map_or(camera, |last| last.camera);
+    let previous: HashMap<scene::NodeId, cgmath::Matrix4<f32>> = last
+        .map(|last| last.drawables.iter().map(|drawable| (drawable.node, drawable.world)).collect())
+        .unwrap_or_default();
+
+    let drawables = scene.ids()
+        .filter_map(|id| scene.get(id).map(|node| (id, node)))
+        .filter_map(|(id, node)| node.mesh.map(|attachment| Drawable
+        {
+            node: id,
+            attachment,
+            // new this tick, nothing to blend from
+            previous: previous.get(&id).copied().unwrap_or_else(|| node.world_matrix()),
+            world: node.world_matrix(),
+        }))
+        .collect();
+
+    world.insert_resource(RenderList { previous_camera, camera, light, drawables });
+}
+
+// the ground, the origin, the lights and any cameras in the scene, while debug drawing is on
+fn debug_draw_scene(world: &mut World)
+{
+    world.resource_scope(|world, debug: &mut DebugDraw|
+    {
+        if !debug.is_enabled()
+        {
+            return;
+        }
+        debug.grid(cgmath::Point3::new(0.0, 0.0, 0.0), 5.0, 0.5, debug_draw::GREY);
+        debug.axes(&cgmath::Matrix4::identity(), 0.5);
+
+        let scene = world.resource::<scene::SceneGraph>();
+        let aspect = world.resource::<camera::Camera>().aspect;
+        for (id, node) in scene.ids().filter_map(|id| scene.get(id).map(|node| (id, node)))
+        {
+            if node.light.is_some()
+            {
+                debug.sphere(node.world_position(), 0.1, debug_draw::YELLOW);
+            }
+            // the camera that's being looked through can't be seen anyway
+            if node.camera.is_some() && scene.active_camera != Some(id)
+            {
+                if let Some(camera) = scene.camera_from_node(id, aspect)
+                {
+                    debug.frustum(&camera.build_view_projection_matrix(), debug_draw::CYAN);
+                }
+            }
+        }
+    });
+}
+
+
+/*   <--------Console Commands-------->   */
+// every command and cvar the world has
+pub fn add_commands(console: &mut Console)
+{
+    console
+        .add_cvar("camera.fovy", "the free camera's field of view, in degrees",
+            |world| world.get_resource_mut::<camera::Camera>().map(|camera| &mut camera.fovy))
+        .add_cvar("camera.speed", "how fast the camera zooms and orbits",
+            |world| world.get_resource_mut::<camera_controller::CameraController>().map(|controller| &mut controller.speed))
+        .add_cvar("task.complete", "whether the level's task is done, setting it shows the task complete screen",
+            |world| world.get_resource_mut::<TaskStatus>().map(|task| &mut task.complete))
+        .add_command("bind", "bind <binding> <action>: adds a binding to an action, like bind Key(P) pause", bind)
+        .add_command("unbind", "unbind <binding> <action>: takes a binding off an action", unbind)
+        .add_command("bindings", "bindings [action]: what every action, or one of them, is bound to", list_bindings)
+        .add_command("save_bindings", "save_bindings [file]: saves the bindings to the player's config folder, or to file", save_bindings)
+        .add_command("nodes", "lists the nodes in the scene", list_nodes)
+        .add_command("spawn", "spawn <node> [x y z]: a copy of a node, at its place or at x y z", spawn_copy)
+        .add_command("teleport", "teleport [node] <x y z>: moves the camera (looking the same way), or a node within its parent", teleport);
+}
+
+// the binding first, since action names can have spaces in them ("quit to menu")
+fn binding_and_action(args: &[&str], usage: &str) -> Result<(input::Binding, String), String>
+{
+    match args
+    {
+        [binding, action @ ..] if !action.is_empty() => Ok((binding.parse()?, action.join(" "))),
+        _ => Err(usage.to_string()),
+    }
+}
+
+fn bind(world: &mut World, args: &[&str]) -> CommandResult
+{
+    let (binding, action) = binding_and_action(args, "usage: bind <binding> <action>")?;
+    world.resource_mut::<input::Input>().bindings_mut().bind(&action, binding);
+    Ok(format!("{:?} -> {}", binding, action))
+}
+
+fn unbind(world: &mut World, args: &[&str]) -> CommandResult
+{
+    let (binding, action) = binding_and_action(args, "usage: unbind <binding> <action>")?;
+    let bindings = world.resource_mut::<input::Input>().bindings_mut();
+    if !bindings.actions.get(&action).is_some_and(|bound| bound.contains(&binding))
+    {
+        return Err(format!("{} isn't bound to {:?}", action, binding));
+    }
+    bindings.unbind(&action, binding);
+    Ok(format!("{:?} no longer does {}", binding, action))
+}
+
+fn list_bindings(world: &mut World, args: &[&str]) -> CommandResult
+{
+    let actions = &world.resource::<input::Input>().bindings().actions;
+    let line = |(action, bound): (&String, &Vec<input::Binding>)| format!("{}: {:?}", action, bound);
+    if args.is_empty()
+    {
+        return Ok(actions.iter().map(line).collect::<Vec<_>>().join("\n"));
+    }
+    let action = args.join(" ");
+    actions.get_key_value(&action).map(line).ok_or_else(|| format!("no action called {}", action))
+}
+
+fn save_bindings(world: &mut World, args: &[&str]) -> CommandResult
+{
+    let path = match args
+    {
+        [] => paths::user_config(input::BINDINGS_FILE).map_err(|e| e.to_string())?,
+        [file] => std::path::PathBuf::from(file),
+        _ => return Err(String::from("usage: save_bindings [file]")),
+    };
+    world.resource::<input::Input>().bindings().save(&path).map_err(|e| e.to_string())?;
+    Ok(format!("saved the bindings to {}", path.display()))
+}
+
+fn list_nodes(world: &mut World, _args: &[&str]) -> CommandResult
+{
+    let scene = world.get_resource::<scene::SceneGraph>().ok_or("no level loaded")?;
+    let names: Vec<&str> = scene.ids().filter_map(|id| scene.get(id)).map(|node| node.name.as_str()).collect();
+    Ok(names.join(", "))
+}
+
+// the entity moving a node around, if it has one
+fn entity_for_node(world: &World, node: scene::NodeId) -> Option<Entity>
+{
+    world.query::<SceneNode>().find(|(_, SceneNode(id))| *id == node).map(|(entity, _)| entity)
+}
+
+fn spawn_copy(world: &mut World, args: &[&str]) -> CommandResult
+{
+    let (name, position) = match args
+    {
+        [name] => (*name, None),
+        [name, position @ ..] => (*name, Some(console::parse_floats::<3>(position)?)),
+        [] => return Err(String::from("usage: spawn <node> [x y z]")),
+    };
+
+    let scene = world.get_resource_mut::<scene::SceneGraph>().ok_or("no level loaded")?;
+    let source = scene.find(name).ok_or_else(|| format!("no node called {}", name))?;
+    let (mesh, light, camera, world_matrix) = scene.get(source)
+        .map(|node| (node.mesh, node.light, node.camera, node.world_matrix()))
+        .ok_or_else(|| format!("no node called {}", name))?;
+
+    // the copy goes at the root, where the original is in the world unless it's been told otherwise
+    let mut local = scene::Transform::from_matrix(world_matrix);
+    if let Some([x, y, z]) = position
+    {
+        local.translation = cgmath::Vector3::new(x, y, z);
+    }
+    let id = scene.add_node(name, local, None);
+    if let Some(node) = scene.get_mut(id)
+    {
+        node.mesh = mesh;
+        node.light = light;
+        node.camera = camera;
+    }
+
+    let entity = spawn_for_node(world, id);
+    // and it spins like the original does
+    if let Some(spin) = entity_for_node(world, source).and_then(|original| world.get::<Spin>(original).copied())
+    {
+        world.insert(entity, spin);
+    }
+    let at = local.translation;
+    Ok(format!("spawned {} at ({:.2}, {:.2}, {:.2})", name, at.x, at.y, at.z))
+}
+
+fn teleport(world: &mut World, args: &[&str]) -> CommandResult
+{
+    match args
+    {
+        [x, y, z] =>
+        {
+            let [x, y, z] = console::parse_floats::<3>(&[x, y, z])?;
+            let camera = world.get_resource_mut::<camera::Camera>().ok_or("no camera")?;
+            let offset = camera.target - camera.eye;
+            camera.eye = cgmath::Point3::new(x, y, z);
+            camera.target = camera.eye + offset;
+            Ok(format!("camera at ({:.2}, {:.2}, {:.2})", x, y, z))
+        }
+        [name, position @ ..] if position.len() == 3 =>
+        {
+            let [x, y, z] = console::parse_floats::<3>(position)?;
+            let translation = cgmath::Vector3::new(x, y, z);
+            let node = world.get_resource::<scene::SceneGraph>()
+                .and_then(|scene| scene.find(name))
+                .ok_or_else(|| format!("no node called {}", name))?;
+            // the entity's transform is copied into the node every tick, so that's the one to move
+            match entity_for_node(world, node).and_then(|entity| world.get_mut::<scene::Transform>(entity))
+            {
+                Some(transform) => transform.translation = translation,
+                None => world.resource_mut::<scene::SceneGraph>().update_local(node, |local| local.translation = translation),
+            }
+            Ok(format!("{} at ({:.2}, {:.2}, {:.2})", name, x, y, z))
+        }
+        _ => Err(String::from("usage: teleport [node] <x y z>")),
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::utils::simulation::Simulation;
+
+    #[test]
+    fn remapped_bindings_save_and_load_back()
+    {
+        let mut simulation = Simulation::new(input::Bindings::default());
+        simulation.run_command("bind Key(P) quit to menu").unwrap();
+        simulation.run_command("unbind Key(Q) quit to menu").unwrap();
+        assert!(simulation.run_command("unbind Key(Q) quit to menu").is_err());
+        assert!(simulation.run_command("bind P pause").is_err());
+
+        let path = std::env::temp_dir().join(format!("with-me-bindings-{}.ron", std::process::id()));
+        simulation.run_command(&format!("save_bindings {}", path.display())).unwrap();
+        let loaded = input::Bindings::load(&path);
+        let _ = std::fs::remove_file(&path);
+
+        let bindings = simulation.world().resource::<input::Input>().bindings();
+        assert_eq!(loaded.unwrap(), *bindings);
+        assert_eq!(bindings.actions[input::QUIT_TO_MENU], vec![input::Binding::Gamepad(crate::utils::gamepad::GamepadButton::Select), input::Binding::Key(winit::event::VirtualKeyCode::P)]);
+    }
+}
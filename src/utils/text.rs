@@ -0,0 +1,472 @@
+use std::
+{
+    collections::HashMap,
+    path::Path,
+};
+use ab_glyph::{ Font, ScaleFont };
+use anyhow::*;
+
+use super::
+{
+    assets,
+    atlas::PixelRect,
+    sprite,
+};
+
+
+/*
+    Text from TrueType/OpenType fonts, for messages, task descriptions,
+    the fps counter...
+
+        let text = Text::new("Find the key", 24.0).wrap(300.0).align(Align::Center);
+        state.draw_text(&mut frame, &text, cgmath::vec2(20.0, 20.0));
+
+    Text is laid out in pixels, top left first and y going down, from spans
+    that each have their own color. Lines break at '\n', and between words
+    (or inside a word too long for a line of its own) when a wrap width is
+    set. Each line is lined up on its own.
+
+    Glyphs are rasterized the first time they're used, at the size they're
+    used at, into one glyph cache texture. The text itself is drawn as
+    sprites out of that texture (see 'sprite.rs'), on screen or on a plane
+    in the world. When the cache fills up it's emptied at the end of the
+    frame, glyphs that didn't fit show up again the frame after.
+*/
+
+// pixels across and down
+const CACHE_SIZE: u32 = 1024;
+// empty pixels between glyphs so filtering doesn't pick up the neighbours
+const CACHE_PADDING: u32 = 1;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FontId(usize);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Align
+{
+    Left,
+    Center,
+    Right,
+}
+
+// part of a text in one color
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextSpan
+{
+    pub text: String,
+    pub color: [f32; 4],
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Text
+{
+    pub spans: Vec<TextSpan>,
+    pub font: FontId,
+    // pixels from the top of the tallest letter to the bottom of the lowest one, about
+    pub size: f32,
+    // lines longer than this are broken between words
+    pub wrap_width: Option<f32>,
+    pub align: Align,
+    // 1 -> the font's own line spacing
+    pub line_spacing: f32,
+}
+
+impl Text
+{
+    // white, in the first font that was loaded
+    pub fn new(text: impl Into<String>, size: f32) -> Self
+    {
+        Self
+        {
+            spans: vec![TextSpan { text: text.into(), color: [1.0, 1.0, 1.0, 1.0] }],
+            font: FontId::default(),
+            size,
+            wrap_width: None,
+            align: Align::Left,
+            line_spacing: 1.0,
+        }
+    }
+
+    // every span so far
+    pub fn color(mut self, color: [f32; 4]) -> Self
+    {
+        for span in &mut self.spans
+        {
+            span.color = color;
+        }
+        self
+    }
+
+    // more text after what's there, in another color
+    pub fn span(mut self, text: impl Into<String>, color: [f32; 4]) -> Self
+    {
+        self.spans.push(TextSpan { text: text.into(), color });
+        self
+    }
+
+    pub fn font(mut self, font: FontId) -> Self
+    {
+        self.font = font;
+        self
+    }
+
+    pub fn wrap(mut self, width: f32) -> Self
+    {
+        self.wrap_width = Some(width);
+        self
+    }
+
+    pub fn align(mut self, align: Align) -> Self
+    {
+        self.align = align;
+        self
+    }
+}
+
+
+/*   <--------Layout-------->   */
+// one glyph of laid out text, x is where the pen is and y the baseline
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LaidOutGlyph
+{
+    pub glyph: ab_glyph::GlyphId,
+    pub x: f32,
+    pub y: f32,
+    pub color: [f32; 4],
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TextLayout
+{
+    pub glyphs: Vec<LaidOutGlyph>,
+    // the space the text takes up, in pixels
+    pub width: f32,
+    pub height: f32,
+}
+
+// a glyph on its way into a line
+#[derive(Copy, Clone)]
+struct Placed
+{
+    glyph: ab_glyph::GlyphId,
+    x: f32,
+    advance: f32,
+    color: [f32; 4],
+    whitespace: bool,
+}
+
+// lines of glyphs, wrapping as they're pushed
+struct LineBreaker<'a>
+{
+    font: ab_glyph::PxScaleFont<&'a ab_glyph::FontArc>,
+    wrap_width: Option<f32>,
+    lines: Vec<Vec<Placed>>,
+    line: Vec<Placed>,
+}
+
+impl LineBreaker<'_>
+{
+    fn end(line: &[Placed]) -> f32
+    {
+        line.last().map_or(0.0, |last| last.x + last.advance)
+    }
+
+    fn new_line(&mut self)
+    {
+        self.lines.push(std::mem::take(&mut self.line));
+    }
+
+    fn push(&mut self, c: char, color: [f32; 4])
+    {
+        if c == '\n'
+        {
+            self.new_line();
+            return;
+        }
+        let glyph = self.font.glyph_id(c);
+        let whitespace = c.is_whitespace();
+        let kern = self.line.last().map_or(0.0, |last| self.font.kern(last.glyph, glyph));
+        let x = Self::end(&self.line) + kern;
+        let advance = self.font.h_advance(glyph);
+
+        // spaces never wrap, they hang off the end of the line until a letter doesn't fit
+        let too_long = self.wrap_width.is_some_and(|wrap_width| x + advance > wrap_width);
+        if too_long && !whitespace && !self.line.is_empty()
+        {
+            match self.line.iter().rposition(|placed| placed.whitespace)
+            {
+                // the word this letter is in moves down to the next line
+                Some(space) =>
+                {
+                    let word = self.line.split_off(space + 1);
+                    while self.line.last().is_some_and(|placed| placed.whitespace)
+                    {
+                        self.line.pop();
+                    }
+                    self.new_line();
+                    let start = word.first().map_or(0.0, |first| first.x);
+                    self.line.extend(word.into_iter().map(|placed| Placed { x: placed.x - start, ..placed }));
+                }
+                // the word is longer than a line, break it here
+                None => self.new_line(),
+            }
+            let x = Self::end(&self.line);
+            self.line.push(Placed { glyph, x, advance, color, whitespace });
+            return;
+        }
+        self.line.push(Placed { glyph, x, advance, color, whitespace });
+    }
+
+    fn finish(mut self) -> Vec<Vec<Placed>>
+    {
+        self.new_line();
+        self.lines
+    }
+}
+
+// lays out text with font, see TextRenderer::layout
+fn layout_with(font: &ab_glyph::FontArc, text: &Text) -> TextLayout
+{
+    let scaled = font.as_scaled(text.size);
+    let mut breaker = LineBreaker { font: scaled, wrap_width: text.wrap_width, lines: Vec::new(), line: Vec::new() };
+    for span in &text.spans
+    {
+        for c in span.text.chars()
+        {
+            breaker.push(c, span.color);
+        }
+    }
+    let lines = breaker.finish();
+
+    // trailing spaces don't count towards how wide a line is
+    let line_width = |line: &Vec<Placed>| line.iter().rev()
+        .find(|placed| !placed.whitespace)
+        .map_or(0.0, |placed| placed.x + placed.advance);
+    let widest = lines.iter().map(line_width).fold(0.0, f32::max);
+    let box_width = text.wrap_width.unwrap_or(widest);
+
+    let line_height = (scaled.ascent() - scaled.descent() + scaled.line_gap()) * text.line_spacing;
+    let mut glyphs = Vec::new();
+    for (i, line) in lines.iter().enumerate()
+    {
+        let offset = match text.align
+        {
+            Align::Left => 0.0,
+            Align::Center => (box_width - line_width(line)) / 2.0,
+            Align::Right => box_width - line_width(line),
+        };
+        let baseline = scaled.ascent() + i as f32 * line_height;
+        glyphs.extend(line.iter()
+            .filter(|placed| !placed.whitespace)
+            .map(|placed| LaidOutGlyph { glyph: placed.glyph, x: offset + placed.x, y: baseline, color: placed.color }));
+    }
+
+    let height = match lines.len()
+    {
+        0 => 0.0,
+        count => (count - 1) as f32 * line_height + scaled.ascent() - scaled.descent(),
+    };
+    TextLayout { glyphs, width: widest, height }
+}
+
+
+/*   <--------Glyph Cache-------->   */
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct GlyphKey
+{
+    font: FontId,
+    glyph: ab_glyph::GlyphId,
+    // in quarter pixels, so sizes that are nearly the same share glyphs
+    size: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct CachedGlyph
+{
+    rect: PixelRect,
+    // from the pen position on the baseline to the top left of rect
+    offset: cgmath::Vector2<f32>,
+}
+
+struct GlyphCache
+{
+    texture: assets::TextureHandle,
+    // None for glyphs with nothing to draw, like spaces
+    glyphs: HashMap<GlyphKey, Option<CachedGlyph>>,
+    // where the next glyph goes, glyphs are packed on shelves like in 'atlas.rs'
+    x: u32,
+    shelf_y: u32,
+    shelf_height: u32,
+    // rasterized since the last upload
+    pending: Vec<(PixelRect, image::RgbaImage)>,
+    // something didn't fit, start over after this frame
+    full: bool,
+}
+
+impl GlyphCache
+{
+    // somewhere for a glyph of this size, None if the cache is full
+    fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32)>
+    {
+        let (padded_width, padded_height) = (width + CACHE_PADDING, height + CACHE_PADDING);
+        if self.x + padded_width > CACHE_SIZE
+        {
+            self.x = 0;
+            self.shelf_y += self.shelf_height;
+            self.shelf_height = 0;
+        }
+        if padded_width > CACHE_SIZE || self.shelf_y + padded_height > CACHE_SIZE
+        {
+            self.full = true;
+            return None;
+        }
+        let place = (self.x, self.shelf_y);
+        self.x += padded_width;
+        self.shelf_height = self.shelf_height.max(padded_height);
+        Some(place)
+    }
+
+    fn get(&mut self, font: &ab_glyph::FontArc, key: GlyphKey) -> Option<CachedGlyph>
+    {
+        if let Some(cached) = self.glyphs.get(&key)
+        {
+            return *cached;
+        }
+        let glyph = key.glyph.with_scale_and_position(key.size as f32 / 4.0, ab_glyph::point(0.0, 0.0));
+        let outlined = match font.outline_glyph(glyph)
+        {
+            Some(outlined) => outlined,
+            None =>
+            {
+                self.glyphs.insert(key, None);
+                return None;
+            }
+        };
+        let bounds = outlined.px_bounds();
+        let (width, height) = (bounds.width() as u32, bounds.height() as u32);
+        // not cached, so it's tried again once the cache has been emptied
+        let (x, y) = self.allocate(width, height)?;
+
+        // white, the coverage goes in alpha so the sprite's tint colors it
+        let mut image = image::RgbaImage::from_pixel(width, height, image::Rgba([255, 255, 255, 0]));
+        outlined.draw(|px, py, coverage|
+        {
+            if px < width && py < height
+            {
+                image.put_pixel(px, py, image::Rgba([255, 255, 255, (coverage.clamp(0.0, 1.0) * 255.0) as u8]));
+            }
+        });
+        let rect = PixelRect { x, y, width, height };
+        self.pending.push((rect, image));
+
+        let cached = CachedGlyph { rect, offset: cgmath::Vector2::new(bounds.min.x, bounds.min.y) };
+        self.glyphs.insert(key, Some(cached));
+        Some(cached)
+    }
+
+    fn clear(&mut self)
+    {
+        self.glyphs.clear();
+        self.pending.clear();
+        self.x = 0;
+        self.shelf_y = 0;
+        self.shelf_height = 0;
+        self.full = false;
+    }
+}
+
+
+/*   <--------Text Renderer-------->   */
+pub struct TextRenderer
+{
+    fonts: Vec<ab_glyph::FontArc>,
+    cache: GlyphCache,
+}
+
+impl TextRenderer
+{
+    // the glyph cache texture goes in the asset manager, the same as any other sprite texture
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, assets: &mut assets::AssetManager) -> Result<Self>
+    {
+        let empty = image::RgbaImage::from_pixel(CACHE_SIZE, CACHE_SIZE, image::Rgba([255, 255, 255, 0]));
+        let texture = assets.add_texture(device, queue, "glyph cache", &empty)?;
+        Ok(Self
+        {
+            fonts: Vec::new(),
+            cache: GlyphCache
+            {
+                texture,
+                glyphs: HashMap::new(),
+                x: 0,
+                shelf_y: 0,
+                shelf_height: 0,
+                pending: Vec::new(),
+                full: false,
+            },
+        })
+    }
+
+    // a .ttf or .otf file, the first one loaded is the font Text uses unless it's told otherwise
+    pub fn load_font(&mut self, path: &Path) -> Result<FontId>
+    {
+        let bytes = std::fs::read(path).with_context(|| format!("couldn't read font {}", path.display()))?;
+        let font = ab_glyph::FontArc::try_from_vec(bytes).with_context(|| format!("{} isn't a font", path.display()))?;
+        self.fonts.push(font);
+        Ok(FontId(self.fonts.len() - 1))
+    }
+
+    // where every glyph goes, without drawing anything, empty if the font isn't loaded
+    pub fn layout(&self, text: &Text) -> TextLayout
+    {
+        match self.fonts.get(text.font.0)
+        {
+            Some(font) => layout_with(font, text),
+            None => TextLayout::default(),
+        }
+    }
+
+    // the text as sprites out of the glyph cache, its top left corner at position
+    pub fn sprites(&mut self, text: &Text, position: cgmath::Vector2<f32>) -> Vec<sprite::Sprite>
+    {
+        let layout = self.layout(text);
+        let font = match self.fonts.get(text.font.0)
+        {
+            Some(font) => font,
+            None => return Vec::new(),
+        };
+        let size = (text.size * 4.0).round() as u32;
+        layout.glyphs.iter()
+            .filter_map(|laid_out|
+            {
+                let cached = self.cache.get(font, GlyphKey { font: text.font, glyph: laid_out.glyph, size })?;
+                let rect = cached.rect;
+                // whole pixels keep the glyphs sharp on screen
+                let top_left = cgmath::Vector2::new(
+                    (position.x + laid_out.x + cached.offset.x).round(),
+                    (position.y + laid_out.y + cached.offset.y).round(),
+                );
+                let size = cgmath::Vector2::new(rect.width as f32, rect.height as f32);
+                Some(sprite::Sprite
+                {
+                    tint: laid_out.color,
+                    uv: rect.uv((CACHE_SIZE, CACHE_SIZE)),
+                    ..sprite::Sprite::new(self.cache.texture, top_left + size / 2.0, size)
+                })
+            })
+            .collect()
+    }
+
+    // copies newly rasterized glyphs to the gpu, once a frame before the sprites are drawn
+    pub fn upload(&mut self, queue: &wgpu::Queue, assets: &mut assets::AssetManager)
+    {
+        for (rect, image) in self.cache.pending.drain(..)
+        {
+            assets.write_texture(queue, self.cache.texture, rect.x, rect.y, &image);
+        }
+        if self.cache.full
+        {
+            log::warn!("the glyph cache is full, starting it over");
+            self.cache.clear();
+        }
+    }
+}
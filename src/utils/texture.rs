@@ -1,4 +1,3 @@
-use image::GenericImageView;
 use anyhow::*;
 
 pub struct Texture
@@ -10,24 +9,77 @@ pub struct Texture
 
 impl Texture
 {
-    pub fn from_bytes(
-        device: &wgpu::Device,
-        queue: &wgpu::Queue,
-        bytes: &[u8], 
-        label: &str
-    ) -> Result<Self> {
-        let img = image::load_from_memory(bytes)?;
-        Self::from_image(device, queue, &img, Some(label))
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+    // the depth buffer has to be the same size as the surface (and have as many samples as what's drawn with it), so this is recreated on resize
+    pub fn create_depth_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, sample_count: u32, label: &str) -> Self
+    {
+        let size = wgpu::Extent3d
+        {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(
+            &wgpu::TextureDescriptor
+            {
+                label: Some(label),
+                size,
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format: Self::DEPTH_FORMAT,
+                // RENDER_ATTACHMENT because the render pass writes depth into it
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            }
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor
+        {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            ..Default::default()
+        });
+
+        Self { texture, view, sampler }
     }
 
-    pub fn from_image(
+    // what multisampled passes draw into before it's resolved to the surface, the same size and format as it
+    pub fn create_multisampled_view(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, sample_count: u32, label: &str) -> wgpu::TextureView
+    {
+        let texture = device.create_texture(
+            &wgpu::TextureDescriptor
+            {
+                label: Some(label),
+                size: wgpu::Extent3d { width: config.width, height: config.height, depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format: config.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            }
+        );
+        // the view keeps the texture alive
+        texture.create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    // images are decoded and converted to rgba by the asset workers (see 'assets.rs'),
+    // so all that is left to do here is copy the pixels to the gpu
+    pub fn from_rgba(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        img: &image::DynamicImage,
+        rgba: &image::RgbaImage,
         label: Option<&str>
     ) -> Result<Self> {
-        let rgba = img.to_rgba8();
-        let dimensions = img.dimensions();
+        let dimensions = rgba.dimensions();
 
         // create the Texture
         let size = wgpu::Extent3d
@@ -40,7 +92,7 @@ impl Texture
             &wgpu::TextureDescriptor
             {
                 // All textures are stored as 3D, we represent our 2D texture by setting depth to 1.
-                size: size,
+                size,
                 mip_level_count: 1,
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
@@ -48,7 +100,7 @@ impl Texture
                 // TEXTURE_BINDING tells wgpu that we want to use this texture in shaders
                 // COPY_DST means that we want to copy data to this texture
                 usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-                label: Some("diffuse texture"),
+                label,
             }
         );
         // load the texture in
@@ -62,7 +114,7 @@ impl Texture
                 aspect: wgpu::TextureAspect::All,
             },
             // actual pixel data
-            &rgba,
+            rgba,
             // layout of texture
             wgpu::ImageDataLayout
             {
@@ -92,4 +144,27 @@ impl Texture
         
         Ok(Self { texture, view, sampler })
     }
-}
\ No newline at end of file
+
+    // replaces part of the texture with rgba, its top left corner at (x, y)
+    pub fn write_region(&self, queue: &wgpu::Queue, x: u32, y: u32, rgba: &image::RgbaImage)
+    {
+        let (width, height) = rgba.dimensions();
+        queue.write_texture(
+            wgpu::ImageCopyTexture
+            {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x, y, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            rgba,
+            wgpu::ImageDataLayout
+            {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(4 * width),
+                rows_per_image: std::num::NonZeroU32::new(height),
+            },
+            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        );
+    }
+}
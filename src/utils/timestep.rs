@@ -0,0 +1,152 @@
+use std::time::{ Duration, Instant };
+
+
+/*
+    Fixed timestep game loop.
+
+    Real time that passes between frames goes into an accumulator, and the
+    simulation runs one tick for every full tick's worth of time in it.
+    Whatever is left over (less than one tick) becomes the interpolation
+    alpha: how far the renderer should blend from the previous tick's
+    state towards the current one, so motion looks smooth even when the
+    display rate doesn't match the tick rate.
+
+    If the game falls behind (a long frame, a breakpoint, dragging the
+    window) it only catches up max_steps ticks at once and drops the rest
+    instead of spiraling into running more and more ticks per frame.
+*/
+
+// how often the simulation ticks, whatever the display's refresh rate is
+pub const TICK_RATE_HZ: f64 = 60.0;
+
+pub struct FixedTimestep
+{
+    tick: Duration,
+    accumulator: Duration,
+    max_steps: u32,
+    last: Option<Instant>,
+}
+
+impl FixedTimestep
+{
+    pub fn new(tick_rate_hz: f64, max_steps: u32) -> Self
+    {
+        Self
+        {
+            tick: Self::tick_for(tick_rate_hz),
+            accumulator: Duration::ZERO,
+            max_steps: max_steps.max(1),
+            last: None,
+        }
+    }
+
+    // how long one tick is at a tick rate, for things that tick without a FixedTimestep (like console scripts)
+    pub fn tick_for(tick_rate_hz: f64) -> Duration
+    {
+        Duration::from_secs_f64(1.0 / tick_rate_hz.max(1.0))
+    }
+
+    // how much simulated time one tick is
+    pub fn tick_duration(&self) -> Duration
+    {
+        self.tick
+    }
+
+    // adds the time since the last call and returns how many ticks to run now
+    pub fn advance(&mut self, now: Instant) -> u32
+    {
+        let elapsed = match self.last
+        {
+            Some(last) => now.saturating_duration_since(last),
+            // first frame, nothing has happened yet
+            None => Duration::ZERO,
+        };
+        self.last = Some(now);
+        self.accumulator += elapsed;
+
+        let mut steps = 0;
+        while self.accumulator >= self.tick && steps < self.max_steps
+        {
+            self.accumulator -= self.tick;
+            steps += 1;
+        }
+
+        // too far behind, let the missing time go
+        if self.accumulator >= self.tick
+        {
+            log::warn!("simulation fell behind, skipping {:.1} ms", (self.accumulator - self.tick).as_secs_f64() * 1000.0);
+            self.accumulator = self.tick.mul_f64(0.999);
+        }
+
+        steps
+    }
+
+    // 0.0 -> render the previous tick, 1.0 -> render the latest one
+    pub fn alpha(&self) -> f32
+    {
+        (self.accumulator.as_secs_f64() / self.tick.as_secs_f64()).clamp(0.0, 1.0) as f32
+    }
+
+    // call after a long pause (loading, the window being hidden) so it isn't counted as lag
+    pub fn reset(&mut self)
+    {
+        self.last = None;
+        self.accumulator = Duration::ZERO;
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const TICK: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn runs_a_tick_for_every_tick_of_time()
+    {
+        let mut timestep = FixedTimestep::new(100.0, 5);
+        let start = Instant::now();
+        // the first call only starts the clock
+        assert_eq!(timestep.advance(start), 0);
+        assert_eq!(timestep.advance(start + TICK * 3), 3);
+        assert_eq!(timestep.tick_duration(), TICK);
+    }
+
+    #[test]
+    fn leftover_time_is_the_alpha()
+    {
+        let mut timestep = FixedTimestep::new(100.0, 5);
+        let start = Instant::now();
+        timestep.advance(start);
+        assert_eq!(timestep.advance(start + TICK / 4), 0);
+        assert!((timestep.alpha() - 0.25).abs() < 1e-3);
+        assert_eq!(timestep.advance(start + TICK * 7 / 4), 1);
+        assert!((timestep.alpha() - 0.75).abs() < 1e-3);
+    }
+
+    #[test]
+    fn catching_up_is_capped()
+    {
+        let mut timestep = FixedTimestep::new(100.0, 5);
+        let start = Instant::now();
+        timestep.advance(start);
+        assert_eq!(timestep.advance(start + TICK * 50), 5);
+        // the rest was dropped, not saved up for the next frames
+        assert!(timestep.alpha() < 1.0);
+        assert_eq!(timestep.advance(start + TICK * 50), 0);
+    }
+
+    #[test]
+    fn reset_forgets_the_time_in_between()
+    {
+        let mut timestep = FixedTimestep::new(100.0, 5);
+        let start = Instant::now();
+        timestep.advance(start);
+        timestep.reset();
+        assert_eq!(timestep.advance(start + TICK * 20), 0);
+        assert_eq!(timestep.alpha(), 0.0);
+        assert_eq!(timestep.advance(start + TICK * 22), 2);
+    }
+}
@@ -0,0 +1,610 @@
+use std::collections::HashMap;
+use wgpu::util::DeviceExt;
+use winit::
+{
+    dpi::PhysicalSize,
+    event::{ ElementState, ModifiersState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent },
+};
+
+use super::texture;
+
+
+/*
+    The immediate mode ui (egui) for menus, settings and debug panels.
+    Game states build it while they render, through the context:
+
+        let ctx = engine.ui();
+        egui::Window::new("Settings").show(&ctx, |ui|
+        {
+            ui.add(egui::Slider::new(&mut volume, 0.0..=1.0).text("volume"));
+        });
+
+    Window events go to the ui before anything else (see 'game_state.rs'),
+    and the ones it uses don't reach the world, so clicking a button
+    doesn't also turn the camera. Releases always get through, or a key
+    held down when the pointer went over a window would stay held.
+
+    Everything is laid out in points, a point being scale factor pixels,
+    and it's drawn in its own pass over everything else.
+*/
+
+// how far one line of a mouse wheel scrolls, in points
+const SCROLL_LINE: f32 = 50.0;
+
+
+pub struct Ui
+{
+    context: egui::Context,
+    // what happened since the last frame
+    raw_input: egui::RawInput,
+    pixels_per_point: f32,
+    size: PhysicalSize<u32>,
+    pointer: Option<egui::Pos2>,
+    modifiers: egui::Modifiers,
+    start: std::time::Instant,
+    renderer: UiRenderer,
+}
+
+impl Ui
+{
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        size: PhysicalSize<u32>,
+        scale_factor: f64,
+    ) -> Self
+    {
+        Self
+        {
+            context: egui::Context::default(),
+            raw_input: egui::RawInput::default(),
+            pixels_per_point: scale_factor as f32,
+            size,
+            pointer: None,
+            modifiers: egui::Modifiers::default(),
+            start: std::time::Instant::now(),
+            renderer: UiRenderer::new(device, format),
+        }
+    }
+
+    // cheap to clone, it's shared inside
+    pub fn context(&self) -> &egui::Context
+    {
+        &self.context
+    }
+
+    pub fn resize(&mut self, size: PhysicalSize<u32>)
+    {
+        self.size = size;
+    }
+
+    // true -> the ui used the event, so nothing else should
+    pub fn input(&mut self, event: &WindowEvent) -> bool
+    {
+        match event
+        {
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } =>
+            {
+                self.pixels_per_point = *scale_factor as f32;
+                false
+            }
+            WindowEvent::CursorMoved { position, .. } =>
+            {
+                let pos = egui::pos2(position.x as f32 / self.pixels_per_point, position.y as f32 / self.pixels_per_point);
+                self.pointer = Some(pos);
+                self.raw_input.events.push(egui::Event::PointerMoved(pos));
+                // dragging a slider shouldn't look around too
+                self.context.is_using_pointer()
+            }
+            WindowEvent::CursorLeft { .. } =>
+            {
+                self.pointer = None;
+                self.raw_input.events.push(egui::Event::PointerGone);
+                false
+            }
+            WindowEvent::MouseInput { state, button, .. } =>
+            {
+                let (pos, button) = match (self.pointer, pointer_button(*button))
+                {
+                    (Some(pos), Some(button)) => (pos, button),
+                    _ => return false,
+                };
+                let pressed = *state == ElementState::Pressed;
+                self.raw_input.events.push(egui::Event::PointerButton { pos, button, pressed, modifiers: self.modifiers });
+                pressed && self.context.wants_pointer_input()
+            }
+            WindowEvent::MouseWheel { delta, .. } =>
+            {
+                let delta = match delta
+                {
+                    MouseScrollDelta::LineDelta(x, y) => egui::vec2(*x, *y) * SCROLL_LINE,
+                    MouseScrollDelta::PixelDelta(delta) => egui::vec2(delta.x as f32, delta.y as f32) / self.pixels_per_point,
+                };
+                self.raw_input.events.push(egui::Event::Scroll(delta));
+                self.context.wants_pointer_input()
+            }
+            WindowEvent::ModifiersChanged(state) =>
+            {
+                self.modifiers = modifiers(*state);
+                false
+            }
+            WindowEvent::ReceivedCharacter(c) =>
+            {
+                if c.is_control()
+                {
+                    return false;
+                }
+                self.raw_input.events.push(egui::Event::Text(c.to_string()));
+                self.context.wants_keyboard_input()
+            }
+            WindowEvent::KeyboardInput { input, .. } =>
+            {
+                let key = match input.virtual_keycode.and_then(key)
+                {
+                    Some(key) => key,
+                    None => return false,
+                };
+                let pressed = input.state == ElementState::Pressed;
+                self.raw_input.events.push(egui::Event::Key { key, pressed, modifiers: self.modifiers });
+                pressed && self.context.wants_keyboard_input()
+            }
+            _ => false,
+        }
+    }
+
+    // starts laying out this frame's ui, with everything that happened since the last one
+    pub fn begin_frame(&mut self)
+    {
+        let mut raw_input = std::mem::take(&mut self.raw_input);
+        raw_input.pixels_per_point = Some(self.pixels_per_point);
+        raw_input.screen_rect = Some(egui::Rect::from_min_size(
+            egui::Pos2::ZERO,
+            egui::vec2(self.size.width as f32, self.size.height as f32) / self.pixels_per_point,
+        ));
+        raw_input.time = Some(self.start.elapsed().as_secs_f64());
+        raw_input.modifiers = self.modifiers;
+        self.context.begin_frame(raw_input);
+    }
+
+    // finishes the frame's ui and uploads it, false -> there's nothing to draw
+    pub fn end_frame(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> bool
+    {
+        let output = self.context.end_frame();
+        let meshes = self.context.tessellate(output.shapes);
+        let screen = [self.size.width as f32 / self.pixels_per_point, self.size.height as f32 / self.pixels_per_point];
+        self.renderer.update_textures(device, queue, output.textures_delta);
+        self.renderer.prepare(device, queue, meshes, screen, self.pixels_per_point, self.size);
+        !self.renderer.draws.is_empty()
+    }
+
+    // draws what the last end_frame() uploaded over what's on screen
+    pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, load: wgpu::LoadOp<wgpu::Color>)
+    {
+        self.renderer.draw(encoder, view, load);
+    }
+}
+
+fn pointer_button(button: MouseButton) -> Option<egui::PointerButton>
+{
+    match button
+    {
+        MouseButton::Left => Some(egui::PointerButton::Primary),
+        MouseButton::Right => Some(egui::PointerButton::Secondary),
+        MouseButton::Middle => Some(egui::PointerButton::Middle),
+        MouseButton::Other(_) => None,
+    }
+}
+
+fn modifiers(state: ModifiersState) -> egui::Modifiers
+{
+    egui::Modifiers
+    {
+        alt: state.alt(),
+        ctrl: state.ctrl(),
+        shift: state.shift(),
+        mac_cmd: cfg!(target_os = "macos") && state.logo(),
+        command: if cfg!(target_os = "macos") { state.logo() } else { state.ctrl() },
+    }
+}
+
+// only the keys egui knows about, text comes in through ReceivedCharacter
+fn key(keycode: VirtualKeyCode) -> Option<egui::Key>
+{
+    use egui::Key;
+    Some(match keycode
+    {
+        VirtualKeyCode::Down => Key::ArrowDown,
+        VirtualKeyCode::Left => Key::ArrowLeft,
+        VirtualKeyCode::Right => Key::ArrowRight,
+        VirtualKeyCode::Up => Key::ArrowUp,
+        VirtualKeyCode::Escape => Key::Escape,
+        VirtualKeyCode::Tab => Key::Tab,
+        VirtualKeyCode::Back => Key::Backspace,
+        VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => Key::Enter,
+        VirtualKeyCode::Space => Key::Space,
+        VirtualKeyCode::Insert => Key::Insert,
+        VirtualKeyCode::Delete => Key::Delete,
+        VirtualKeyCode::Home => Key::Home,
+        VirtualKeyCode::End => Key::End,
+        VirtualKeyCode::PageUp => Key::PageUp,
+        VirtualKeyCode::PageDown => Key::PageDown,
+        VirtualKeyCode::Key0 | VirtualKeyCode::Numpad0 => Key::Num0,
+        VirtualKeyCode::Key1 | VirtualKeyCode::Numpad1 => Key::Num1,
+        VirtualKeyCode::Key2 | VirtualKeyCode::Numpad2 => Key::Num2,
+        VirtualKeyCode::Key3 | VirtualKeyCode::Numpad3 => Key::Num3,
+        VirtualKeyCode::Key4 | VirtualKeyCode::Numpad4 => Key::Num4,
+        VirtualKeyCode::Key5 | VirtualKeyCode::Numpad5 => Key::Num5,
+        VirtualKeyCode::Key6 | VirtualKeyCode::Numpad6 => Key::Num6,
+        VirtualKeyCode::Key7 | VirtualKeyCode::Numpad7 => Key::Num7,
+        VirtualKeyCode::Key8 | VirtualKeyCode::Numpad8 => Key::Num8,
+        VirtualKeyCode::Key9 | VirtualKeyCode::Numpad9 => Key::Num9,
+        VirtualKeyCode::A => Key::A,
+        VirtualKeyCode::B => Key::B,
+        VirtualKeyCode::C => Key::C,
+        VirtualKeyCode::D => Key::D,
+        VirtualKeyCode::E => Key::E,
+        VirtualKeyCode::F => Key::F,
+        VirtualKeyCode::G => Key::G,
+        VirtualKeyCode::H => Key::H,
+        VirtualKeyCode::I => Key::I,
+        VirtualKeyCode::J => Key::J,
+        VirtualKeyCode::K => Key::K,
+        VirtualKeyCode::L => Key::L,
+        VirtualKeyCode::M => Key::M,
+        VirtualKeyCode::N => Key::N,
+        VirtualKeyCode::O => Key::O,
+        VirtualKeyCode::P => Key::P,
+        VirtualKeyCode::Q => Key::Q,
+        VirtualKeyCode::R => Key::R,
+        VirtualKeyCode::S => Key::S,
+        VirtualKeyCode::T => Key::T,
+        VirtualKeyCode::U => Key::U,
+        VirtualKeyCode::V => Key::V,
+        VirtualKeyCode::W => Key::W,
+        VirtualKeyCode::X => Key::X,
+        VirtualKeyCode::Y => Key::Y,
+        VirtualKeyCode::Z => Key::Z,
+        _ => return None,
+    })
+}
+
+
+/*   <--------Renderer-------->   */
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ScreenUniform
+{
+    // in points, the last two are padding
+    size: [f32; 4],
+}
+
+// one of egui's meshes, out of the shared buffers
+struct UiDraw
+{
+    texture: egui::TextureId,
+    // in pixels
+    scissor: (u32, u32, u32, u32),
+    indices: std::ops::Range<u32>,
+    base_vertex: i32,
+}
+
+struct UiRenderer
+{
+    pipeline: wgpu::RenderPipeline,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    screen_buffer: wgpu::Buffer,
+    screen_bind_group: wgpu::BindGroup,
+    // egui's own textures, the font atlas and any images it's been given
+    textures: HashMap<egui::TextureId, (texture::Texture, wgpu::BindGroup)>,
+    // egui frees textures after painting, which is the next frame for us
+    to_free: Vec<egui::TextureId>,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    // in vertices and indices
+    capacity: (usize, usize),
+    draws: Vec<UiDraw>,
+}
+
+impl UiRenderer
+{
+    fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self
+    {
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor
+        {
+            label: Some("Ui Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/ui.wgsl").into()),
+        });
+
+        let screen_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor
+        {
+            label: Some("ui_screen_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry
+            {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer
+                {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let screen_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor
+        {
+            label: Some("Ui Screen Buffer"),
+            contents: bytemuck::cast_slice(&[ScreenUniform { size: [1.0, 1.0, 0.0, 0.0] }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let screen_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor
+        {
+            label: Some("ui_screen_bind_group"),
+            layout: &screen_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: screen_buffer.as_entire_binding() }],
+        });
+
+        // same as the asset manager's, but the ui keeps its textures to itself
+        let texture_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor
+        {
+            label: Some("ui_texture_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry
+                {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture
+                    {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry
+                {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor
+        {
+            label: Some("Ui Pipeline Layout"),
+            bind_group_layouts: &[&screen_bind_group_layout, &texture_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor
+        {
+            label: Some("Ui Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState
+            {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout
+                {
+                    // egui's own vertex, position and uv in f32s then a srgba color in bytes
+                    array_stride: std::mem::size_of::<egui::epaint::Vertex>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Unorm8x4],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState
+            {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState
+                {
+                    format,
+                    // egui's colors have their alpha multiplied in already
+                    blend: Some(wgpu::BlendState
+                    {
+                        color: wgpu::BlendComponent
+                        {
+                            src_factor: wgpu::BlendFactor::One,
+                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: wgpu::BlendComponent
+                        {
+                            src_factor: wgpu::BlendFactor::OneMinusDstAlpha,
+                            dst_factor: wgpu::BlendFactor::One,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState
+            {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                // egui doesn't keep to a winding
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let capacity = (1024, 3072);
+        let (vertex_buffer, index_buffer) = Self::create_buffers(device, capacity);
+        Self
+        {
+            pipeline,
+            texture_bind_group_layout,
+            screen_buffer,
+            screen_bind_group,
+            textures: HashMap::new(),
+            to_free: Vec::new(),
+            vertex_buffer,
+            index_buffer,
+            capacity,
+            draws: Vec::new(),
+        }
+    }
+    fn create_buffers(device: &wgpu::Device, (vertices, indices): (usize, usize)) -> (wgpu::Buffer, wgpu::Buffer)
+    {
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor
+        {
+            label: Some("Ui Vertex Buffer"),
+            size: (vertices * std::mem::size_of::<egui::epaint::Vertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let index_buffer = device.create_buffer(&wgpu::BufferDescriptor
+        {
+            label: Some("Ui Index Buffer"),
+            size: (indices * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        (vertex_buffer, index_buffer)
+    }
+
+    // new textures and changed parts of old ones (mostly the font atlas growing)
+    fn update_textures(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, delta: egui::TexturesDelta)
+    {
+        for id in self.to_free.drain(..)
+        {
+            self.textures.remove(&id);
+        }
+        self.to_free = delta.free;
+
+        for (id, image_delta) in delta.set
+        {
+            let [width, height] = image_delta.image.size();
+            let pixels: Vec<u8> = match &image_delta.image
+            {
+                egui::ImageData::Color(image) => image.pixels.iter().flat_map(|color| color.to_array()).collect(),
+                egui::ImageData::Alpha(image) => image.srgba_pixels(1.0).flat_map(|color| color.to_array()).collect(),
+            };
+            let rgba = match image::RgbaImage::from_raw(width as u32, height as u32, pixels)
+            {
+                Some(rgba) => rgba,
+                None => continue,
+            };
+
+            if let Some([x, y]) = image_delta.pos
+            {
+                match self.textures.get(&id)
+                {
+                    Some((texture, _)) => texture.write_region(queue, x as u32, y as u32, &rgba),
+                    None => log::warn!("ui texture {:?} changed before it was made", id),
+                }
+                continue;
+            }
+
+            let texture = match texture::Texture::from_rgba(device, queue, &rgba, Some("ui texture"))
+            {
+                Ok(texture) => texture,
+                Err(e) =>
+                {
+                    log::error!("couldn't make ui texture {:?}: {:#}", id, e);
+                    continue;
+                }
+            };
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor
+            {
+                label: Some("ui_texture_bind_group"),
+                layout: &self.texture_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&texture.view) },
+                    wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&texture.sampler) },
+                ],
+            });
+            self.textures.insert(id, (texture, bind_group));
+        }
+    }
+
+    // uploads every mesh, screen is in points and size in pixels
+    fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        meshes: Vec<egui::ClippedMesh>,
+        screen: [f32; 2],
+        pixels_per_point: f32,
+        size: PhysicalSize<u32>,
+    )
+    {
+        queue.write_buffer(&self.screen_buffer, 0, bytemuck::cast_slice(&[ScreenUniform { size: [screen[0], screen[1], 0.0, 0.0] }]));
+
+        let mut vertices: Vec<egui::epaint::Vertex> = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+        self.draws.clear();
+        for egui::ClippedMesh(clip, mesh) in meshes
+        {
+            // the clip rect in pixels, kept on screen
+            let min_x = ((clip.min.x * pixels_per_point).round().max(0.0) as u32).min(size.width);
+            let min_y = ((clip.min.y * pixels_per_point).round().max(0.0) as u32).min(size.height);
+            let max_x = ((clip.max.x * pixels_per_point).round().max(0.0) as u32).min(size.width);
+            let max_y = ((clip.max.y * pixels_per_point).round().max(0.0) as u32).min(size.height);
+            if mesh.indices.is_empty() || max_x <= min_x || max_y <= min_y
+            {
+                continue;
+            }
+
+            let start = indices.len() as u32;
+            self.draws.push(UiDraw
+            {
+                texture: mesh.texture_id,
+                scissor: (min_x, min_y, max_x - min_x, max_y - min_y),
+                indices: start..start + mesh.indices.len() as u32,
+                base_vertex: vertices.len() as i32,
+            });
+            vertices.extend(mesh.vertices);
+            indices.extend(mesh.indices);
+        }
+
+        // the buffers grow with the ui, same as the sprite buffers
+        if vertices.len() > self.capacity.0 || indices.len() > self.capacity.1
+        {
+            self.capacity = (vertices.len().max(self.capacity.0).next_power_of_two(), indices.len().max(self.capacity.1).next_power_of_two());
+            (self.vertex_buffer, self.index_buffer) = Self::create_buffers(device, self.capacity);
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+        queue.write_buffer(&self.index_buffer, 0, bytemuck::cast_slice(&indices));
+    }
+
+    fn draw(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, load: wgpu::LoadOp<wgpu::Color>)
+    {
+        let mut ui_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor
+        {
+            label: Some("Ui Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment
+            {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations { load, store: true },
+            }],
+            depth_stencil_attachment: None,
+        });
+        ui_pass.set_pipeline(&self.pipeline);
+        ui_pass.set_bind_group(0, &self.screen_bind_group, &[]);
+        ui_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        ui_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        for draw in &self.draws
+        {
+            // only egui's own textures are drawn
+            let bind_group = match self.textures.get(&draw.texture)
+            {
+                Some((_, bind_group)) => bind_group,
+                None => continue,
+            };
+            let (x, y, width, height) = draw.scissor;
+            ui_pass.set_scissor_rect(x, y, width, height);
+            ui_pass.set_bind_group(1, bind_group, &[]);
+            ui_pass.draw_indexed(draw.indices.clone(), draw.base_vertex, 0..1);
+        }
+    }
+}
@@ -5,6 +5,7 @@ pub struct Vertex
 {
     pub position: [f32; 3],
     pub tex_coords: [f32; 2],
+    pub normal: [f32; 3],
 }
 
 unsafe impl bytemuck::Pod for Vertex {}
@@ -30,10 +31,15 @@ impl Vertex
                     offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
                     shader_location: 1,
                     format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute
+                {
+                    offset: std::mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x3,
                 }
             ]
         }
         // here the VertexBufferLayout is returned automatically
     }
 }
- 
\ No newline at end of file